@@ -19,8 +19,7 @@ impl<'a, IoPorts: PortAccessor> Beeper<'a, IoPorts> {
     }
 }
 
-impl<'a, IoPorts: PortAccessor> SoundEmitter for Beeper<'a, IoPorts> {
-    #[must_use]
+impl<IoPorts: PortAccessor> SoundEmitter for Beeper<'_, IoPorts> {
     fn prepare(&mut self) -> bool {
         // Set beeper regime:
         if !self.port_accessor.write_byte(0x43, 0xB6) {
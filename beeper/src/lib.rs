@@ -2,5 +2,10 @@
 
 pub mod sound_emitter;
 pub mod generic;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 pub mod iopl_based;
-pub mod port_accessor;
\ No newline at end of file
+pub mod port_accessor;
+pub mod virtual_speaker;
+
+#[cfg(target_os = "linux")]
+pub mod linux_ports;
\ No newline at end of file
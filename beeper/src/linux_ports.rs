@@ -0,0 +1,190 @@
+//!
+//! Port accessor for Linux.
+//!
+//! Direct access (`in`/`out` from usermode) is granted either by the I/O permission
+//! bitmap (`ioperm`) for the speaker ports only or by raising IOPL (`iopl`) for all ports.
+//! Both require `CAP_SYS_RAWIO`.
+//! If neither is available, ports are accessed via `/dev/port`, where the file offset is the port number.
+//! There are no `in`/`out` instructions beyond x86, so only `/dev/port` works there.
+//!
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use std::arch::asm;
+use std::{
+    fs::File,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf}
+};
+
+use iopl::level::Level;
+
+use crate::port_accessor::PortAccessor;
+
+#[derive(Debug, Clone, Default)]
+pub enum Access {
+    #[default]
+    Auto, // Ioperm, then Iopl, then the default /dev/port
+    Ioperm,
+    Iopl,
+    DevPort(PathBuf)
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Raise(iopl::linux::error::Error),
+    OpenDevPort(PathBuf, std::io::Error)
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Raise(err) => write!(f, "{err}"),
+            Error::OpenDevPort(path, err) => write!(f, "Unable to open {}: {err}", path.display())
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Raise(err) => Some(err),
+            Error::OpenDevPort(_, err) => Some(err)
+        }
+    }
+}
+
+pub struct LinuxPorts {
+    dev_port: Option<File> // Direct I/O if None
+}
+
+impl LinuxPorts {
+    pub const DEV_PORT_PATH: &'static str = "/dev/port";
+
+    // PIT channel 2 data and control ports, and the speaker control port:
+    const PIT_PORTS: (u16, u16) = (0x42, 2);
+    const SPEAKER_PORTS: (u16, u16) = (0x61, 1);
+
+    /// # Errors
+    ///
+    /// Returns `Error` if the requested access method is not available.
+    /// For `Access::Auto` it is the error of the last fallback (`/dev/port`).
+    pub fn new(access: &Access) -> Result<Self, Error> {
+        match access {
+            Access::Auto => Self::with_ioperm()
+                .or_else(|_| Self::with_iopl())
+                .or_else(|_| Self::with_dev_port(Self::DEV_PORT_PATH)),
+            Access::Ioperm => Self::with_ioperm(),
+            Access::Iopl => Self::with_iopl(),
+            Access::DevPort(path) => Self::with_dev_port(path)
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Returns `Error::Raise` if the permission bitmap can't be changed.
+    pub fn with_ioperm() -> Result<Self, Error> {
+        iopl::linux::grant_ports(Self::PIT_PORTS.0, Self::PIT_PORTS.1).map_err(Error::Raise)?;
+        iopl::linux::grant_ports(Self::SPEAKER_PORTS.0, Self::SPEAKER_PORTS.1).map_err(Error::Raise)?;
+        Ok(Self { dev_port: None })
+    }
+
+    /// # Errors
+    ///
+    /// Returns `Error::Raise` if IOPL can't be raised.
+    pub fn with_iopl() -> Result<Self, Error> {
+        iopl::linux::raise(Level::Ring3).map_err(Error::Raise)?;
+        Ok(Self { dev_port: None })
+    }
+
+    /// # Errors
+    ///
+    /// Returns `Error::OpenDevPort` if the file can't be opened for reading and writing.
+    pub fn with_dev_port(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|err| Error::OpenDevPort(path.to_path_buf(), err))?;
+
+        Ok(Self { dev_port: Some(file) })
+    }
+
+    #[must_use]
+    pub fn is_direct(&self) -> bool {
+        self.dev_port.is_none()
+    }
+}
+
+impl PortAccessor for LinuxPorts {
+    fn read_byte(&self, port_number: u16) -> Option<u8> {
+        if let Some(dev_port) = &self.dev_port {
+            let mut value = [0_u8; 1];
+            dev_port.read_exact_at(&mut value, u64::from(port_number)).ok()?;
+            return Some(value[0]);
+        }
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            let value: u8;
+            unsafe {
+                asm!(
+                    "in al, dx",
+                    out("al") value,
+                    in("dx") port_number,
+                    options(nomem, nostack, preserves_flags)
+                );
+            }
+            Some(value)
+        }
+
+        // The direct access is never granted there:
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+        None
+    }
+
+    fn write_byte(&self, port_number: u16, value: u8) -> bool {
+        if let Some(dev_port) = &self.dev_port {
+            return dev_port.write_all_at(&[value], u64::from(port_number)).is_ok();
+        }
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            unsafe {
+                asm!(
+                    "out dx, al",
+                    in("al") value,
+                    in("dx") port_number,
+                    options(nomem, nostack, preserves_flags)
+                );
+            }
+            true
+        }
+
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+        false
+    }
+}
+
+
+
+#[test]
+fn test() {
+    let path = std::env::temp_dir().join(format!("beeper_dev_port_{}", std::process::id()));
+    std::fs::write(&path, [0_u8; 0x100]).unwrap();
+
+    let ports = LinuxPorts::new(&Access::DevPort(path.clone())).unwrap();
+    assert!(!ports.is_direct());
+
+    assert!(ports.write_byte(0x43, 0xB6));
+    assert!(ports.write_byte(0x61, 0b11));
+    assert_eq!(ports.read_byte(0x43), Some(0xB6));
+    assert_eq!(ports.read_byte(0x61), Some(0b11));
+
+    let raw = std::fs::read(&path).unwrap();
+    assert_eq!(raw[0x43], 0xB6);
+    assert_eq!(raw[0x61], 0b11);
+    assert_eq!(raw[0x42], 0);
+
+    std::fs::remove_file(&path).unwrap();
+}
\ No newline at end of file
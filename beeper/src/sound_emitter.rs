@@ -1,26 +1,26 @@
-///
-/// Base frequency of the system clock generator is 1'193'182 Hz.
-/// We can set a divisor of the clock in the range of [1..65535] as it has 16-bit width.
-///
-/// So we have the equation:
-///   Frequency(Hz) = Base(Hz) / Divisor
-///   or
-///   Divisor = Base(Hz) / Frequency(Hz)
-///
-/// Where:
-///   Base(Hz) = 1'193'182 Hz
-///   Divisor ∈ [1..65535], zero value is inapplicable as we can't divide by zero
-///   
-/// As follows:
-///   Fmin = 1'193'182 / 65535 ≈ 18.206 Hz
-///   Fmax = 1'193'182 / 1 = 1'193'182 Hz
-///
+//!
+//! Base frequency of the system clock generator is 1'193'182 Hz.
+//! We can set a divisor of the clock in the range of [1..65535] as it has 16-bit width.
+//!
+//! So we have the equation:
+//!   Frequency(Hz) = Base(Hz) / Divisor
+//!   or
+//!   Divisor = Base(Hz) / Frequency(Hz)
+//!
+//! Where:
+//!   Base(Hz) = 1'193'182 Hz
+//!   Divisor ∈ [1..65535], zero value is inapplicable as we can't divide by zero
+//!   
+//! As follows:
+//!   Fmin = 1'193'182 / 65535 ≈ 18.206 Hz
+//!   Fmax = 1'193'182 / 1 = 1'193'182 Hz
+//!
 
 
 pub struct ClockGenerator;
 
 impl ClockGenerator {
-    const BASE_FREQ: u32 = 1_193_182; // In Hz
+    pub const BASE_FREQ: u32 = 1_193_182; // In Hz
 }
 
 type Hertz = u32;
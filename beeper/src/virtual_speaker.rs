@@ -0,0 +1,262 @@
+//!
+//! Offline speaker that records everything sent to it on a virtual clock
+//! and renders the membrane movement into a PCM WAV file.
+//!
+//! The model:
+//!   * PIT channel 2 runs in mode 3 (square wave): the output is high for the first
+//!     half of each `divisor` clock periods and low for the second one.
+//!     The counter restarts when the gate raises or a new divisor is written.
+//!   * Bit 0 of the port 0x61 gates the PIT channel 2, its output stays high while the gate is low.
+//!   * Bit 1 of the port 0x61 enables the speaker data.
+//!   * Membrane is raised when both the data bit and the PIT output are high.
+//!
+//! Real speakers are AC-coupled, so the rendered signal passes through a DC blocker.
+//!
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path
+};
+
+use nano_sleep::VirtualSleep;
+
+use crate::sound_emitter::{BeeperDivisor, BeeperFrequency, ClockGenerator, SoundEmitter};
+
+type Nsec = u64;
+
+#[derive(Clone, Copy)]
+struct State {
+    timestamp: Nsec,
+    control_port_value: u8,
+    divisor: u16,
+    counter_start: Nsec
+}
+
+impl State {
+    const GATE_BIT: u8 = 0b01;
+    const DATA_BIT: u8 = 0b10;
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn pit_output(&self, time: Nsec) -> bool {
+        if self.control_port_value & Self::GATE_BIT == 0 {
+            return true;
+        }
+
+        let divisor = u64::from(self.divisor.max(1));
+        let elapsed_clocks = (u128::from(time - self.counter_start) * u128::from(ClockGenerator::BASE_FREQ) / 1_000_000_000) as u64;
+        (elapsed_clocks % divisor) < divisor.div_ceil(2)
+    }
+
+    fn is_raised(&self, time: Nsec) -> bool {
+        (self.control_port_value & Self::DATA_BIT != 0) && self.pit_output(time)
+    }
+}
+
+pub struct VirtualSpeaker<'a> {
+    clock: &'a VirtualSleep,
+    states: Vec<State>
+}
+
+impl<'a> VirtualSpeaker<'a> {
+    const OVERSAMPLING: u32 = 16;
+    const DC_BLOCKER_POLE: f32 = 0.995;
+    const VOLUME: f32 = 0.8;
+
+    #[must_use]
+    pub fn new(clock: &'a VirtualSleep) -> Self {
+        Self {
+            clock,
+            states: vec![State { timestamp: clock.elapsed(), control_port_value: 0, divisor: u16::MAX, counter_start: clock.elapsed() }]
+        }
+    }
+
+    fn current(&self) -> State {
+        // There is always the initial state:
+        self.states[self.states.len() - 1]
+    }
+
+    fn push(&mut self, mut state: State) {
+        state.timestamp = self.clock.elapsed();
+        if let Some(last) = self.states.last_mut() {
+            if last.timestamp == state.timestamp {
+                *last = state;
+                return;
+            }
+        }
+        self.states.push(state);
+    }
+
+    fn set_control(&mut self, control_port_value: u8) {
+        let mut state = self.current();
+        let gate_raised = (state.control_port_value & State::GATE_BIT == 0) && (control_port_value & State::GATE_BIT != 0);
+        if gate_raised {
+            state.counter_start = self.clock.elapsed();
+        }
+        state.control_port_value = control_port_value;
+        self.push(state);
+    }
+
+    /// Renders everything from the creation up to the current virtual time
+    /// into mono samples with the given sample rate.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn render(&self, sample_rate: u32) -> Vec<i16> {
+        let begin = self.states[0].timestamp;
+        let end = self.clock.elapsed();
+        let sample_count = (u128::from(end - begin) * u128::from(sample_rate) / 1_000_000_000) as usize;
+        let subsample_duration = 1_000_000_000_f64 / f64::from(sample_rate * Self::OVERSAMPLING);
+
+        let mut samples = Vec::with_capacity(sample_count);
+        let mut state_index = 0;
+        let mut prev_level = 0_f32;
+        let mut prev_filtered = 0_f32;
+        for sample_number in 0..sample_count {
+            let mut raised_count = 0_u32;
+            for subsample_number in 0..Self::OVERSAMPLING {
+                let subsample = (sample_number as u64) * u64::from(Self::OVERSAMPLING) + u64::from(subsample_number);
+                let time = begin + ((subsample as f64) * subsample_duration) as Nsec;
+                while (state_index + 1 < self.states.len()) && (self.states[state_index + 1].timestamp <= time) {
+                    state_index += 1;
+                }
+
+                if self.states[state_index].is_raised(time) {
+                    raised_count += 1;
+                }
+            }
+
+            let level = (raised_count as f32) / (Self::OVERSAMPLING as f32);
+            let filtered = level - prev_level + Self::DC_BLOCKER_POLE * prev_filtered;
+            prev_level = level;
+            prev_filtered = filtered;
+
+            samples.push(((filtered * Self::VOLUME).clamp(-1_f32, 1_f32) * f32::from(i16::MAX)) as i16);
+        }
+
+        samples
+    }
+
+    /// Renders the speaker output as a 16-bit mono PCM WAV.
+    ///
+    /// # Errors
+    ///
+    /// Returns `io::Error` if the writer fails or the rendered data exceeds 4 GB.
+    pub fn write_wav(&self, writer: &mut impl Write, sample_rate: u32) -> io::Result<()> {
+        const HEADER_SIZE: u32 = 36;
+
+        let samples = self.render(sample_rate);
+        let data_size = u32::try_from(samples.len() * 2)
+            .ok()
+            .filter(|size| size.checked_add(HEADER_SIZE).is_some())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Rendered data is too large for WAV"))?;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(HEADER_SIZE + data_size).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16_u32.to_le_bytes())?;          // Subchunk size
+        writer.write_all(&1_u16.to_le_bytes())?;           // PCM
+        writer.write_all(&1_u16.to_le_bytes())?;           // Mono
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * 2).to_le_bytes())?; // Byte rate
+        writer.write_all(&2_u16.to_le_bytes())?;           // Block align
+        writer.write_all(&16_u16.to_le_bytes())?;          // Bits per sample
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        for sample in samples {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+
+        writer.flush()
+    }
+
+    /// # Errors
+    ///
+    /// Returns `io::Error` if the file can't be created or written.
+    pub fn save_wav(&self, path: impl AsRef<Path>, sample_rate: u32) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_wav(&mut writer, sample_rate)
+    }
+}
+
+impl SoundEmitter for VirtualSpeaker<'_> {
+    fn prepare(&mut self) -> bool {
+        true // Mode 3 is the only mode we model
+    }
+
+    fn play(&mut self) {
+        let control_port_value = self.current().control_port_value | State::GATE_BIT | State::DATA_BIT;
+        self.set_control(control_port_value);
+    }
+
+    fn mute(&mut self) {
+        let control_port_value = self.current().control_port_value & !(State::GATE_BIT | State::DATA_BIT);
+        self.set_control(control_port_value);
+    }
+
+    fn set_divisor(&mut self, divisor: BeeperDivisor) {
+        let mut state = self.current();
+        state.divisor = divisor.get();
+        state.counter_start = self.clock.elapsed();
+        self.push(state);
+    }
+
+    fn set_frequency(&mut self, freq: BeeperFrequency) {
+        self.set_divisor(freq.into());
+    }
+
+    fn up(&mut self) {
+        let control_port_value = self.current().control_port_value | State::DATA_BIT;
+        self.set_control(control_port_value);
+    }
+
+    fn down(&mut self) {
+        let control_port_value = self.current().control_port_value & !State::DATA_BIT;
+        self.set_control(control_port_value);
+    }
+}
+
+
+
+#[test]
+fn test() {
+    use nano_sleep::NanoWaiter;
+
+    const SAMPLE_RATE: u32 = 48_000;
+    const NSEC_IN_MSEC: u64 = 1_000_000;
+
+    let clock = VirtualSleep::new(1000);
+    let mut speaker = VirtualSpeaker::new(&clock);
+
+    // 1 kHz tone for 100 ms, then 100 ms of silence:
+    assert!(speaker.prepare());
+    speaker.set_frequency(BeeperFrequency::new_clamped(1000));
+    speaker.play();
+    clock.nano_sleep(100 * NSEC_IN_MSEC);
+    speaker.mute();
+    clock.nano_sleep(100 * NSEC_IN_MSEC);
+
+    let samples = speaker.render(SAMPLE_RATE);
+    assert_eq!(samples.len(), (SAMPLE_RATE / 5) as usize);
+
+    let (tone, silence) = samples.split_at(samples.len() / 2);
+    let rising_edges = tone.windows(2).filter(|pair| pair[0] < 0 && pair[1] >= 0).count();
+    assert!((99..=101).contains(&rising_edges), "{rising_edges} periods instead of 100");
+    assert!(silence[silence.len() / 2..].iter().all(|sample| sample.unsigned_abs() < 100));
+
+    // Membrane driven by the data bit directly:
+    let clock = VirtualSleep::new(1000);
+    let mut speaker = VirtualSpeaker::new(&clock);
+    for _ in 0..10 {
+        speaker.up();
+        clock.nano_sleep(NSEC_IN_MSEC);
+        speaker.down();
+        clock.nano_sleep(NSEC_IN_MSEC);
+    }
+
+    let mut wav = Vec::new();
+    speaker.write_wav(&mut wav, SAMPLE_RATE).unwrap();
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(wav.len(), 44 + (SAMPLE_RATE as usize / 50) * 2);
+}
\ No newline at end of file
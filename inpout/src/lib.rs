@@ -1,3 +1,4 @@
+#![cfg(target_os = "windows")]
 #![warn(clippy::pedantic)]
 
 pub mod interface;
@@ -5,3 +5,6 @@ pub mod level;
 
 #[cfg(target_os = "windows")]
 pub mod windows;
+
+#[cfg(target_os = "linux")]
+pub mod linux;
\ No newline at end of file
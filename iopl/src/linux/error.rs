@@ -0,0 +1,23 @@
+#[derive(Debug)]
+pub enum Error {
+    Iopl(std::io::Error),
+    Ioperm(std::io::Error)
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Iopl(err) => write!(f, "Failed to raise IOPL: {err}"),
+            Error::Ioperm(err) => write!(f, "Failed to set I/O port permissions: {err}")
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Iopl(err)
+            | Error::Ioperm(err) => Some(err)
+        }
+    }
+}
\ No newline at end of file
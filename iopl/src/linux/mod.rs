@@ -0,0 +1,77 @@
+pub mod error;
+
+use crate::level::Level;
+
+use error::Error as IoplError;
+
+/// # Errors
+///
+/// Returns `IoplError::Iopl` if the process lacks `CAP_SYS_RAWIO`
+/// or the kernel doesn't support `iopl` (e.g. non-x86 builds).
+pub fn raise(level: Level) -> Result<(), IoplError> {
+    let level: libc::c_int = match level {
+        Level::Ring0 => 0,
+        Level::Ring1 => 1,
+        Level::Ring2 => 2,
+        Level::Ring3 => 3
+    };
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let status = unsafe { libc::iopl(level) };
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    let status = unsupported(level);
+
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(IoplError::Iopl(std::io::Error::last_os_error()))
+    }
+}
+
+///
+/// Grants access to the ports `[first_port..first_port + count)`
+/// using the I/O permission bitmap of the current thread.
+/// Only ports below 0x400 can be granted this way.
+///
+/// # Errors
+///
+/// Returns `IoplError::Ioperm` if the process lacks `CAP_SYS_RAWIO`
+/// or the range is out of bounds.
+pub fn grant_ports(first_port: u16, count: u16) -> Result<(), IoplError> {
+    set_port_permission(first_port, count, true)
+}
+
+/// # Errors
+///
+/// Returns `IoplError::Ioperm` if the permission bitmap can't be changed.
+pub fn revoke_ports(first_port: u16, count: u16) -> Result<(), IoplError> {
+    set_port_permission(first_port, count, false)
+}
+
+fn set_port_permission(first_port: u16, count: u16, turn_on: bool) -> Result<(), IoplError> {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let status = unsafe {
+        libc::ioperm(
+            libc::c_ulong::from(first_port),
+            libc::c_ulong::from(count),
+            libc::c_int::from(turn_on)
+        )
+    };
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    let status = unsupported((first_port, count, turn_on));
+
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(IoplError::Ioperm(std::io::Error::last_os_error()))
+    }
+}
+
+///
+/// The I/O ports exist only on x86, elsewhere the calls fail as the kernel would do.
+///
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn unsupported<Arguments>(_arguments: Arguments) -> libc::c_int {
+    unsafe { *libc::__errno_location() = libc::ENOSYS };
+    -1
+}
@@ -2,12 +2,14 @@
 
 use std::{
     arch::x86_64,
+    cell::Cell,
     time::{Instant, Duration}
 };
 
 pub trait NanoWaiter {
     fn nano_sleep(&self, nanoseconds: u64);
     fn ticks_in_nanosecond(&self) -> f32;
+    fn ticks(&self) -> u64; // Current timestamp in ticks
 }
 
 
@@ -80,16 +82,16 @@ impl NanoSleep {
                     deltas.sort_unstable();
 
                     let calc_median = |sorted_array: &[u64]| -> u64 {
-                        if sorted_array.len() % 2 == 0 {
+                        if sorted_array.len().is_multiple_of(2) {
                             let high_mid_index = sorted_array.len() / 2;
-                            (sorted_array[high_mid_index - 1] + sorted_array[high_mid_index]) / 2
+                            u64::midpoint(sorted_array[high_mid_index - 1], sorted_array[high_mid_index])
                         } else {
                             sorted_array[sorted_array.len() / 2]
                         }
                     };
 
                     let left_half = &deltas[0..(deltas.len() / 2)];
-                    let right_half = &deltas[(if deltas.len() % 2 == 0 { deltas.len() / 2 } else { deltas.len() / 2 + 1 })..];
+                    let right_half = &deltas[(if deltas.len().is_multiple_of(2) { deltas.len() / 2 } else { deltas.len() / 2 + 1 })..];
                     
                     let quartile_1 = calc_median(left_half);
                     let quartile_3 = calc_median(right_half);
@@ -164,11 +166,58 @@ impl NanoWaiter for NanoSleep {
     }
 
     #[inline]
-    #[must_use]
     #[allow(clippy::cast_precision_loss)]
     fn ticks_in_nanosecond(&self) -> f32 {
         (self.tsc_ticks_in_one_microsecond as f32) / 1000_f32
     }
+
+    #[inline]
+    fn ticks(&self) -> u64 {
+        unsafe { x86_64::_rdtsc() }
+    }
+}
+
+
+
+///
+/// Virtual clock that doesn't wait at all: sleeping just moves the clock forward,
+/// so everything driven by it runs as fast as possible.
+/// One tick is one nanosecond.
+///
+/// Busy loops that poll `ticks()` instead of sleeping would never end
+/// with a frozen clock, so each poll costs `poll_interval` nanoseconds.
+///
+pub struct VirtualSleep {
+    elapsed: Cell<u64>,
+    poll_interval: u64
+}
+
+impl VirtualSleep {
+    #[must_use]
+    pub fn new(poll_interval_nsec: u64) -> Self {
+        Self { elapsed: Cell::new(0), poll_interval: poll_interval_nsec.max(1) }
+    }
+
+    /// Virtual time passed since the creation, in nanoseconds.
+    #[must_use]
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed.get()
+    }
+}
+
+impl NanoWaiter for VirtualSleep {
+    fn nano_sleep(&self, nanoseconds: u64) {
+        self.elapsed.set(self.elapsed.get() + nanoseconds);
+    }
+
+    fn ticks_in_nanosecond(&self) -> f32 {
+        1_f32
+    }
+
+    fn ticks(&self) -> u64 {
+        self.elapsed.set(self.elapsed.get() + self.poll_interval);
+        self.elapsed.get()
+    }
 }
 
 
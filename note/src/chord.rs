@@ -0,0 +1,233 @@
+use std::str::FromStr;
+
+use super::{Note, Interval, PitchClass, OctaveNumber, Shift, LETTERS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Suspended2,
+    Suspended4,
+    Power,           // The root and the fifth
+    Major6,
+    Minor6,
+    Dominant7,
+    Major7,
+    Minor7,
+    MinorMajor7,
+    HalfDiminished7, // Minor seventh with the flat fifth
+    Diminished7,
+    Augmented7,
+    Dominant9,
+    Major9,
+    Minor9,
+    Add9
+}
+
+// Symbols of the qualities after the root, the first one is printed:
+const SYMBOLS: [(ChordQuality, &[&str]); 20] = [
+    (ChordQuality::Major, &["", "maj", "M"]),
+    (ChordQuality::Minor, &["m", "min", "-"]),
+    (ChordQuality::Diminished, &["dim", "°", "o"]),
+    (ChordQuality::Augmented, &["aug", "+"]),
+    (ChordQuality::Suspended2, &["sus2"]),
+    (ChordQuality::Suspended4, &["sus4", "sus"]),
+    (ChordQuality::Power, &["5"]),
+    (ChordQuality::Major6, &["6"]),
+    (ChordQuality::Minor6, &["m6", "min6"]),
+    (ChordQuality::Dominant7, &["7"]),
+    (ChordQuality::Major7, &["maj7", "M7", "Δ", "Δ7"]),
+    (ChordQuality::Minor7, &["m7", "min7", "-7"]),
+    (ChordQuality::MinorMajor7, &["mM7", "m(maj7)", "minmaj7"]),
+    (ChordQuality::HalfDiminished7, &["m7b5", "ø", "ø7"]),
+    (ChordQuality::Diminished7, &["dim7", "°7", "o7"]),
+    (ChordQuality::Augmented7, &["aug7", "+7", "7#5"]),
+    (ChordQuality::Dominant9, &["9"]),
+    (ChordQuality::Major9, &["maj9", "M9"]),
+    (ChordQuality::Minor9, &["m9", "min9"]),
+    (ChordQuality::Add9, &["add9"])
+];
+
+impl ChordQuality {
+    ///
+    /// Degrees of the chord tones (3 is the third) and their semitones above the root,
+    /// the degree gives the letter of the tone, so Cm has Eb instead of D#.
+    ///
+    const fn tones(self) -> &'static [(u8, i16)] {
+        match self {
+            ChordQuality::Major => &[(1, 0), (3, 4), (5, 7)],
+            ChordQuality::Minor => &[(1, 0), (3, 3), (5, 7)],
+            ChordQuality::Diminished => &[(1, 0), (3, 3), (5, 6)],
+            ChordQuality::Augmented => &[(1, 0), (3, 4), (5, 8)],
+            ChordQuality::Suspended2 => &[(1, 0), (2, 2), (5, 7)],
+            ChordQuality::Suspended4 => &[(1, 0), (4, 5), (5, 7)],
+            ChordQuality::Power => &[(1, 0), (5, 7)],
+            ChordQuality::Major6 => &[(1, 0), (3, 4), (5, 7), (6, 9)],
+            ChordQuality::Minor6 => &[(1, 0), (3, 3), (5, 7), (6, 9)],
+            ChordQuality::Dominant7 => &[(1, 0), (3, 4), (5, 7), (7, 10)],
+            ChordQuality::Major7 => &[(1, 0), (3, 4), (5, 7), (7, 11)],
+            ChordQuality::Minor7 => &[(1, 0), (3, 3), (5, 7), (7, 10)],
+            ChordQuality::MinorMajor7 => &[(1, 0), (3, 3), (5, 7), (7, 11)],
+            ChordQuality::HalfDiminished7 => &[(1, 0), (3, 3), (5, 6), (7, 10)],
+            ChordQuality::Diminished7 => &[(1, 0), (3, 3), (5, 6), (7, 9)],
+            ChordQuality::Augmented7 => &[(1, 0), (3, 4), (5, 8), (7, 10)],
+            ChordQuality::Dominant9 => &[(1, 0), (3, 4), (5, 7), (7, 10), (9, 14)],
+            ChordQuality::Major9 => &[(1, 0), (3, 4), (5, 7), (7, 11), (9, 14)],
+            ChordQuality::Minor9 => &[(1, 0), (3, 3), (5, 7), (7, 10), (9, 14)],
+            ChordQuality::Add9 => &[(1, 0), (3, 4), (5, 7), (9, 14)]
+        }
+    }
+
+    #[must_use]
+    pub fn intervals(self) -> Vec<Interval> {
+        self.tones().iter().map(|(_, semitones)| Interval(*semitones)).collect()
+    }
+
+    #[must_use]
+    pub fn symbol(self) -> &'static str {
+        SYMBOLS
+            .iter()
+            .find(|(quality, _)| *quality == self)
+            .map_or("", |(_, symbols)| symbols[0])
+    }
+}
+
+impl FromStr for ChordQuality {
+    type Err = &'static str;
+
+    ///
+    /// Symbol of the quality written after the root, e.g. m, 7 or maj7, the empty one is major.
+    ///
+    fn from_str(symbol: &str) -> Result<Self, Self::Err> {
+        SYMBOLS
+            .iter()
+            .find(|(_, symbols)| symbols.contains(&symbol))
+            .map(|(quality, _)| *quality)
+            .ok_or("Invalid chord quality: must be one of [maj, m, dim, aug, sus2, sus4, 5, 6, m6, 7, maj7, m7, mM7, m7b5, dim7, aug7, 9, maj9, m9, add9].")
+    }
+}
+
+///
+/// Chord symbol like Cmaj7 or F#m: the root without the octave and the quality.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    root: PitchClass,
+    spelling: Shift, // Accidental of the root as it's written
+    quality: ChordQuality
+}
+
+impl Chord {
+    #[must_use]
+    pub fn new(root: Note, quality: ChordQuality) -> Chord {
+        Chord { root: root.pitch_class(), spelling: root.semitone_shift(), quality }
+    }
+
+    #[must_use]
+    pub fn root(&self) -> PitchClass {
+        self.root
+    }
+
+    #[must_use]
+    pub fn quality(&self) -> ChordQuality {
+        self.quality
+    }
+
+    ///
+    /// Pitch classes of the chord tones, bit N stands for N semitones above C.
+    ///
+    #[must_use]
+    pub fn pitch_classes(&self) -> u16 {
+        self.quality
+            .tones()
+            .iter()
+            .fold(0, |mask, (_, semitones)| mask | (1 << ((i16::from(self.root) + semitones) % 12)))
+    }
+
+    ///
+    /// Tones of the chord in the root position starting from the root in the given octave,
+    /// spelled by their degrees: the tones beyond the highest octave are left out.
+    ///
+    #[must_use]
+    pub fn notes(&self, octave: OctaveNumber) -> Vec<Note> {
+        let root = Note::from_pitch_class(self.root, octave, self.spelling);
+        let root_letter = LETTERS.iter().position(|letter| *letter == root.letter()).unwrap_or_default();
+        let spelling = if self.spelling == Shift::None { Shift::Diesis } else { self.spelling };
+
+        self.quality
+            .tones()
+            .iter()
+            .filter_map(|(degree, semitones)| {
+                let note = root.transposed(Interval(*semitones))?.spelled(spelling);
+                let letter = LETTERS[(root_letter + usize::from(*degree) - 1) % LETTERS.len()];
+                Some(note.with_letter(letter).unwrap_or(note))
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let root = Note::from_pitch_class(self.root, 0, self.spelling);
+        let accidental = match root.semitone_shift() {
+            Shift::None => "",
+            Shift::Diesis => "#",
+            Shift::Bemolle => "b"
+        };
+        write!(f, "{}{accidental}{}", root.letter(), self.quality.symbol())
+    }
+}
+
+impl FromStr for Chord {
+    type Err = &'static str;
+
+    ///
+    /// The root letter with an optional accidental [#, ♯, b, ♭] and the quality, e.g. C, F#m, Bb7 or Ebmaj7.
+    ///
+    fn from_str(symbol: &str) -> Result<Self, Self::Err> {
+        let root_length = symbol
+            .char_indices()
+            .nth(1)
+            .filter(|(_, accidental)| matches!(accidental, '#' | '♯' | 'b' | '♭'))
+            .map_or(1, |(index, accidental)| index + accidental.len_utf8())
+            .min(symbol.len());
+
+        if !symbol.is_char_boundary(root_length) {
+            return Err("Invalid chord: the root must be a note letter [A..G] or [a..g].");
+        }
+
+        let (root, suffix) = symbol.split_at(root_length);
+        let (root, spelling) = Note::parse_pitch_class(root)?;
+
+        Ok(Chord { root, spelling, quality: suffix.parse()? })
+    }
+}
+
+
+
+#[test]
+fn test() {
+    let names = |notes: Vec<Note>| notes.iter().map(ToString::to_string).collect::<Vec<String>>().join(" ");
+
+    let chord = Chord::from_str("Cmaj7").unwrap();
+    assert_eq!(chord.quality(), ChordQuality::Major7);
+    assert_eq!(names(chord.notes(4)), "C4 E4 G4 B4");
+
+    assert_eq!(names(Chord::from_str("F#m").unwrap().notes(3)), "F3# A3 C4#");
+    assert_eq!(names(Chord::from_str("Cm").unwrap().notes(4)), "C4 E4b G4");
+    assert_eq!(names(Chord::from_str("Bb7").unwrap().notes(3)), "B3b D4 F4 A4b");
+    assert_eq!(names(Chord::from_str("Bdim7").unwrap().notes(3)), "B3 D4 F4 A4b");
+    assert_eq!(names(Chord::from_str("Asus4").unwrap().notes(4)), "A4 D5 E5");
+
+    assert_eq!(Chord::from_str("Ebm7b5").unwrap().to_string(), "Ebm7b5");
+    assert_eq!(Chord::from_str("C♯-7").unwrap().to_string(), "C#m7");
+    assert_eq!(Chord::from_str("G").unwrap().pitch_classes(), 1 << 7 | 1 << 11 | 1 << 2);
+    assert_eq!(Chord::new(Note::Db(4), ChordQuality::Major).to_string(), "Db");
+
+    assert_eq!(ChordQuality::from_str("m7b5").unwrap(), ChordQuality::HalfDiminished7);
+    assert!(Chord::from_str("H7").is_err());
+    assert!(Chord::from_str("Cmaj13").is_err());
+    assert!(Chord::from_str("").is_err());
+}
@@ -0,0 +1,194 @@
+use std::{ops::{Add, Neg, Sub}, str::FromStr};
+
+use super::Note;
+
+///
+/// Distance between two pitches in semitones, the negative ones go down.
+///
+#[derive(Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug)]
+pub struct Interval(pub i16);
+
+const SEMITONES_IN_OCTAVE: i16 = 12;
+const DEGREES_IN_OCTAVE: i16 = 7;
+
+// Names of the intervals within the octave, the tritone is the augmented fourth:
+const NAMES: [(char, i16); 12] = [
+    ('P', 1), ('m', 2), ('M', 2), ('m', 3), ('M', 3), ('P', 4),
+    ('A', 4), ('P', 5), ('m', 6), ('M', 6), ('m', 7), ('M', 7)
+];
+
+// Semitones of the major or perfect interval for each degree above the unison:
+const MAJOR_SEMITONES: [i16; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+impl Interval {
+    pub const UNISON: Interval = Interval(0);
+    pub const MINOR_SECOND: Interval = Interval(1);
+    pub const MAJOR_SECOND: Interval = Interval(2);
+    pub const MINOR_THIRD: Interval = Interval(3);
+    pub const MAJOR_THIRD: Interval = Interval(4);
+    pub const PERFECT_FOURTH: Interval = Interval(5);
+    pub const TRITONE: Interval = Interval(6);
+    pub const PERFECT_FIFTH: Interval = Interval(7);
+    pub const MINOR_SIXTH: Interval = Interval(8);
+    pub const MAJOR_SIXTH: Interval = Interval(9);
+    pub const MINOR_SEVENTH: Interval = Interval(10);
+    pub const MAJOR_SEVENTH: Interval = Interval(11);
+    pub const OCTAVE: Interval = Interval(12);
+
+    #[must_use]
+    pub const fn semitones(self) -> i16 {
+        self.0
+    }
+
+    #[must_use]
+    pub const fn octaves(count: i16) -> Interval {
+        Interval(count * SEMITONES_IN_OCTAVE)
+    }
+
+    ///
+    /// Interval from the first note up to the second one, it's negative if the second one is lower.
+    ///
+    #[must_use]
+    pub fn between(from: Note, to: Note) -> Interval {
+        Interval(i16::try_from(to.position() - from.position()).unwrap_or_default())
+    }
+
+    ///
+    /// The same interval reduced to a single octave upwards, e.g. the major tenth becomes the major third.
+    ///
+    #[must_use]
+    pub const fn simple(self) -> Interval {
+        Interval(self.0.rem_euclid(SEMITONES_IN_OCTAVE))
+    }
+
+    ///
+    /// The complement of the simple interval to the octave, e.g. the fifth for the fourth.
+    ///
+    #[must_use]
+    pub const fn inverted(self) -> Interval {
+        Interval((SEMITONES_IN_OCTAVE - self.simple().0) % SEMITONES_IN_OCTAVE)
+    }
+
+    ///
+    /// Frequency ratio of the interval in the equal temperament.
+    ///
+    #[must_use]
+    pub fn ratio(self) -> f32 {
+        2_f32.powf(f32::from(self.0) / f32::from(SEMITONES_IN_OCTAVE))
+    }
+}
+
+impl Add for Interval {
+    type Output = Interval;
+
+    fn add(self, other: Interval) -> Interval {
+        Interval(self.0 + other.0)
+    }
+}
+
+impl Sub for Interval {
+    type Output = Interval;
+
+    fn sub(self, other: Interval) -> Interval {
+        Interval(self.0 - other.0)
+    }
+}
+
+impl Neg for Interval {
+    type Output = Interval;
+
+    fn neg(self) -> Interval {
+        Interval(-self.0)
+    }
+}
+
+impl Sub for Note {
+    type Output = Interval;
+
+    fn sub(self, other: Note) -> Interval {
+        Interval::between(other, self)
+    }
+}
+
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let distance = self.0.unsigned_abs();
+        let octaves = distance / SEMITONES_IN_OCTAVE.unsigned_abs();
+        let (quality, number) = NAMES[usize::from(distance % SEMITONES_IN_OCTAVE.unsigned_abs())];
+
+        // The whole octaves are perfect: the 8th, the 15th, etc.
+        let number = number.unsigned_abs() + octaves * DEGREES_IN_OCTAVE.unsigned_abs();
+        write!(f, "{sign}{quality}{number}")
+    }
+}
+
+impl FromStr for Interval {
+    type Err = &'static str;
+
+    ///
+    /// Either the number of semitones (e.g. 7 or -12) or the name of the interval,
+    /// the quality and the number (e.g. P5, m3, M9, A4, d5 or TT), optionally with '-' to go down.
+    ///
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if let Ok(semitones) = name.parse::<i16>() {
+            return Ok(Interval(semitones));
+        }
+
+        let (sign, name) = match name.strip_prefix('-') {
+            Some(name) => (-1, name),
+            None => (1, name.strip_prefix('+').unwrap_or(name))
+        };
+
+        if name == "TT" {
+            return Ok(Interval(sign * Interval::TRITONE.0));
+        }
+
+        let mut chars = name.chars();
+        let quality = chars.next().ok_or("Invalid interval: empty name.")?;
+        let Ok(number @ 1..=99) = chars.as_str().parse::<i16>() else {
+            return Err("Invalid interval number: must be in the range of 1..99.");
+        };
+
+        let degree = (number - 1) % DEGREES_IN_OCTAVE;
+        let is_perfect = matches!(degree, 0 | 3 | 4);
+        let alteration = match (quality, is_perfect) {
+            ('P', true) | ('M', false) => 0,
+            ('m', false) => -1,
+            ('A', _) => 1,
+            ('d', true) if number > 1 => -1,
+            ('d', false) => -2,
+            _ => return Err("Invalid interval quality: must be one of [P, M, m, A, d] fitting the number.")
+        };
+
+        let octaves = (number - 1) / DEGREES_IN_OCTAVE;
+        let semitones = octaves * SEMITONES_IN_OCTAVE + MAJOR_SEMITONES[usize::from(degree.unsigned_abs())] + alteration;
+        Ok(Interval(sign * semitones))
+    }
+}
+
+
+
+#[test]
+fn test() {
+    assert_eq!(Interval::from_str("P5").unwrap(), Interval::PERFECT_FIFTH);
+    assert_eq!(Interval::from_str("-m3").unwrap(), -Interval::MINOR_THIRD);
+    assert_eq!(Interval::from_str("M9").unwrap(), Interval::OCTAVE + Interval::MAJOR_SECOND);
+    assert_eq!(Interval::from_str("d5").unwrap(), Interval::TRITONE);
+    assert_eq!(Interval::from_str("-12").unwrap(), Interval::octaves(-1));
+    assert!(Interval::from_str("P3").is_err());
+    assert!(Interval::from_str("d1").is_err());
+
+    for semitones in -30..=30 {
+        let interval = Interval(semitones);
+        assert_eq!(Interval::from_str(&interval.to_string()).unwrap(), interval, "{interval}");
+    }
+    assert_eq!(Interval(16).to_string(), "M10");
+    assert_eq!(Interval(24).to_string(), "P15");
+
+    assert_eq!(Note::G(4) - Note::C(4), Interval::PERFECT_FIFTH);
+    assert_eq!(Interval::between(Note::A(4), Note::Cs(3)), Interval(-20));
+    assert_eq!(Interval::PERFECT_FOURTH.inverted(), Interval::PERFECT_FIFTH);
+    assert_eq!(Interval(-5).simple(), Interval::PERFECT_FIFTH);
+    assert!((Interval::OCTAVE.ratio() - 2.0).abs() < 1e-6);
+}
@@ -2,8 +2,18 @@
 
 use std::str::{self, FromStr};
 
+mod chord;
+mod interval;
+mod scale;
+
+pub use chord::{Chord, ChordQuality};
+pub use interval::Interval;
+pub use scale::Scale;
+
 pub type SemitoneNumber = u8;
 pub type OctaveNumber = u8;
+pub type MidiNumber = u8;  // 60 is C4, 69 is A4
+pub type PitchClass = u8;  // Note regardless of the octave, 0 is C, 11 is B
 
 #[derive(Default, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
 pub enum Shift {
@@ -13,7 +23,11 @@ pub enum Shift {
     Bemolle
 }
 
-#[derive(PartialOrd, Copy, Clone, Debug)]
+///
+/// Spelled note: the enharmonic ones (e.g. C#4 and Db4) are equal
+/// and ordered by their pitch, but still printed as they are spelled.
+///
+#[derive(Copy, Clone, Debug)]
 pub enum Note {
     C(OctaveNumber),
     Cs(OctaveNumber),
@@ -34,6 +48,12 @@ pub enum Note {
     B(OctaveNumber)
 }
 
+const A4_FREQ: f32 = 440_f32;
+const MIDI_C0: MidiNumber = 12;
+const MIDI_MAX: MidiNumber = 127;
+const LETTERS: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+const NATURALS: [PitchClass; 7] = [0, 2, 4, 5, 7, 9, 11]; // Pitch classes of the letters
+
 impl Note {
     pub const SEMITONE_COUNT: u8 = 12;
 
@@ -59,45 +79,100 @@ impl Note {
 
     #[must_use]
     pub fn find_nearest(freq_hz: f32) -> Note {
-        let mut lower_bound = 0_u8;
-        let mut upper_bound = u8::MAX;
-        loop {
-            let median = lower_bound + (upper_bound - lower_bound) / 2;
-            let probe_note = Note::from_semitone(median);
-            let probe_freq = probe_note.freq();
+        Self::from_freq(freq_hz).0
+    }
 
-            let median_freq_delta = (freq_hz - probe_freq).abs();
+    ///
+    /// Nearest note to the frequency and the deviation from it in cents (-50..50).
+    ///
+    #[must_use]
+    pub fn from_freq(freq_hz: f32) -> (Note, f32) {
+        Self::from_freq_at(freq_hz, A4_FREQ)
+    }
 
-            if (lower_bound == upper_bound) || (median_freq_delta < 0.5_f32) {
-                return probe_note;
-            }
+    ///
+    /// Nearest note to the frequency with the given pitch of A4 and the deviation from it in cents.
+    /// The frequencies beyond the notes are clamped to C0 or to the highest note.
+    ///
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    pub fn from_freq_at(freq_hz: f32, a4_freq: f32) -> (Note, f32) {
+        let position = 12_f32 * (freq_hz / a4_freq).log2() + Note::A(4).position() as f32;
+        let note = Note::from_semitone(position.round().clamp(0_f32, f32::from(u8::MAX)) as u8);
+        (note, 1200_f32 * (freq_hz / note.freq_at(a4_freq)).log2())
+    }
 
-            if median == lower_bound {
-                let upper_note = Note::from_semitone(upper_bound);
-                if (upper_note.freq() - freq_hz).abs() > median_freq_delta {
-                    return probe_note;
-                }
-                return upper_note;
-            }
+    ///
+    /// The MIDI octave -1 (the numbers 0..11) is below C0, so it has no notes.
+    ///
+    #[must_use]
+    pub const fn from_midi(midi: MidiNumber) -> Option<Note> {
+        if midi < MIDI_C0 || midi > MIDI_MAX {
+            return None;
+        }
 
-            if median == upper_bound {
-                let lower_note = Note::from_semitone(lower_bound);
-                if (lower_note.freq() - freq_hz).abs() > median_freq_delta {
-                    return probe_note;
-                }
-                return lower_note;
-            }
+        Some(Note::from_semitone(midi - MIDI_C0))
+    }
 
-            if freq_hz < probe_freq {
-                upper_bound = median;
-            } else if freq_hz > probe_freq {
-                lower_bound = median;
-            } else {
-                return probe_note;
-            }
+    #[must_use]
+    pub fn midi(&self) -> Option<MidiNumber> {
+        let position = self.position() + i32::from(MIDI_C0);
+        u8::try_from(position).ok().filter(|midi| *midi <= MIDI_MAX)
+    }
+
+    ///
+    /// The black keys are spelled with sharps unless flats are asked for.
+    ///
+    #[must_use]
+    pub const fn from_pitch_class(pitch_class: PitchClass, octave: OctaveNumber, spelling: Shift) -> Note {
+        let is_flat = matches!(spelling, Shift::Bemolle);
+        match pitch_class % Self::SEMITONE_COUNT {
+            0 => Note::C(octave),
+            1 => if is_flat { Note::Db(octave) } else { Note::Cs(octave) },
+            2 => Note::D(octave),
+            3 => if is_flat { Note::Eb(octave) } else { Note::Ds(octave) },
+            4 => Note::E(octave),
+            5 => Note::F(octave),
+            6 => if is_flat { Note::Gb(octave) } else { Note::Fs(octave) },
+            7 => Note::G(octave),
+            8 => if is_flat { Note::Ab(octave) } else { Note::Gs(octave) },
+            9 => Note::A(octave),
+            10 => if is_flat { Note::Bb(octave) } else { Note::As(octave) },
+            _ => Note::B(octave)
         }
     }
 
+    ///
+    /// Parses the note name without the octave, e.g. C, F# or Bb, returns its pitch class and spelling.
+    ///
+    /// # Errors
+    ///
+    /// Returns the description of the problem if the letter or the accidental is unknown.
+    ///
+    pub fn parse_pitch_class(name: &str) -> Result<(PitchClass, Shift), &'static str> {
+        let mut chars = name.chars();
+        let letter = chars.next().ok_or("Invalid note format: the note letter [A..G] or [a..g] is not present.")?;
+        let shift = match chars.as_str() {
+            "" => Shift::None,
+            "s" | "#" | "♯" => Shift::Diesis,
+            "b" | "♭" => Shift::Bemolle,
+            _ => return Err("Invalid semitone shift format: must absent or be one of [s, #, ♯, b, ♭]")
+        };
+
+        let natural = LETTERS
+            .iter()
+            .position(|candidate| candidate.eq_ignore_ascii_case(&letter))
+            .ok_or("Invalid note name: unexpected note letter, must be in the range of [A..G] or [a..g].")?;
+        let pitch_class = i32::from(NATURALS[natural]);
+        let pitch_class = match shift {
+            Shift::None => pitch_class,
+            Shift::Diesis => pitch_class + 1,
+            Shift::Bemolle => pitch_class - 1
+        };
+
+        Ok((u8::try_from(pitch_class.rem_euclid(12)).unwrap_or_default(), shift))
+    }
+
     #[must_use]
     pub const fn octave_number(&self) -> OctaveNumber {
         match *self {
@@ -130,28 +205,115 @@ impl Note {
         }
     }
 
+    #[must_use]
+    pub const fn pitch_class(&self) -> PitchClass {
+        match *self {
+            Note::C(_) => 0,
+            Note::Cs(_) | Note::Db(_) => 1,
+            Note::D(_) => 2,
+            Note::Ds(_) | Note::Eb(_) => 3,
+            Note::E(_) => 4,
+            Note::F(_) => 5,
+            Note::Fs(_) | Note::Gb(_) => 6,
+            Note::G(_) => 7,
+            Note::Gs(_) | Note::Ab(_) => 8,
+            Note::A(_) => 9,
+            Note::As(_) | Note::Bb(_) => 10,
+            Note::B(_) => 11
+        }
+    }
+
+    ///
+    /// Semitones above C0 saturated at the highest semitone number,
+    /// the notes beyond it need the position.
+    ///
     #[must_use]
     pub fn semitone_number(&self) -> SemitoneNumber {
+        self.octave_number().saturating_mul(Self::SEMITONE_COUNT).saturating_add(self.pitch_class())
+    }
+
+    ///
+    /// Semitones above C0, unlike the semitone number it doesn't overflow in the high octaves.
+    ///
+    #[must_use]
+    pub const fn position(self) -> i32 {
+        self.octave_number() as i32 * Self::SEMITONE_COUNT as i32 + self.pitch_class() as i32
+    }
+
+    const fn from_position(position: i32, spelling: Shift) -> Option<Note> {
+        let octave = position.div_euclid(Self::SEMITONE_COUNT as i32);
+        if octave < 0 || octave > OctaveNumber::MAX as i32 {
+            return None;
+        }
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        Some(Note::from_pitch_class(position.rem_euclid(Self::SEMITONE_COUNT as i32) as u8, octave as u8, spelling))
+    }
+
+    #[must_use]
+    pub const fn letter(&self) -> char {
         match *self {
-            Note::C(octave_number) => octave_number * Self::SEMITONE_COUNT,
-            Note::Cs(octave_number) | Note::Db(octave_number) => octave_number * Self::SEMITONE_COUNT + 1,
-            Note::D(octave_number) => octave_number * Self::SEMITONE_COUNT + 2,
-            Note::Ds(octave_number) | Note::Eb(octave_number) => octave_number * Self::SEMITONE_COUNT + 3,
-            Note::E(octave_number) => octave_number * Self::SEMITONE_COUNT + 4,
-            Note::F(octave_number) => octave_number * Self::SEMITONE_COUNT + 5,
-            Note::Fs(octave_number) | Note::Gb(octave_number) => octave_number * Self::SEMITONE_COUNT + 6,
-            Note::G(octave_number) => octave_number * Self::SEMITONE_COUNT + 7,
-            Note::Gs(octave_number) | Note::Ab(octave_number) => octave_number * Self::SEMITONE_COUNT + 8,
-            Note::A(octave_number) => octave_number * Self::SEMITONE_COUNT + 9,
-            Note::As(octave_number) | Note::Bb(octave_number) => octave_number * Self::SEMITONE_COUNT + 10,
-            Note::B(octave_number) => octave_number * Self::SEMITONE_COUNT + 11,
+            Note::C(_) | Note::Cs(_) => 'C',
+            Note::Db(_) | Note::D(_) | Note::Ds(_) => 'D',
+            Note::Eb(_) | Note::E(_) => 'E',
+            Note::F(_) | Note::Fs(_) => 'F',
+            Note::Gb(_) | Note::G(_) | Note::Gs(_) => 'G',
+            Note::Ab(_) | Note::A(_) | Note::As(_) => 'A',
+            Note::Bb(_) | Note::B(_) => 'B'
+        }
+    }
+
+    ///
+    /// The same pitch spelled with a sharp or with a flat if it's a black key,
+    /// the note is left as it is without the shift.
+    ///
+    #[must_use]
+    pub const fn spelled(&self, shift: Shift) -> Note {
+        match shift {
+            Shift::None => *self,
+            _ => Note::from_pitch_class(self.pitch_class(), self.octave_number(), shift)
         }
     }
 
+    ///
+    /// The same pitch spelled with the given letter, if there is such a note (e.g. E# isn't).
+    ///
+    #[must_use]
+    pub fn with_letter(&self, letter: char) -> Option<Note> {
+        [Shift::Diesis, Shift::Bemolle]
+            .into_iter()
+            .map(|shift| self.spelled(shift))
+            .find(|note| note.letter() == letter.to_ascii_uppercase())
+    }
+
+    ///
+    /// Moves the note by the interval keeping the accidentals of its spelling, the sharps by default.
+    ///
+    #[must_use]
+    pub fn transposed(&self, interval: Interval) -> Option<Note> {
+        Note::from_position(self.position() + i32::from(interval.semitones()), self.semitone_shift())
+    }
+
     #[must_use]
     pub fn freq(&self) -> f32 {
+        self.freq_at(A4_FREQ)
+    }
+
+    ///
+    /// Frequency of the note detuned by the cents, the inverse of `from_freq`.
+    ///
+    #[must_use]
+    pub fn freq_with_cents(&self, cents: f32) -> f32 {
+        self.freq() * 2_f32.powf(cents / 1200_f32)
+    }
+
+    ///
+    /// Frequency of the note in the equal temperament tuned to the given pitch of A4,
+    /// e.g. 432 or 442 Hz instead of the standard 440 Hz.
+    ///
+    #[must_use]
+    pub fn freq_at(&self, a4_freq: f32) -> f32 {
         const A4: Note = Note::A(4);
-        const A4_FREQ: f32 = 440_f32;
 
         // https://en.wikipedia.org/wiki/Musical_note
         //
@@ -160,55 +322,61 @@ impl Note {
         //   440 Hz - frequency of the A4 note.
         //   n - semitone number relative to the A4 note.
         //
-        let power = (f32::from(self.semitone_number()) - f32::from(A4.semitone_number())) / 12_f32;
-        A4_FREQ * 2_f32.powf(power)
+        #[allow(clippy::cast_precision_loss)]
+        let power = (self.position() - A4.position()) as f32 / 12_f32;
+        a4_freq * 2_f32.powf(power)
     }
 }
 
-impl ToString for Note {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Note {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match *self {
-            Note::C(octave_number)  => format!("C{octave_number}"),
-            Note::Cs(octave_number) => format!("C{octave_number}#"),
-            Note::Db(octave_number) => format!("D{octave_number}b"),
-            Note::D(octave_number)  => format!("D{octave_number}"),
-            Note::Ds(octave_number) => format!("D{octave_number}#"),
-            Note::Eb(octave_number) => format!("E{octave_number}b"),
-            Note::E(octave_number)  => format!("E{octave_number}"),
-            Note::F(octave_number)  => format!("F{octave_number}"),
-            Note::Fs(octave_number) => format!("F{octave_number}#"),
-            Note::Gb(octave_number) => format!("G{octave_number}b"),
-            Note::G(octave_number)  => format!("G{octave_number}"),
-            Note::Gs(octave_number) => format!("G{octave_number}#"),
-            Note::Ab(octave_number) => format!("A{octave_number}b"),
-            Note::A(octave_number)  => format!("A{octave_number}"),
-            Note::As(octave_number) => format!("A{octave_number}#"),
-            Note::Bb(octave_number) => format!("B{octave_number}b"),
-            Note::B(octave_number)  => format!("B{octave_number}"),
+            Note::C(octave_number)  => write!(f, "C{octave_number}"),
+            Note::Cs(octave_number) => write!(f, "C{octave_number}#"),
+            Note::Db(octave_number) => write!(f, "D{octave_number}b"),
+            Note::D(octave_number)  => write!(f, "D{octave_number}"),
+            Note::Ds(octave_number) => write!(f, "D{octave_number}#"),
+            Note::Eb(octave_number) => write!(f, "E{octave_number}b"),
+            Note::E(octave_number)  => write!(f, "E{octave_number}"),
+            Note::F(octave_number)  => write!(f, "F{octave_number}"),
+            Note::Fs(octave_number) => write!(f, "F{octave_number}#"),
+            Note::Gb(octave_number) => write!(f, "G{octave_number}b"),
+            Note::G(octave_number)  => write!(f, "G{octave_number}"),
+            Note::Gs(octave_number) => write!(f, "G{octave_number}#"),
+            Note::Ab(octave_number) => write!(f, "A{octave_number}b"),
+            Note::A(octave_number)  => write!(f, "A{octave_number}"),
+            Note::As(octave_number) => write!(f, "A{octave_number}#"),
+            Note::Bb(octave_number) => write!(f, "B{octave_number}b"),
+            Note::B(octave_number)  => write!(f, "B{octave_number}"),
         }
     }
 }
 
 impl PartialEq for Note {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::C(left_octave_number) , Self::C(right_octave_number))   |
-            (Self::Cs(left_octave_number) | Self::Db(left_octave_number), Self::Cs(right_octave_number) | Self::Db(right_octave_number)) |
-            (Self::D(left_octave_number) , Self::D(right_octave_number))   |
-            (Self::Ds(left_octave_number) | Self::Eb(left_octave_number), Self::Ds(right_octave_number) | Self::Eb(right_octave_number)) |
-            (Self::E(left_octave_number) , Self::E(right_octave_number))   |
-            (Self::F(left_octave_number) , Self::F(right_octave_number))   |
-            (Self::Fs(left_octave_number) | Self::Gb(left_octave_number), Self::Fs(right_octave_number) | Self::Gb(right_octave_number)) |
-            (Self::G(left_octave_number) , Self::G(right_octave_number))   |
-            (Self::Gs(left_octave_number) | Self::Ab(left_octave_number), Self::Gs(right_octave_number) | Self::Ab(right_octave_number)) |
-            (Self::A(left_octave_number) , Self::A(right_octave_number))   |
-            (Self::As(left_octave_number) | Self::Bb(left_octave_number), Self::As(right_octave_number) | Self::Bb(right_octave_number)) |
-            (Self::B(left_octave_number) , Self::B(right_octave_number))   => left_octave_number == right_octave_number,
-            _ => false,
-        }
+        self.position() == other.position()
     }
 }
 
+impl Eq for Note {}
+
+impl PartialOrd for Note {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Note {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.position().cmp(&other.position())
+    }
+}
+
+impl std::hash::Hash for Note {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.position().hash(state);
+    }
+}
 
 impl FromStr for Note {
     type Err = &'static str;
@@ -294,6 +462,30 @@ fn test() {
     assert_eq!(Note::from_str("C4#").unwrap(), Note::Db(4));
     assert_eq!(Note::from_str("c135s").unwrap().to_string(), "C135#");
     assert_eq!(Note::from_semitone(16), Note::E(1));
+    assert_eq!(Note::C(30).semitone_number(), u8::MAX);
+    assert!((Note::C(30).freq() / Note::C(29).freq() - 2.0).abs() < 1e-3);
+
+    assert_eq!(Note::from_midi(60), Some(Note::C(4)));
+    assert_eq!(Note::from_midi(11), None);
+    assert_eq!(Note::A(4).midi(), Some(69));
+    assert_eq!(Note::Ab(9).midi(), None);
+
+    let (note, cents) = Note::from_freq(452.0);
+    assert_eq!(note, Note::A(4));
+    assert!((cents - 46.6).abs() < 0.1, "{cents}");
+    assert!((Note::A(4).freq_with_cents(cents) - 452.0).abs() < 0.01);
+    assert_eq!(Note::from_freq_at(432.0, 432.0).0, Note::A(4));
+
+    assert!(Note::Db(4) == Note::Cs(4) && Note::Db(4).cmp(&Note::Cs(4)).is_eq());
+    assert!(Note::B(3) < Note::C(4) && Note::C(4) < Note::Db(4) && Note::Cs(4) < Note::D(4));
+    assert_eq!(Note::Cs(4).spelled(Shift::Bemolle).to_string(), "D4b");
+    assert_eq!(Note::Gs(2).with_letter('a').map(|note| note.to_string()).as_deref(), Some("A2b"));
+    assert_eq!(Note::E(2).with_letter('F'), None);
+    assert_eq!(Note::Bb(3).transposed(Interval::MAJOR_THIRD).map(|note| note.to_string()).as_deref(), Some("D4"));
+    assert_eq!(Note::Eb(4).transposed(-Interval::OCTAVE).map(|note| note.to_string()).as_deref(), Some("E3b"));
+    assert_eq!(Note::C(0).transposed(-Interval::MINOR_SECOND), None);
+    assert_eq!(Note::parse_pitch_class("Bb"), Ok((10, Shift::Bemolle)));
+    assert_eq!(Note::parse_pitch_class("b#"), Ok((0, Shift::Diesis)));
 
     for i in 0..=u8::MAX {
         let probe_note = Note::from_semitone(i);
@@ -0,0 +1,125 @@
+use std::str::FromStr;
+
+use super::{Note, Interval, Shift, LETTERS};
+
+///
+/// Degrees of the scale above its tonic, bit N stands for N semitones.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scale {
+    Chromatic,
+    Major,           // Ionian
+    Minor,           // Natural minor, Aeolian
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,           // Minor pentatonic with the flat fifth
+    Custom(u16)
+}
+
+impl Scale {
+    #[must_use]
+    pub fn degrees(self) -> u16 {
+        let degrees: &[u8] = match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+            Scale::Custom(degrees) => return degrees & 0xFFF
+        };
+
+        degrees.iter().fold(0, |mask, degree| mask | (1 << degree))
+    }
+
+    #[must_use]
+    pub fn contains(self, degree: usize) -> bool {
+        self.degrees() & (1 << (degree % 12)) != 0
+    }
+
+    ///
+    /// Notes of the scale in the octave above the tonic. The heptatonic scales use each letter once,
+    /// as far as the notes allow, the rest keep the accidentals of the tonic, the sharps by default.
+    ///
+    #[must_use]
+    pub fn notes(self, tonic: Note) -> Vec<Note> {
+        let is_heptatonic = self.degrees().count_ones() == 7;
+        let tonic_letter = LETTERS.iter().position(|letter| *letter == tonic.letter()).unwrap_or_default();
+        let spelling = if tonic.semitone_shift() == Shift::None { Shift::Diesis } else { tonic.semitone_shift() };
+
+        (0..12_i16)
+            .filter(|degree| self.contains(usize::from(degree.unsigned_abs())))
+            .filter_map(|degree| tonic.transposed(Interval(degree)))
+            .enumerate()
+            .map(|(index, note)| {
+                let spelled = note.spelled(spelling);
+                if is_heptatonic {
+                    spelled.with_letter(LETTERS[(tonic_letter + index) % LETTERS.len()]).unwrap_or(spelled)
+                } else {
+                    spelled
+                }
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Scale {
+    type Err = &'static str;
+
+    ///
+    /// Either the name of the scale or its degrees in semitones above the tonic like 0/2/4/7/9.
+    ///
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let scale = match name {
+            "chromatic" => Scale::Chromatic,
+            "major" | "ionian" => Scale::Major,
+            "minor" | "aeolian" => Scale::Minor,
+            "dorian" => Scale::Dorian,
+            "phrygian" => Scale::Phrygian,
+            "lydian" => Scale::Lydian,
+            "mixolydian" => Scale::Mixolydian,
+            "locrian" => Scale::Locrian,
+            "pentatonic" => Scale::MajorPentatonic,
+            "minor-pentatonic" => Scale::MinorPentatonic,
+            "blues" => Scale::Blues,
+            degrees => {
+                let mut mask = 0_u16;
+                for degree in degrees.split('/') {
+                    let Ok(degree @ 0..=11) = degree.trim().parse::<u8>() else {
+                        return Err("Invalid scale: expected the name of the scale or its degrees in the range of 0..11 like 0/2/4/7/9.");
+                    };
+                    mask |= 1 << degree;
+                }
+                Scale::Custom(mask)
+            }
+        };
+
+        Ok(scale)
+    }
+}
+
+
+
+#[test]
+fn test() {
+    assert_eq!(Scale::from_str("0/2/4/5/7/9/11").unwrap().degrees(), Scale::Major.degrees());
+    assert!(Scale::from_str("0/12").is_err());
+    assert!(Scale::from_str("harmonic").is_err());
+
+    let names = |notes: Vec<Note>| notes.iter().map(ToString::to_string).collect::<Vec<String>>().join(" ");
+    assert_eq!(names(Scale::Major.notes(Note::F(4))), "F4 G4 A4 B4b C5 D5 E5");
+    assert_eq!(names(Scale::Minor.notes(Note::C(4))), "C4 D4 E4b F4 G4 A4b B4b");
+    assert_eq!(names(Scale::Major.notes(Note::Eb(4))), "E4b F4 G4 A4b B4b C5 D5");
+    assert_eq!(names(Scale::Lydian.notes(Note::D(3))), "D3 E3 F3# G3# A3 B3 C4#");
+    assert_eq!(names(Scale::MinorPentatonic.notes(Note::A(4))), "A4 C5 D5 E5 G5");
+}
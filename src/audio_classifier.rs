@@ -1,38 +1,240 @@
-use crate::wave::wav_header::WavHeader;
+use crate::{synth::rtttl, wave::wav_header};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AudioType {
     Unknown,
-    Mp3,
     Wav,
-    //Xm, // Not supported yet
+    Mp3,
+    Flac,
+    Ogg,   // Vorbis or Opus
+    Aiff,
+    Midi,
+    Xm,    // FastTracker 2
+    Mod,   // ProTracker and its clones
+    S3m,   // ScreamTracker 3
+    Vgm,   // Video Game Music register dump
+    Rtttl, // Ring tone text
     Synth
 }
 
-fn is_mp3(buf: &[u8]) -> bool {
-    ((buf[0] == 0x48) && (buf[1] == 0x44) && (buf[2] == 0x33)) // ID3 tag
-    || ((buf[0] == 0xFF) && (buf[1] == 0xFB)) // LAME beginning
+///
+/// How sure the classifier is about the type found by the contents.
+///
+enum Confidence {
+    Magic, // Unambiguous signature at the fixed offset
+    Guess  // The contents only look like the type, the extension may overrule it
+}
+
+fn has_at(buf: &[u8], offset: usize, tag: &[u8]) -> bool {
+    buf.get(offset..offset + tag.len()) == Some(tag)
 }
 
 ///
 /// Check whether the file starts with the "#!/bin/beesynth".
-/// 
+///
 fn is_synth(buf: &[u8]) -> bool {
-    const TAG: &[u8] = b"#!/bin/beesynth";
-    buf.len() >= TAG.len() && buf[..TAG.len()] == *TAG
+    has_at(buf, 0, b"#!/bin/beesynth")
 }
 
-impl AudioType {
-    pub fn classify(buf: &[u8]) -> Self {
-        if WavHeader::is_wav(buf) {
-            AudioType::Wav
-        // } else if XmHeader::is_xm(buf) {
-        //     AudioType::Xm
-        } else if is_mp3(buf) {
+///
+/// MPEG audio frame without the ID3 tag: 11 sync bits followed by a valid version and layer,
+/// so both `FF FB` of MPEG-1 and `FF F3`/`FF F2` of MPEG-2 are recognized.
+///
+fn is_mpeg_frame(buf: &[u8]) -> bool {
+    const VERSION_RESERVED: u8 = 0b01;
+    const LAYER_RESERVED: u8 = 0b00;
+
+    let [0xFF, header, ..] = buf else {
+        return false;
+    };
+
+    (header & 0xE0 == 0xE0) && ((header >> 3) & 0b11 != VERSION_RESERVED) && ((header >> 1) & 0b11 != LAYER_RESERVED)
+}
+
+///
+/// The 4-byte signature at offset 1080 of Amiga modules, it tells the number of channels.
+///
+fn is_mod(buf: &[u8]) -> bool {
+    const TAGS: [&[u8]; 6] = [b"M.K.", b"M!K!", b"FLT4", b"FLT8", b"CD81", b"OKTA"];
+
+    let Some(tag) = buf.get(1080..1084) else {
+        return false;
+    };
+
+    TAGS.contains(&tag)
+        || (tag[0].is_ascii_digit() && &tag[1..] == b"CHN")      // 6CHN, 8CHN
+        || (tag[..2].iter().all(u8::is_ascii_digit) && &tag[2..] == b"CH") // 16CH, 32CH
+}
+
+///
+/// `Name:d=4,o=5,b=63:notes` where the defaults section consists only of `key=value` pairs.
+/// Any text with two colons would pass with the empty defaults, so then the first note must be valid.
+///
+fn is_rtttl(buf: &[u8]) -> bool {
+    // The head may cut a multibyte character:
+    let text = String::from_utf8_lossy(&buf[..buf.len().min(256)]);
+
+    let mut sections = text.trim_start().splitn(3, ':');
+    let (Some(name), Some(defaults), Some(notes)) = (sections.next(), sections.next(), sections.next()) else {
+        return false;
+    };
+
+    let entries = defaults.split(',').filter(|entry| !entry.trim().is_empty()).collect::<Vec<&str>>();
+    let are_defaults_valid = entries.iter().all(|entry| entry
+        .split_once('=')
+        .is_some_and(|(key, value)| {
+            let value = value.trim();
+            matches!(key.trim(), "d" | "o" | "b") && !value.is_empty() && value.chars().all(|ch| ch.is_ascii_digit())
+        })
+    );
+
+    !name.contains(char::is_control)
+        && are_defaults_valid
+        && (!entries.is_empty() || notes.split(',').next().is_some_and(rtttl::is_note))
+}
+
+fn classify_contents(buf: &[u8]) -> Option<(AudioType, Confidence)> {
+    let magic = if wav_header::is_wav(buf) {
+        AudioType::Wav
+    } else if has_at(buf, 0, b"RIFF") && has_at(buf, 8, b"RMID") || has_at(buf, 0, b"MThd") {
+        AudioType::Midi
+    } else if has_at(buf, 0, b"ID3") {
+        AudioType::Mp3
+    } else if has_at(buf, 0, b"fLaC") {
+        AudioType::Flac
+    } else if has_at(buf, 0, b"OggS") {
+        AudioType::Ogg
+    } else if has_at(buf, 0, b"FORM") && (has_at(buf, 8, b"AIFF") || has_at(buf, 8, b"AIFC")) {
+        AudioType::Aiff
+    } else if has_at(buf, 0, b"Extended Module: ") {
+        AudioType::Xm
+    } else if has_at(buf, 44, b"SCRM") {
+        AudioType::S3m
+    } else if has_at(buf, 0, b"Vgm ") {
+        AudioType::Vgm
+    } else if is_synth(buf) {
+        AudioType::Synth
+    } else {
+        let guess = if is_mod(buf) {
+            AudioType::Mod
+        } else if is_mpeg_frame(buf) {
             AudioType::Mp3
-        } else if is_synth(buf) {
-            AudioType::Synth
+        } else if is_rtttl(buf) {
+            AudioType::Rtttl
         } else {
-            AudioType::Unknown
+            return None;
+        };
+
+        return Some((guess, Confidence::Guess));
+    };
+
+    Some((magic, Confidence::Magic))
+}
+
+fn classify_extension(extension: &str) -> AudioType {
+    match extension.to_ascii_lowercase().as_str() {
+        "wav" | "wave" | "rf64" => AudioType::Wav,
+        "mp3" | "mp2" => AudioType::Mp3,
+        "flac" => AudioType::Flac,
+        "ogg" | "oga" | "opus" => AudioType::Ogg,
+        "aif" | "aiff" | "aifc" => AudioType::Aiff,
+        "mid" | "midi" | "rmi" => AudioType::Midi,
+        "xm" => AudioType::Xm,
+        "mod" => AudioType::Mod,
+        "s3m" => AudioType::S3m,
+        "vgm" => AudioType::Vgm,
+        "rtttl" | "rtx" => AudioType::Rtttl,
+        "synth" => AudioType::Synth,
+        _ => AudioType::Unknown
+    }
+}
+
+impl AudioType {
+    ///
+    /// Detects the type by the magic bytes of the contents.
+    /// The extension is used as a tiebreaker when the contents are ambiguous
+    /// (frame sync of MP3, signatures of MOD and RTTTL can occur by chance) or not recognized.
+    ///
+    #[must_use]
+    pub fn classify(buf: &[u8], extension: Option<&str>) -> Self {
+        let by_extension = extension.map_or(AudioType::Unknown, classify_extension);
+
+        match classify_contents(buf) {
+            Some((audio_type, Confidence::Magic)) => audio_type,
+            Some((audio_type, Confidence::Guess)) => if by_extension == AudioType::Unknown {
+                audio_type
+            } else {
+                by_extension
+            },
+            None => by_extension
         }
     }
-}
\ No newline at end of file
+
+    ///
+    /// Whether the type is a sequence of events for a synthesizer rather than a recording,
+    /// neither the decoder nor ffmpeg is able to render it.
+    ///
+    #[must_use]
+    pub fn is_sequence(self) -> bool {
+        matches!(self, AudioType::Midi | AudioType::Vgm)
+    }
+
+    ///
+    /// Whether the type is decoded in-process with the converter as a fallback.
+    ///
+    #[must_use]
+    pub fn is_decodable(self) -> bool {
+        matches!(self, AudioType::Mp3 | AudioType::Flac | AudioType::Ogg | AudioType::Aiff | AudioType::Unknown)
+    }
+}
+
+
+
+#[test]
+fn test() {
+    // Too short buffers don't panic:
+    assert_eq!(AudioType::classify(&[], None), AudioType::Unknown);
+    assert_eq!(AudioType::classify(&[0xFF], None), AudioType::Unknown);
+    assert_eq!(AudioType::classify(&[], Some("xm")), AudioType::Xm);
+
+    // All MPEG frame headers:
+    assert_eq!(AudioType::classify(&[0xFF, 0xFB, 0x90, 0x00], None), AudioType::Mp3);
+    assert_eq!(AudioType::classify(&[0xFF, 0xF3, 0x90, 0x00], None), AudioType::Mp3);
+    assert_eq!(AudioType::classify(&[0xFF, 0xF2, 0x90, 0x00], None), AudioType::Mp3);
+    assert_eq!(AudioType::classify(&[0xFF, 0xE9, 0x90, 0x00], None), AudioType::Unknown); // Reserved layer
+    assert_eq!(AudioType::classify(b"ID3\x04", Some("ogg")), AudioType::Mp3);
+
+    assert_eq!(AudioType::classify(b"fLaC\0\0\0\x22", None), AudioType::Flac);
+    assert_eq!(AudioType::classify(b"OggS\0\x02", None), AudioType::Ogg);
+    assert_eq!(AudioType::classify(b"FORM\0\0\0\0AIFC", None), AudioType::Aiff);
+    assert_eq!(AudioType::classify(b"MThd\0\0\0\x06", None), AudioType::Midi);
+    assert_eq!(AudioType::classify(b"RIFF\0\0\0\0RMIDdata", None), AudioType::Midi);
+    assert_eq!(AudioType::classify(b"Extended Module: Song", None), AudioType::Xm);
+    assert_eq!(AudioType::classify(b"Vgm \0\0", None), AudioType::Vgm);
+    assert_eq!(AudioType::classify(b"#!/bin/beesynth\n", None), AudioType::Synth);
+
+    let mut s3m = vec![0; 48];
+    s3m[44..].copy_from_slice(b"SCRM");
+    assert_eq!(AudioType::classify(&s3m, None), AudioType::S3m);
+
+    let mut module = vec![0; 1084];
+    module[1080..].copy_from_slice(b"M.K.");
+    assert_eq!(AudioType::classify(&module, None), AudioType::Mod);
+    module[1080..].copy_from_slice(b"16CH");
+    assert_eq!(AudioType::classify(&module, Some("mod")), AudioType::Mod);
+
+    assert_eq!(AudioType::classify(b"Nokia:d=4,o=5,b=180:8e6,8d6,f#,g#", None), AudioType::Rtttl);
+    assert_eq!(AudioType::classify(b"Nokia::8e6", None), AudioType::Rtttl);
+    assert_eq!(AudioType::classify(b"Text: some: words", None), AudioType::Unknown);
+    assert_eq!(AudioType::classify(b"Note:: see the words", None), AudioType::Unknown);
+    assert_eq!(AudioType::classify(b"Note:d=:8e6", None), AudioType::Unknown);
+    assert_eq!(AudioType::classify(b"Nokia: :16a#.6,c", None), AudioType::Rtttl);
+
+    // The extension overrules guesses but not the magic:
+    assert_eq!(AudioType::classify(&[0xFF, 0xFB, 0x90, 0x00], Some("S3M")), AudioType::S3m);
+    assert_eq!(AudioType::classify(b"fLaC\0\0\0\x22", Some("mp3")), AudioType::Flac);
+
+    // Only the sequences need a synthesizer:
+    assert!(AudioType::Midi.is_sequence() && AudioType::Vgm.is_sequence());
+    assert!(!AudioType::Xm.is_sequence() && !AudioType::Unknown.is_sequence());
+}
@@ -1,31 +1,40 @@
 use std::{
     fs,
     env,
-    path::PathBuf,
+    ffi::OsStr,
+    path::{Path, PathBuf},
     hash::Hasher,
-    fmt::Debug
+    fmt::Debug,
+    time::SystemTime
 };
 
 #[derive(Debug)]
 pub enum Error {
     AbsentFFmpeg,
-    GetExePath(std::io::Error),
-    AbsentRootFolder,
-    CreateCacheFolder(std::io::Error),
+    AbsentCacheFolder,
+    CreateCacheFolder(PathBuf, std::io::Error),
+    ListCache(PathBuf, std::io::Error),
     Read(std::io::Error),
     RunFFmpeg(std::io::Error),
+    Rename(PathBuf, std::io::Error),
     Conversion(String, String)
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::AbsentFFmpeg => write!(f, "FFmpeg executable not found in ./assets/ffmpeg/ffmpeg.exe"),
-            Error::GetExePath(ref err) => write!(f, "Unable to get the current executable path: {err}"),
-            Error::AbsentRootFolder => write!(f, "Absent root folder"),
-            Error::CreateCacheFolder(ref err) => write!(f, "Unable to create the cache folder ./assets/cache/: {err}"),
+            Error::AbsentFFmpeg => write!(
+                f,
+                "FFmpeg executable not found: set it using --ffmpeg=<path>, the {FFMPEG_ENV} environment variable \
+                 or the \"{FFMPEG_KEY} = <path>\" line of the config file, put it to ./assets/ffmpeg/ next to the executable \
+                 or add it to PATH"
+            ),
+            Error::AbsentCacheFolder => write!(f, "Unable to determine the cache folder, set it using the {CACHE_ENV} environment variable"),
+            Error::CreateCacheFolder(ref path, ref err) => write!(f, "Unable to create the cache folder {}: {err}", path.display()),
+            Error::ListCache(ref path, ref err) => write!(f, "Unable to list the cache folder {}: {err}", path.display()),
             Error::Read(ref err) => write!(f, "Unable to read the given file: {err}"),
             Error::RunFFmpeg(ref err) => write!(f, "Unable to run FFmpeg: {err}"),
+            Error::Rename(ref path, ref err) => write!(f, "Unable to move the converted file to {}: {err}", path.display()),
             Error::Conversion(ref stdout, ref stderr) => write!(f, "Conversion failure:\nStdOut:\n{stdout}\nStdErr:\n{stderr}"),
         }
     }
@@ -34,15 +43,42 @@ impl std::fmt::Display for Error {
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            Error::GetExePath(ref err)        |
-            Error::CreateCacheFolder(ref err) |
-            Error::Read(ref err)              |
+            Error::CreateCacheFolder(_, ref err) |
+            Error::ListCache(_, ref err)         |
+            Error::Rename(_, ref err)            |
+            Error::Read(ref err)                 |
             Error::RunFFmpeg(ref err) => Some(err),
             _ => None
         }
     }
 }
 
+const FFMPEG_ENV: &str = "BEESYNTH_FFMPEG"; // Path to the FFmpeg executable
+const CACHE_ENV: &str = "BEESYNTH_CACHE_DIR"; // Folder for converted files
+const FFMPEG_KEY: &str = "ffmpeg"; // Path to the FFmpeg executable in the config file
+
+const FFMPEG_NAME: &str = if cfg!(target_os = "windows") { "ffmpeg.exe" } else { "ffmpeg" };
+const CACHED_EXTENSION: &str = "wav";
+const TEMP_EXTENSION: &str = "tmp";
+
+pub struct Settings {
+    pub ffmpeg_path: Option<PathBuf>, // Overrides the environment variable and the lookup
+    pub sample_rate: Option<u32>,     // The original rate is kept if None
+    pub cache_limit: u64              // In bytes, the least recently used files are evicted above it
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self { ffmpeg_path: None, sample_rate: None, cache_limit: 1024 * 1024 * 1024 }
+    }
+}
+
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub last_used: SystemTime
+}
+
 
 
 fn calc_hash(data: &[u8]) -> u64 {
@@ -51,41 +87,199 @@ fn calc_hash(data: &[u8]) -> u64 {
     hasher.finish()
 }
 
+///
+/// Platform config file: `$XDG_CONFIG_HOME/beesynth/config` or `~/.config/beesynth/config` on Linux,
+/// `%APPDATA%\beesynth\config` on Windows and `~/Library/Application Support/beesynth/config` on macOS.
+///
+fn config_path() -> Option<PathBuf> {
+    let non_empty = |name: &str| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+
+    let base = if cfg!(target_os = "windows") {
+        non_empty("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        non_empty("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        non_empty("XDG_CONFIG_HOME").or_else(|| non_empty("HOME").map(|home| home.join(".config")))
+    };
+
+    base.map(|base| base.join("beesynth").join("config"))
+}
+
+///
+/// Value of the `key = value` line of the config, `#` starts a comment.
+///
+fn config_value(config: &str, key: &str) -> Option<String> {
+    config
+        .lines()
+        .map(|line| line.split_once('#').map_or(line, |(line, _)| line))
+        .filter_map(|line| line.split_once('='))
+        .find(|(name, _)| name.trim() == key)
+        .map(|(_, value)| value.trim().to_string())
+}
 
+///
+/// Picks `FFmpeg` among the candidates: the first configured path is the only one taken,
+/// otherwise the bundled executable and the `FFmpeg` in the folders of the search path.
+///
+fn locate_ffmpeg(configured: [Option<PathBuf>; 3], bundled: Option<PathBuf>, search_path: Option<&OsStr>) -> Option<PathBuf> {
+    if let Some(path) = configured.into_iter().flatten().next() {
+        return path.is_file().then_some(path);
+    }
 
-pub fn convert_to_wav(
-    file_path: &std::path::Path,
-    bitness: u8,
-    sample_rate: u32) -> Result<PathBuf, Error>
-{
-    let assets_folder = env::current_exe().map_err(Error::GetExePath)?
-        .parent().ok_or(Error::AbsentRootFolder)?
-        .join("assets");
+    bundled.filter(|path| path.is_file()).or_else(|| env::split_paths(search_path?)
+        .map(|folder| folder.join(FFMPEG_NAME))
+        .find(|path| path.is_file())
+    )
+}
+
+///
+/// Looks for `FFmpeg` in the following order: the explicit path, the environment variable,
+/// the config setting, the bundled `./assets/ffmpeg/` next to the executable and finally `PATH`.
+///
+fn find_ffmpeg(explicit_path: Option<&Path>) -> Option<PathBuf> {
+    let configured = config_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|config| config_value(&config, FFMPEG_KEY))
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from);
+
+    let bundled = env::current_exe()
+        .ok()
+        .and_then(|exe_path| exe_path.parent().map(|folder| folder.join("assets").join("ffmpeg").join(FFMPEG_NAME)));
+
+    locate_ffmpeg(
+        [explicit_path.map(Path::to_path_buf), env::var_os(FFMPEG_ENV).map(PathBuf::from), configured],
+        bundled,
+        env::var_os("PATH").as_deref()
+    )
+}
+
+///
+/// Platform cache folder: `$XDG_CACHE_HOME` or `~/.cache` on Linux,
+/// `%LOCALAPPDATA%` on Windows and `~/Library/Caches` on macOS.
+///
+pub fn cache_folder() -> Result<PathBuf, Error> {
+    if let Some(path) = env::var_os(CACHE_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let non_empty = |name: &str| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+
+    let base = if cfg!(target_os = "windows") {
+        non_empty("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        non_empty("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        non_empty("XDG_CACHE_HOME").or_else(|| non_empty("HOME").map(|home| home.join(".cache")))
+    };
+
+    base.map(|base| base.join("beesynth")).ok_or(Error::AbsentCacheFolder)
+}
+
+///
+/// Converted files of the cache, the least recently used first.
+///
+pub fn list_cache() -> Result<Vec<CacheEntry>, Error> {
+    let folder = cache_folder()?;
+    if !folder.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = fs::read_dir(&folder)
+        .map_err(|err| Error::ListCache(folder.clone(), err))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == CACHED_EXTENSION))
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            let last_used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some(CacheEntry { path, size: metadata.len(), last_used })
+        })
+        .collect::<Vec<CacheEntry>>();
+
+    entries.sort_by_key(|entry| entry.last_used);
+    Ok(entries)
+}
+
+///
+/// Removes all converted files and leftovers of interrupted conversions.
+/// Returns the number of removed files.
+///
+pub fn clear_cache() -> Result<usize, Error> {
+    let folder = cache_folder()?;
+    if !folder.exists() {
+        return Ok(0);
+    }
+
+    let removed = fs::read_dir(&folder)
+        .map_err(|err| Error::ListCache(folder.clone(), err))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == CACHED_EXTENSION || extension == TEMP_EXTENSION))
+        .filter(|path| fs::remove_file(path).is_ok())
+        .count();
+
+    Ok(removed)
+}
+
+///
+/// Evicts the least recently used files until the cache fits the limit.
+/// The file being used right now is never evicted.
+///
+fn evict(limit: u64, keep: &Path) -> Result<(), Error> {
+    let entries = list_cache()?;
+    let mut total_size = entries.iter().map(|entry| entry.size).sum::<u64>();
+
+    for entry in entries {
+        if total_size <= limit {
+            break;
+        }
+
+        // Another invocation may have removed it already:
+        if entry.path != keep && fs::remove_file(&entry.path).is_ok() {
+            total_size -= entry.size;
+        }
+    }
 
-    if !assets_folder.exists() {
-        return Err(Error::AbsentFFmpeg);
+    Ok(())
+}
+
+///
+/// Marks the cached file as recently used, the modification time is used
+/// as the access time is often disabled by the file system.
+///
+fn touch(path: &Path) {
+    if let Ok(file) = fs::File::options().write(true).open(path) {
+        let _result = file.set_modified(SystemTime::now());
     }
+}
 
-    let cache_folder = assets_folder.join("cache");
+pub fn convert_to_wav(
+    file_path: &Path,
+    bitness: u8,
+    settings: &Settings) -> Result<PathBuf, Error>
+{
+    let cache_folder = cache_folder()?;
     if !cache_folder.exists() {
         fs::DirBuilder::new()
             .recursive(true)
             .create(&cache_folder)
-            .map_err(Error::CreateCacheFolder)?;
+            .map_err(|err| Error::CreateCacheFolder(cache_folder.clone(), err))?;
     }
 
     let file = fs::read(file_path).map_err(Error::Read)?;
     let hash = calc_hash(&file);
 
-    let cached_path = cache_folder.join(format!("{hash}_{bitness}_{sample_rate}.wav"));
+    // All channels are kept to be mixed down the same way as native WAV files:
+    let rate_name = settings.sample_rate.map_or_else(|| String::from("native"), |sample_rate| sample_rate.to_string());
+    let file_name = format!("{hash}_{bitness}_{rate_name}_multichannel");
+    let cached_path = cache_folder.join(format!("{file_name}.{CACHED_EXTENSION}"));
     if cached_path.exists() {
+        touch(&cached_path);
         return Ok(cached_path);
     }
 
-    let ffmpeg_path = assets_folder.join("ffmpeg").join("ffmpeg.exe");
-    if !ffmpeg_path.exists() {
-        return Err(Error::AbsentFFmpeg);
-    }
+    let ffmpeg_path = find_ffmpeg(settings.ffmpeg_path.as_deref()).ok_or(Error::AbsentFFmpeg)?;
 
     let encoder_name = match bitness {
         8  => "pcm_u8",
@@ -98,22 +292,131 @@ pub fn convert_to_wav(
         )
     };
 
-    let ffmpeg_process = std::process::Command::new(ffmpeg_path)
+    // Concurrent conversions of the same file write to their own temporary files,
+    // the complete one replaces the cached file atomically:
+    let temp_path = cache_folder.join(format!("{file_name}.{}.{TEMP_EXTENSION}", std::process::id()));
+
+    let mut ffmpeg_command = std::process::Command::new(ffmpeg_path);
+    ffmpeg_command
+        .arg("-y")
         .arg("-i").arg(file_path)
-        .arg("-acodec").arg(encoder_name)
-        .arg("-ac").arg("1")
-        .arg("-ar").arg(sample_rate.to_string())
-        .arg(&cached_path)
+        .arg("-acodec").arg(encoder_name);
+
+    // Keep the original rate if not specified:
+    if let Some(sample_rate) = settings.sample_rate {
+        ffmpeg_command.arg("-ar").arg(sample_rate.to_string());
+    }
+
+    let ffmpeg_process = ffmpeg_command
+        .arg("-f").arg("wav") // The temporary extension doesn't tell the format
+        .arg(&temp_path)
         .output()
         .map_err(Error::RunFFmpeg)?
         ;
 
-    if !cached_path.exists() {
+    if !ffmpeg_process.status.success() || !temp_path.exists() {
+        let _result = fs::remove_file(&temp_path);
         return Err(Error::Conversion(
             String::from_utf8_lossy(&ffmpeg_process.stdout).to_string(),
             String::from_utf8_lossy(&ffmpeg_process.stderr).to_string())
         );
     }
 
+    if let Err(err) = fs::rename(&temp_path, &cached_path) {
+        let _result = fs::remove_file(&temp_path);
+
+        // Windows refuses to replace the file which is being read by another invocation,
+        // it has the same contents anyway:
+        if !cached_path.exists() {
+            return Err(Error::Rename(cached_path, err));
+        }
+    }
+
+    evict(settings.cache_limit, &cached_path)?;
+
     Ok(cached_path)
-}
\ No newline at end of file
+}
+
+
+
+#[test]
+fn test() {
+    let folder = env::temp_dir().join(format!("beesynth-converter-{}", std::process::id()));
+    let cache = folder.join("cache");
+    fs::create_dir_all(&cache).unwrap();
+    env::set_var(CACHE_ENV, &cache);
+
+    // The least recently used files go first, the one in use stays even if it's the oldest:
+    let now = SystemTime::now();
+    let cached = |name: &str, age_secs: u64| {
+        let path = cache.join(format!("{name}.{CACHED_EXTENSION}"));
+        fs::write(&path, [0; 10]).unwrap();
+        fs::File::options().write(true).open(&path).unwrap().set_modified(now - std::time::Duration::from_secs(age_secs)).unwrap();
+        path
+    };
+    let remaining = || list_cache().unwrap().into_iter().map(|entry| entry.path).collect::<Vec<PathBuf>>();
+    let (old, middle, new) = (cached("old", 30), cached("middle", 20), cached("new", 10));
+    evict(25, &new).unwrap();
+    assert_eq!(remaining(), [middle.clone(), new.clone()]);
+    evict(5, &middle).unwrap();
+    assert_eq!(remaining(), [middle]);
+    assert!(!old.exists() && !new.exists());
+
+    // The explicit path wins even if it's absent, then the environment variable and the config setting,
+    // the bundled executable is preferred to the search path:
+    let ffmpeg = |name: &str| {
+        let path = folder.join(name).join(FFMPEG_NAME);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, []).unwrap();
+        path
+    };
+    let (explicit, environment, configured, bundled, found) = (ffmpeg("explicit"), ffmpeg("env"), ffmpeg("config"), ffmpeg("bundled"), ffmpeg("path"));
+    let search_path = env::join_paths([folder.join("absent"), folder.join("path")]).unwrap();
+    let locate = |configured: [Option<&PathBuf>; 3], bundled: Option<&PathBuf>| {
+        locate_ffmpeg(configured.map(Option::<&PathBuf>::cloned), bundled.cloned(), Some(&search_path))
+    };
+    assert_eq!(locate([Some(&explicit), Some(&environment), Some(&configured)], Some(&bundled)), Some(explicit));
+    assert_eq!(locate([Some(&folder.join("absent")), Some(&environment), None], Some(&bundled)), None);
+    assert_eq!(locate([None, Some(&environment), Some(&configured)], Some(&bundled)), Some(environment));
+    assert_eq!(locate([None, None, Some(&configured)], Some(&bundled)), Some(configured));
+    assert_eq!(locate([None, None, None], Some(&bundled)), Some(bundled));
+    assert_eq!(locate([None, None, None], Some(&folder.join("absent"))), Some(found.clone()));
+    assert_eq!(locate([None, None, None], None), Some(found));
+    assert_eq!(config_value("# ffmpeg = /commented\nffmpeg = /usr/bin/ffmpeg # system\n", FFMPEG_KEY).as_deref(), Some("/usr/bin/ffmpeg"));
+
+    // FFmpeg writes the temporary file which is renamed to the cached one, the failed conversion leaves nothing:
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let script = |name: &str, body: &str| {
+            let path = folder.join(name);
+            fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+        let succeeding = script("succeeding", r#"for last; do :; done; case "$last" in *.tmp) printf RIFF > "$last";; *) exit 1;; esac"#);
+        let failing = script("failing", r#"for last; do :; done; printf RIFF > "$last"; exit 1"#);
+        let source = folder.join("source.mid");
+        let settings = |ffmpeg: &PathBuf| Settings { ffmpeg_path: Some(ffmpeg.clone()), sample_rate: None, cache_limit: u64::MAX };
+        let temp_files = || fs::read_dir(&cache).unwrap().filter(|entry| {
+            entry.as_ref().unwrap().path().extension().is_some_and(|extension| extension == TEMP_EXTENSION)
+        }).count();
+
+        fs::write(&source, b"failing").unwrap();
+        assert!(matches!(convert_to_wav(&source, 16, &settings(&failing)), Err(Error::Conversion(..))));
+        assert_eq!(temp_files(), 0);
+
+        fs::write(&source, b"succeeding").unwrap();
+        let converted = convert_to_wav(&source, 16, &settings(&succeeding)).unwrap();
+        assert_eq!(converted, cache.join(format!("{}_16_native_multichannel.{CACHED_EXTENSION}", calc_hash(b"succeeding"))));
+        assert_eq!(fs::read(&converted).unwrap(), b"RIFF");
+        assert_eq!(temp_files(), 0);
+
+        // The cached file is reused without running FFmpeg:
+        assert_eq!(convert_to_wav(&source, 16, &settings(&failing)).unwrap(), converted);
+    }
+
+    env::remove_var(CACHE_ENV);
+    fs::remove_dir_all(&folder).unwrap();
+}
@@ -0,0 +1,454 @@
+//!
+//! In-process decoding of compressed formats (MP3, FLAC, Ogg Vorbis and Opus)
+//! straight into amplitude chunks without the intermediate WAV file.
+//!
+
+use std::{fs::File, path::Path};
+
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{CodecRegistry, DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error as SymphoniaError,
+    formats::{FormatOptions, FormatReader},
+    io::{MediaSourceStream, MediaSourceStreamOptions},
+    meta::MetadataOptions,
+    probe::Hint
+};
+
+use crate::wave::{filter::{Data, WaveData}, wav_extractor::Downmix};
+
+#[derive(Debug)]
+pub enum Error {
+    Open(std::io::Error),
+    Probe(SymphoniaError),
+    AbsentTrack,
+    UnknownSampleRate,
+    UnsupportedCodec(SymphoniaError)
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Open(ref err) => write!(f, "Unable to open the given file: {err}"),
+            Error::Probe(ref err) => write!(f, "Unknown container format: {err}"),
+            Error::AbsentTrack => write!(f, "There are no audio tracks"),
+            Error::UnknownSampleRate => write!(f, "The sample rate of the track is unknown"),
+            Error::UnsupportedCodec(ref err) => write!(f, "Unsupported codec: {err}")
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Open(ref err) => Some(err),
+            Error::Probe(ref err) | Error::UnsupportedCodec(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+fn codecs() -> &'static CodecRegistry {
+    static CODECS: std::sync::OnceLock<CodecRegistry> = std::sync::OnceLock::new();
+    CODECS.get_or_init(|| {
+        let mut registry = CodecRegistry::new();
+        symphonia::default::register_enabled_codecs(&mut registry);
+        #[cfg(feature = "opus")]
+        registry.register_all::<opus::OpusDecoder>();
+        registry
+    })
+}
+
+///
+/// Decodes the first audio track of the file lazily
+/// yielding chunks of at least `frames_per_chunk` frames.
+///
+pub struct Decoder {
+    format: Box<dyn FormatReader>,
+    codec: Box<dyn symphonia::core::codecs::Decoder>,
+    track_id: u32,
+    sample_rate: u32,
+    num_channels: Option<u16>, // May be unknown before the first packet is decoded
+    frame_count: Option<u64>,  // Not every container tells it
+    downmix: Downmix,
+    frames_per_chunk: usize,
+    is_finished: bool
+}
+
+impl Decoder {
+    pub fn open(path: &Path, frames_per_chunk: usize) -> Result<Self, Error> {
+        let file = File::open(path).map_err(Error::Open)?;
+        let stream = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
+
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            hint.with_extension(extension);
+        }
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+            .map_err(Error::Probe)?;
+
+        let format = probed.format;
+        let track = format.tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(Error::AbsentTrack)?;
+
+        let track_id = track.id;
+        let sample_rate = track.codec_params.sample_rate.ok_or(Error::UnknownSampleRate)?;
+        let num_channels = track.codec_params.channels.and_then(|channels| u16::try_from(channels.count()).ok());
+        let frame_count = track.codec_params.n_frames;
+
+        let codec = codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(Error::UnsupportedCodec)?;
+
+        Ok(Self {
+            format,
+            codec,
+            track_id,
+            sample_rate,
+            num_channels,
+            frame_count,
+            downmix: Downmix::default(),
+            frames_per_chunk: frames_per_chunk.max(1),
+            is_finished: false
+        })
+    }
+
+    #[must_use]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[must_use]
+    pub fn num_channels(&self) -> Option<u16> {
+        self.num_channels
+    }
+
+    #[must_use]
+    pub fn frame_count(&self) -> Option<u64> {
+        self.frame_count
+    }
+
+    pub fn set_downmix(&mut self, downmix: Downmix) {
+        self.downmix = downmix;
+    }
+
+    ///
+    /// Decodes the next packet of the track appending its mixed samples.
+    /// Returns false at the end of the stream or on unrecoverable errors.
+    ///
+    fn decode_packet(&mut self, samples: &mut Vec<f32>) -> bool {
+        let packet = match self.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(ref err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => return false,
+            Err(err) => {
+                eprintln!("Unable to read the next packet: {err}");
+                return false;
+            }
+        };
+
+        if packet.track_id() != self.track_id {
+            return true;
+        }
+
+        let decoded = match self.codec.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Corrupted packets are skipped:
+            Err(SymphoniaError::DecodeError(_)) => return true,
+            Err(err) => {
+                eprintln!("Unable to decode the packet: {err}");
+                return false;
+            }
+        };
+
+        let spec = *decoded.spec();
+        let num_channels = spec.channels.count();
+        if num_channels == 0 || decoded.frames() == 0 {
+            return true;
+        }
+
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+
+        let downmix = self.downmix;
+        samples.extend(
+            buffer.samples()
+                .chunks_exact(num_channels)
+                .map(|frame| downmix.mix(num_channels, |index| frame.get(index).copied().unwrap_or_default()))
+        );
+
+        true
+    }
+}
+
+impl Iterator for Decoder {
+    type Item = Data;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished {
+            return None;
+        }
+
+        let mut samples = Vec::with_capacity(self.frames_per_chunk);
+        while samples.len() < self.frames_per_chunk {
+            if !self.decode_packet(&mut samples) {
+                self.is_finished = true;
+                break;
+            }
+        }
+
+        if samples.is_empty() {
+            return None;
+        }
+
+        Some(Data::Amplitude(WaveData { samples, sample_rate: self.sample_rate }))
+    }
+}
+
+
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn test() {
+    // Stereo FLAC of two 32-frame blocks stored verbatim: the left channel is 0.5, the right one is -0.25:
+    const SAMPLE_RATE: u32 = 8000;
+    const BLOCK_SIZE: u16 = 32;
+
+    fn crc(bytes: &[u8], width: u32, poly: u32) -> u32 {
+        let top = 1 << (width - 1);
+        let mask = u32::MAX >> (32 - width);
+        bytes.iter().fold(0, |crc, byte| (0..8).fold(crc ^ (u32::from(*byte) << (width - 8)), |crc, _| {
+            if crc & top == 0 { (crc << 1) & mask } else { ((crc << 1) ^ poly) & mask }
+        }))
+    }
+
+    type Decoded = ((u32, Option<u16>, Option<u64>), Vec<Vec<f32>>);
+
+    fn decode(extension: &str, bytes: &[u8], downmix: Downmix) -> Decoded {
+        let path = std::env::temp_dir().join(format!("beesynth-decoder-{}.{extension}", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let decoded = Decoder::open(&path, 48).map(|mut decoder| {
+            let params = (decoder.sample_rate(), decoder.num_channels(), decoder.frame_count());
+            decoder.set_downmix(downmix);
+            (params, decoder.collect::<Vec<Data>>())
+        });
+        std::fs::remove_file(&path).unwrap();
+
+        let Ok((params, chunks)) = decoded else {
+            panic!("Unable to decode the {extension} fixture");
+        };
+
+        let samples = chunks.into_iter().map(|chunk| match chunk {
+            Data::Amplitude(wave) if wave.sample_rate == params.0 => wave.samples,
+            _ => panic!("Unexpected data")
+        }).collect();
+
+        (params, samples)
+    }
+
+    let mut flac = b"fLaC".to_vec();
+    flac.extend([0x80, 0, 0, 34]); // The last block, STREAMINFO of 34 bytes
+    flac.extend(BLOCK_SIZE.to_be_bytes());
+    flac.extend(BLOCK_SIZE.to_be_bytes());
+    flac.extend([0; 6]); // Unknown frame sizes
+    let info = (u64::from(SAMPLE_RATE) << 44) | (1 << 41) | (15 << 36) | u64::from(BLOCK_SIZE * 2); // 2 channels of 16 bits
+    flac.extend(info.to_be_bytes());
+    flac.extend([0; 16]); // No MD5
+
+    for frame in 0..2 {
+        // Fixed block size, 8-bit size at the end, the rate of STREAMINFO, independent channels of 16 bits:
+        let start = flac.len();
+        flac.extend([0xFF, 0xF8, 0x60, 0x18, frame, u8::try_from(BLOCK_SIZE - 1).unwrap()]);
+        flac.push(u8::try_from(crc(&flac[start..], 8, 0x07)).unwrap());
+        for sample in [16384_i16, -8192] {
+            flac.push(0x02); // Verbatim subframe
+            (0..BLOCK_SIZE).for_each(|_| flac.extend(sample.to_be_bytes()));
+        }
+        flac.extend(u16::try_from(crc(&flac[start..], 16, 0x8005)).unwrap().to_be_bytes());
+    }
+
+    let (params, samples) = decode("flac", &flac, Downmix::Side);
+    assert_eq!(params, (SAMPLE_RATE, Some(2), Some(u64::from(BLOCK_SIZE * 2))));
+
+    // The chunks are at least 48 frames long but consist of whole packets, the last one is the rest:
+    assert_eq!(samples.iter().map(Vec::len).collect::<Vec<usize>>(), [64]);
+    assert!(samples.iter().flatten().all(|sample| (sample - 0.375).abs() < 1e-6));
+
+    // Silent mono MP3 of four MPEG-1 Layer III frames at 32 kbps and 32 kHz, each one is 144 bytes of 1152 samples.
+    // The side information is zero, so there is no bit reservoir and no spectral values:
+    let mp3 = (0..4).flat_map(|_| [[0xFF, 0xFB, 0x18, 0xC0].as_slice(), &[0; 140]].concat()).collect::<Vec<u8>>();
+
+    let (params, samples) = decode("mp3", &mp3, Downmix::Mono);
+    assert_eq!((params.0, params.1), (32000, Some(1)));
+    assert_eq!(samples.iter().map(Vec::len).collect::<Vec<usize>>(), [1152, 1152, 1152, 1152]);
+    assert!(samples.iter().flatten().all(|sample| *sample == 0.0));
+
+    // Silent mono Ogg Vorbis of short 64-sample blocks. The setup has a single codebook and unused floors,
+    // so each audio packet is one zero byte which adds the half of the block after the first one:
+    let bits = |fields: &[(u32, u32)]| -> Vec<u8> {
+        let bits = fields.iter().flat_map(|&(value, width)| (0..width).map(move |bit| (value >> bit) & 1 == 1)).collect::<Vec<bool>>();
+        bits.chunks(8).map(|byte| (0..).zip(byte).fold(0, |packed, (bit, is_set)| packed | (u8::from(*is_set) << bit))).collect()
+    };
+
+    let mut ident = b"\x01vorbis".to_vec();
+    ident.extend(0_u32.to_le_bytes()); // Version
+    ident.push(1); // Channels
+    ident.extend(SAMPLE_RATE.to_le_bytes());
+    ident.extend([0; 12]); // Unknown bitrates
+    ident.extend([0x66, 0x01]); // Both block sizes are 64, the framing bit
+
+    let mut comment = b"\x03vorbis".to_vec();
+    comment.extend([0; 8]); // No vendor and comments
+    comment.push(0x01);
+
+    let mut setup = b"\x05vorbis".to_vec();
+    setup.extend(bits(&[
+        (0, 8), (0x56_4342, 24), (1, 16), (2, 24), (0, 1), (0, 1), (0, 5), (0, 5), (0, 4), // A codebook of two 1-bit entries
+        (0, 6), (0, 16), // Time domain transform placeholder
+        (0, 6), (1, 16), (0, 5), (0, 2), (7, 4), // Floor 1 without partitions
+        (0, 6), (0, 16), (0, 24), (0, 24), (0, 24), (0, 6), (0, 8), (0, 3), (0, 1), // Empty residue 0
+        (0, 6), (0, 16), (0, 1), (0, 1), (0, 2), (0, 8), (0, 8), (0, 8), // Mapping of the channel to them
+        (0, 6), (0, 1), (0, 16), (0, 16), (0, 8), // Short block mode
+        (1, 1) // Framing bit
+    ]));
+
+    let page = |header_type: u8, granule: u64, sequence: u32, packets: &[&[u8]]| -> Vec<u8> {
+        let lacing = packets.iter().flat_map(|packet| {
+            let mut lacing = vec![255; packet.len() / 255];
+            lacing.push(u8::try_from(packet.len() % 255).unwrap());
+            lacing
+        }).collect::<Vec<u8>>();
+
+        let mut page = b"OggS".to_vec();
+        page.extend([0, header_type]);
+        page.extend(granule.to_le_bytes());
+        page.extend(1_u32.to_le_bytes()); // Serial number
+        page.extend(sequence.to_le_bytes());
+        page.extend([0; 4]); // CRC
+        page.push(u8::try_from(lacing.len()).unwrap());
+        page.extend(lacing);
+        page.extend(packets.concat());
+
+        let crc = crc(&page, 32, 0x04C1_1DB7);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    };
+
+    let ogg = [
+        page(0x02, 0, 0, &[&ident]), // The beginning of the stream
+        page(0x00, 0, 1, &[&comment, &setup]),
+        page(0x04, 4 * 32, 2, &[[0].as_slice(); 5]) // The end of the stream
+    ].concat();
+
+    let (params, samples) = decode("ogg", &ogg, Downmix::Mono);
+    assert_eq!((params.0, params.1), (SAMPLE_RATE, Some(1)));
+    assert_eq!(samples.iter().map(Vec::len).sum::<usize>(), 4 * 32);
+    assert!(samples.iter().flatten().all(|sample| *sample == 0.0));
+}
+
+
+
+#[cfg(feature = "opus")]
+mod opus {
+    use std::sync::Mutex;
+
+    use audiopus::{coder::{Decoder as OpusCoder, GenericCtl}, packet::Packet as OpusPacket, Channels, MutSignals, SampleRate};
+    use symphonia::core::{
+        audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Signal, SignalSpec},
+        codecs::{CodecDescriptor, CodecParameters, Decoder, DecoderOptions, FinalizeResult, CODEC_TYPE_OPUS},
+        errors::{decode_error, unsupported_error, Result},
+        formats::Packet,
+        support_codec
+    };
+
+    const SAMPLE_RATE: u32 = 48000; // Opus always decodes at 48 kHz
+    const MAX_FRAMES: usize = 5760; // 120 ms at 48 kHz, the longest Opus packet
+
+    ///
+    /// Adapter of libopus to the symphonia decoder interface, the Ogg demuxer
+    /// recognizes Opus streams but symphonia has no decoder for them.
+    ///
+    pub struct OpusDecoder {
+        params: CodecParameters,
+        coder: Mutex<OpusCoder>, // Symphonia requires decoders to be Sync
+        channels: Channels,
+        decoded: Vec<f32>,
+        buffer: AudioBuffer<f32>
+    }
+
+    impl Decoder for OpusDecoder {
+        fn try_new(params: &CodecParameters, _options: &DecoderOptions) -> Result<Self> {
+            let Some(layout) = params.channels else {
+                return unsupported_error("opus: unknown channel layout");
+            };
+
+            let channels = match layout.count() {
+                1 => Channels::Mono,
+                2 => Channels::Stereo,
+                _ => return unsupported_error("opus: only mono and stereo streams are supported")
+            };
+
+            let Ok(coder) = OpusCoder::new(SampleRate::Hz48000, channels) else {
+                return unsupported_error("opus: unable to create the decoder");
+            };
+
+            Ok(Self {
+                params: params.clone(),
+                coder: Mutex::new(coder),
+                channels,
+                decoded: vec![0.0; MAX_FRAMES * layout.count()],
+                buffer: AudioBuffer::new(MAX_FRAMES as u64, SignalSpec::new(SAMPLE_RATE, layout))
+            })
+        }
+
+        fn supported_codecs() -> &'static [CodecDescriptor] {
+            &[support_codec!(CODEC_TYPE_OPUS, "opus", "Opus")]
+        }
+
+        fn reset(&mut self) {
+            if let Ok(coder) = self.coder.get_mut() {
+                let _result = coder.reset_state();
+            }
+        }
+
+        fn codec_params(&self) -> &CodecParameters {
+            &self.params
+        }
+
+        fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+            let (Ok(coder), Ok(input), Ok(output)) = (
+                self.coder.get_mut(),
+                OpusPacket::try_from(packet.buf()),
+                MutSignals::try_from(self.decoded.as_mut_slice())
+            ) else {
+                return decode_error("opus: invalid packet");
+            };
+
+            let Ok(frames) = coder.decode_float(Some(input), output, false) else {
+                return decode_error("opus: invalid packet");
+            };
+
+            let num_channels = self.channels as usize;
+            self.buffer.clear();
+            self.buffer.render_reserved(Some(frames));
+            for channel in 0..num_channels {
+                for (frame, sample) in self.buffer.chan_mut(channel).iter_mut().enumerate() {
+                    *sample = self.decoded[frame * num_channels + channel];
+                }
+            }
+
+            Ok(self.buffer.as_audio_buffer_ref())
+        }
+
+        fn finalize(&mut self) -> FinalizeResult {
+            FinalizeResult::default()
+        }
+
+        fn last_decoded(&self) -> AudioBufferRef<'_> {
+            self.buffer.as_audio_buffer_ref()
+        }
+    }
+}
@@ -1,12 +1,23 @@
+#[allow(clippy::too_many_lines)]
 pub(crate) fn print_help() {
-    println!("Usage: {} [options] <file>\n", std::env::args().next().unwrap());
+    let executable = std::env::args().next().unwrap();
+    println!("Usage: {executable} [options] <file>");
+    println!("       {executable} cache <list|clear>\n");
     println!(
-r#"<file> can be either a synth file or WAV, MP3, FLAC, MIDI, XM"
-       or any other file which is convertible to WAV using ffmpeg.
+r#"<file> can be either a synth file, a RTTTL ring tone or WAV, MP3, FLAC,
+       Ogg, AIFF, XM, MOD, S3M or any other file which is convertible
+       to WAV using ffmpeg. The format is detected by the contents,
+       the extension is used only when they are ambiguous. MIDI and VGM
+       are rejected as they need a synthesizer to be rendered.
+       WAV, MP3, FLAC, AIFF and Ogg Vorbis are decoded in-process, ffmpeg is used
+       for the rest. Ogg Opus is decoded in-process only if built with the
+       experimental "opus" feature (cargo build --features opus), otherwise
+       it needs ffmpeg too.
        \"Synth\" file is a file with the following format:
        #!/bin/beesynth     # Shebang and required signature
        @bpm: 120           # Beats per minute, required
        @channels: ch1 ch2  # Active channels
+       @transpose: P5,fit  # Optional, see --transpose
        @ch1: !Q:E3   E:0    W:A4  # Notes of the channel 1
              ...
        @ch2: !E:F3b  Q:A3#
@@ -20,6 +31,20 @@ Options:
         Requires some time to initialize.
         Default is IOCTL-based I/O which doesn't require time
         to initialize but has a lot higher latency and less throughput.
+        On Linux raises IOPL using the iopl syscall instantly.
+
+    --port-access=<auto|ioperm|iopl|dev-port>
+        Linux only. The way to access I/O ports of the speaker
+        in the default (non --iopl) mode:
+            * auto - try ioperm, then iopl, then dev-port (default).
+            * ioperm - grant access to the ports 0x42, 0x43 and 0x61 only.
+            * iopl - raise IOPL to access all ports.
+            * dev-port - read and write ports through /dev/port.
+        All of them require root (CAP_SYS_RAWIO).
+
+    --dev-port=<path>
+        Linux only. Access I/O ports through the given file
+        instead of /dev/port, implies --port-access=dev-port.
 
     --switch-interval=<nsec>
         Channel switch interval in nanoseconds.
@@ -27,6 +52,47 @@ Options:
         Example: --switch-interval=35000000  # Switch the channel each 35 msec.
         Applicable for the frequency mode only, ignored otherwise.
 
+    --render=<path>
+        Don't touch the speaker, simulate it instead and save
+        its output to the given 16-bit 44100 Hz WAV file.
+        Works faster than realtime and doesn't require root
+        or a kernel driver.
+        Example: --render=preview.wav
+
+    --downmix=<mono|mid|side|left|right|index>
+        The way to mix the channels of multichannel files down to mono:
+            * mono - average of all channels (default).
+            * mid - (left + right) / 2, keeps the center:
+                    vocals and lead melody.
+            * side - (left - right) / 2, removes the center, stereo and up only.
+            * left, right - the given channel only.
+            * index - the channel with the given zero-based index.
+        Applicable for WAV and converted files only.
+        Example: --downmix=mid
+
+    --convert-rate=<value>
+        Sample rate in Hz of the WAV file produced by ffmpeg
+        for files which can't be decoded in-process.
+        The original rate is kept by default.
+        Example: --convert-rate=22050
+
+    --ffmpeg=<path>
+        Path to the ffmpeg executable used for files which can't be
+        decoded in-process. Otherwise it's taken from the BEESYNTH_FFMPEG
+        environment variable, the "ffmpeg = <path>" line of the config file,
+        ./assets/ffmpeg/ next to the executable or PATH, in this order.
+        The config file is $XDG_CONFIG_HOME/beesynth/config
+        (~/.config/beesynth/config) or %APPDATA%\beesynth\config on Windows.
+        Example: --ffmpeg=/usr/local/bin/ffmpeg
+
+    --cache-limit=<value>
+        Size limit of the cache of converted files in MiB, default is 1024.
+        The least recently used files are removed above the limit.
+        The cache is stored in $XDG_CACHE_HOME/beesynth (~/.cache/beesynth),
+        %LOCALAPPDATA%\beesynth on Windows or BEESYNTH_CACHE_DIR if set.
+        Use "cache list" and "cache clear" commands to manage it.
+        Example: --cache-limit=256
+
 Filtering:
 
     --low-pass=<value>
@@ -41,6 +107,63 @@ Filtering:
         Example: --high-pass=300  # Drops frequencies below 300 Hz
         Amp -> [High-Pass] -> Amp.
 
+    --biquad=<response>,freq=<value>[,q=<value>][,gain=<value>]
+        Second-order (biquad) filter with the given response:
+            * lowpass, highpass - 12 dB per octave beyond freq.
+            * bandpass - passes the band around freq, narrower with higher Q.
+            * notch - cuts the band around freq.
+            * peak - boosts or cuts the band around freq by gain dB.
+            * lowshelf, highshelf - boost or cut below or above freq by gain dB.
+        Q is 0.707 by default, gain is required for peak and shelves.
+        Example: --biquad=peak,freq=1000,q=1.5,gain=6
+        Amp -> [Biquad] -> Amp
+
+    --butterworth=<lowpass|highpass>,freq=<value>[,order=<value>]
+        Maximally flat low-pass or high-pass filter with the cutoff in Hz.
+        Each order adds 6 dB per octave of roll-off, default order is 4.
+        Example: --butterworth=highpass,freq=200 --butterworth=lowpass,freq=2000,order=8
+        Amp -> [Butterworth] -> Amp
+
+    --linkwitz-riley=<lowpass|highpass>,freq=<value>[,order=<value>]
+        Two cascaded Butterworth filters of the half order, -6 dB at the cutoff.
+        The order must be even, default is 4.
+        Example: --linkwitz-riley=lowpass,freq=2000
+        Amp -> [Linkwitz-Riley] -> Amp
+
+    --resample=<value>
+        Convert samples to the given sample rate in Hz using
+        the band-limited windowed-sinc interpolation.
+        The following filters work with the new rate.
+        Example: --resample=22050  # Downsample before --extract-freq
+        Amp -> [Resample] -> Amp
+
+    --normalize[=<peak|rms>][,level=<value>]
+        Bring the peak or RMS level in dBFS to the given one,
+        default is peak to -1 dB or RMS to -14 dB.
+        The level is followed through the track with the instant attack
+        and the slow release, so quiet and loud parts come out alike.
+        Example: --normalize=rms,level=-12
+        Amp -> [Normalize] -> Amp
+
+    --compress[=threshold=<value>,ratio=<value>,attack=<value>,release=<value>,makeup=<value>]
+        Divide the level above the threshold (dBFS, default is -20) by the ratio
+        (default is 4), attack and release are in msec (5 and 100 by default),
+        makeup is the gain in dB applied after the compression (0 by default).
+        Example: --compress=threshold=-30,ratio=6
+        Amp -> [Compress] -> Amp
+
+    --limit[=threshold=<value>,release=<value>]
+        Don't let the level exceed the threshold in dBFS (default is -1),
+        release is in msec (default is 50).
+        Amp -> [Limit] -> Amp
+
+    --gate[=threshold=<value>,attack=<value>,hold=<value>,release=<value>]
+        Mute the signal which stays below the threshold in dBFS (default is -50)
+        longer than hold msec (default is 50), attack and release are in msec
+        (1 and 100 by default). Keeps the noise of quiet parts out of the speaker.
+        Example: --gate=threshold=-40 --normalize --bake-diff=5
+        Amp -> [Gate] -> Amp
+
     --bake-simple
         Bake amplitude samples into the Up/Down sequence
         using the simple strategy (Up if sample is > 0 and Down otherwise).
@@ -56,10 +179,43 @@ Filtering:
         Example: --bake-diff=10  # Switch the position if the difference is > 10%
         Amp -> [Bake] -> Position
 
-    --extract-freq=[min=<value>,max=<value>,sampling=<value>,step=<value>,channels=<value>]
+    --bake-sigma-delta[=order=<value>,rate=<value>]
+        Bake amplitude samples into the Up/Down sequence using
+        the sigma-delta modulator: the speaker is switched at the given
+        rate in Hz (default is 192000) and the share of Up positions
+        follows the amplitude, the quantization error is pushed
+        to the inaudible frequencies. Order is 1 or 2 (default is 2),
+        the second order gives less noise but needs the quieter signal.
+        Each tick is at least the shortest pulse of the backend (see
+        --bake-pwm), so the rate is up to 500 kHz for the direct port access
+        and 100 kHz for IOCTLs and /dev/port, the default is lowered to it.
+        Must be the last filter in the chain.
+        Example: --normalize --bake-sigma-delta=order=2,rate=256000
+        Amp -> [Bake] -> Position
+
+    --bake-pwm[=carrier=<value>,levels=<value>]
+        Bake amplitude samples into the Up/Down sequence using
+        the pulse-width modulation: each period of the inaudible carrier
+        (in Hz, default is 20000) is Up for the share of it given by
+        the amplitude, quantized to the number of levels (default is 32).
+        Pulses are never shorter than the backend can play: about 2 usec
+        for the direct port access and 10 usec for IOCTLs and /dev/port,
+        so the highest carrier is 250 kHz and 50 kHz respectively.
+        The automatic port access on Linux may end up with /dev/port,
+        so it's limited as the latter, use --port-access=ioperm or --iopl
+        for the direct one.
+        Use --low-pass below half of the carrier to avoid aliasing.
+        Must be the last filter in the chain.
+        Example: --low-pass=8000 --bake-pwm=carrier=18000,levels=16
+        Amp -> [Bake] -> Position
+
+    --extract-freq[=<flag>=<value>,...]
         Switch to the frequency mode.
         Find the most valueable frequencies at each point of time
         and send the speaker using PIT-timer.
+        The whole track is analyzed on all cores before the playback starts,
+        the progress is shown meanwhile. Only the found frequencies are kept
+        till the playback, which is a few KB per second of the track.
         Flags (all flags are optional):
             * min=<value> - Drop frequencies below the given one.
             * max=<value> - Drop frequencies above the given one.
@@ -67,22 +223,197 @@ Filtering:
                                  in number of samples, default is 4096 samples.
             * step=<value> - Shift the sampling window by the given number of samples.
                              Default is 32 samples.
-            * channels=<value> - Number of peaks to extract.
+            * channels=<value> - Number of voices to extract, the loudest first.
                                  Will play with the given number of channels:
                                  monophony if 1, polyphony if >= 2.
                                  Default is 2.
+            * window=<value> - Weighting of the sampling window against
+                               the spectral leakage: rect, hann, hamming
+                               or blackman-harris. Default is hann.
+            * padding=<value> - Pad the window with zeros to the given
+                                number of its sizes (1..16) to get
+                                narrower FFT bins. Default is 1.
+            * interpolation=<value> - Refine the frequency between bins:
+                                      none, parabolic (by the neighbour bins)
+                                      or phase (by the phase advance, the most
+                                      accurate for steady tones). Default is parabolic.
+            * min-track=<value> - Each channel follows a single voice from one
+                                  window to the next by the nearest frequency,
+                                  the voices shorter than the given msec are
+                                  replaced with pauses. Default is 0, all are kept.
+            * silence=<value> - RMS level of the window in dBFS below which
+                                it's a pause rather than the peaks of the noise,
+                                off to disable. Default is off.
+            * relative=<value> - The same level, but relative to the loudest
+                                 window of the file (so far when streaming),
+                                 e.g. -40 to pause at fade-outs.
+                                 The higher of both levels is used.
+            * hysteresis=<value> - The sound stops only this many dB below
+                                   the level, so it doesn't chatter. Default is 6.
+            * band=<min>-<max>[/<sampling>[/<step>[/<channels>]]] - Analyze
+                              the range of Hz with its own window, step and number
+                              of channels, repeat for each band. The long windows
+                              resolve the bass, the short ones follow the fast treble.
+                              The omitted values are taken from the flags above,
+                              min and max are replaced by the bands, which must not overlap.
+                              The channels of all bands are played together.
         Example: --extract-freq=min=300,max=4000,sampling=4096,step=32,channels=2
+        Example: --extract-freq=window=blackman-harris,padding=4,interpolation=phase
+        Example: --extract-freq=silence=-50,relative=-35,hysteresis=3
+        Example: --extract-freq=band=30-250/8192/256/1,band=250-4000/2048/64/2
         Amp -> [FFT] -> Freq
 
-    --note-matcher
+    --track-pitch[=min=<value>,max=<value>,step=<value>,confidence=<value>,silence=<value>]
+        Switch to the frequency mode with a single channel.
+        Follow the fundamental frequency of the voice or the lead instrument
+        using the YIN pitch detector, which doesn't jump to the harmonics
+        as --extract-freq does. Frames which aren't periodic enough or
+        quieter than the silence level become pauses.
+        Flags (all flags are optional):
+            * min=<value>, max=<value> - Range of the pitch in Hz, default is 60..1500.
+            * step=<value> - Shift the frame by the given number of samples.
+                             Default is 256 samples.
+            * confidence=<value> - Minimal periodicity of the voiced frame
+                                   in the range of 0..1, default is 0.85.
+            * silence=<value> - Level in dBFS below which the frame is a pause.
+                                Default is -50.
+        Example: --low-pass=2000 --track-pitch=min=80,max=800 --note-matcher
+        Amp -> [Pitch Tracker] -> Freq
+
+    --extract-notes[=low=<note>,high=<note>,bins=<value>,step=<value>,channels=<value>,min-track=<value>,silence=<value>]
+        Switch to the frequency mode.
+        Find the loudest notes using the constant-Q transform: its bins are spaced
+        by semitones, and the window of each bin spans the same number of periods,
+        so the bass notes are told apart as well as the treble ones.
+        The found frequencies are the exact pitches of the notes.
+        Flags (all flags are optional):
+            * low=<note>, high=<note> - Range of the notes, e.g. A1 or C6#,
+                                        default is C2..C7.
+            * bins=<value> - Bins per octave, a multiple of 12. More bins
+                             tolerate detuned notes, but the frame gets longer.
+                             Default is 12.
+            * step=<value> - Shift the frame by the given number of samples.
+                             Default is 256 samples.
+            * channels=<value> - Number of voices to extract, default is 2.
+            * min-track=<value> - Voices shorter than the given msec are
+                                  replaced with pauses. Default is 50 msec.
+            * silence=<value> - Level of the note in dBFS below which
+                                it's dropped. Default is -60.
+        Example: --extract-notes=low=E1,high=E6,bins=24,channels=3
+        Amp -> [Constant-Q] -> Freq
+
+    --note-matcher[=key=<value>,scale=<value>,a4=<value>,temperament=<value>]
         Find the nearest note for each amplitude sample and use it.
+        Flags (all flags are optional):
+            * key=<value> - Tonic of the key, e.g. A, F# or Bb, the notes out of
+                            the scale go to the nearest ones within it. With auto
+                            or if only the scale is given, the key is estimated
+                            by the notes which sound the longest through the track.
+            * scale=<value> - chromatic, major (ionian), minor (aeolian), dorian,
+                              phrygian, lydian, mixolydian, locrian, pentatonic,
+                              minor-pentatonic, blues or the semitones above
+                              the tonic, e.g. 0/2/4/7/9. Default is major
+                              if the key is given, major or minor for the auto key
+                              and chromatic otherwise.
+            * a4=<value> - Pitch of A4 in Hz, e.g. 432 or 442. Default is 440.
+            * temperament=<value> - equal, just (pure thirds and fifths of the key),
+                                    pythagorean or 12 cents above the tonic,
+                                    e.g. 0/90/204/294/408/498/612/702/792/906/996/1110.
+                                    Default is equal.
+        Example: --note-matcher=key=A,scale=minor,a4=442
+        Example: --note-matcher=key=auto,temperament=just
         Freq -> [Note Matcher] -> Freq
 
+    --transpose=<interval>[,octaves=<value>,fit[=<low>-<high>],scope=<value>,gap=<value>]
+        Transpose the notes by the interval: semitones, e.g. -12, or its name,
+        e.g. P5, m3, M9 or -P8. Several intervals are summed up.
+        Flags:
+            * octaves=<value> - Additional shift by whole octaves, e.g. -1.
+            * fit[=<low>-<high>] - Shift the notes by octaves into the band, Hz or
+                                   notes, e.g. 200-2000 or C3-C7. Default is 100-4000
+                                   where the speaker sounds best. The whole phrase
+                                   moves by the same octaves keeping its contour,
+                                   only the notes still out of the band are folded.
+            * scope=<value> - channel (the whole channel moves at once, it's
+                              buffered till the end) or phrase. Default is phrase.
+            * gap=<value> - Pauses of the given msec split the phrases.
+                            Default is 300 msec.
+        Example: --extract-notes --transpose=P5
+        Example: --extract-freq --transpose=fit=C3-C7,scope=channel
+        Freq -> [Transposer] -> Freq
+
+    --vibrato[=rate=<value>,depth=<value>,delay=<value>,step=<value>]
+        Swing the pitch of each note periodically after a delay.
+        Flags (all flags are optional):
+            * rate=<value> - Swings per second, default is 5.5 Hz.
+            * depth=<value> - Swing up and down in cents, default is 30.
+            * delay=<value> - The note sounds steady for the given msec first.
+                              Default is 150 msec.
+            * step=<value> - Change the frequency each given msec, default is 5.
+                             Each change sets the divisor of the timer, so it can't
+                             be faster than the backend allows, and it's never
+                             shorter than a period of the tone.
+        Example: --extract-notes --vibrato=rate=6,depth=40
+        Freq -> [Vibrato] -> Freq
+
+    --glide[=time=<value>,step=<value>]
+        Glide from each note to the next adjoining one (portamento),
+        the notes after a pause start at once.
+        Flags (all flags are optional):
+            * time=<value> - Duration of the glide in msec, default is 60.
+            * step=<value> - The same as of --vibrato.
+        Example: --glide=time=100 --vibrato
+        Freq -> [Portamento] -> Freq
+
+    --arpeggio[=<pattern>,chord=<value>,note=<value>]
+        Cycle rapidly through the tones of the chord on a single channel.
+        Without the chord, the notes sounding together on all channels are
+        merged into one channel, e.g. the chord written in several channels
+        of the synth file. With the chord, each note becomes its root.
+        Flags (all flags are optional):
+            * <pattern> - up, down or updown, default is up.
+            * chord=<value> - Quality of the chord, e.g. m, 7, maj7, sus4 or dim.
+            * note=<value> - Length of each tone in msec, default is 20.
+        Example: --arpeggio=updown,note=16
+        Example: --extract-notes=channels=1 --arpeggio=chord=m
+        Freq -> [Arpeggio] -> Freq
+
+    --pipeline=<expression> or --pipeline "<expression>"
+        The whole filter chain as a single expression, stages are
+        separated by '|' and applied from left to right:
+            * resample(<hz>), highpass(<hz>), lowpass(<hz>)
+            * biquad(...), butterworth(...), linkwitz-riley(...),
+              normalize(...), compress(...), limit(...), gate(...)
+              with the same arguments as the flags above
+            * bake, bake(simple), bake(diff=<percentage>),
+              bake(sigma-delta,order=<1|2>,rate=<hz>),
+              bake(pwm,carrier=<hz>,levels=<n>)
+            * extract(min=<hz>,max=<hz>,sampling=<n>,step=<n>,channels=<n>,
+                      window=<name>,padding=<n>,interpolation=<name>,min-track=<ms>,
+                      silence=<db|off>,relative=<db>,hysteresis=<db>),
+              or extract(band=<min>-<max>/<n>/<n>/<n>,band=...,<shared arguments>)
+            * pitch(min=<hz>,max=<hz>,step=<n>,confidence=<0..1>,silence=<db>)
+            * cqt(low=<note>,high=<note>,bins=<n>,step=<n>,channels=<n>,
+                  min-track=<ms>,silence=<db>)
+            * notes, notes(key=<tonic|auto>,scale=<name>,a4=<hz>,temperament=<name>)
+            * transpose(<interval>,octaves=<n>,fit=<low>-<high>,scope=<channel|phrase>,gap=<ms>),
+              transpose(fit)
+            * vibrato(rate=<hz>,depth=<cents>,delay=<ms>,step=<ms>)
+            * glide(time=<ms>,step=<ms>)
+            * arpeggio(<up|down|updown>,chord=<quality>,note=<ms>)
+        Each stage must accept the data produced by the previous one,
+        otherwise the chain is rejected before the file is decoded.
+        The filters above are checked the same way in the order of flags.
+        Synth files and ring tones are frequencies already, so only notes,
+        transpose, vibrato, glide and arpeggio are applicable to them.
+        Example: --pipeline "highpass(300) | lowpass(4000) | extract(min=100,max=3000,channels=3) | notes"
+
 Examples:
 
     beesynth.exe N:\\Folder\\Music.mp3
     beesynth.exe --low-pass=100 --high-pass=4000 --bake-diff=5 N:\\Folder\\Music.mp3
     beesynth.exe --extract-freq=min=100,max=3000 --note-matcher N:\\Folder\\Music.mp3
+    beesynth.exe --pipeline "lowpass(3000) | extract(max=3000) | notes" N:\\Folder\\Music.mp3
 "#
     );
 }
\ No newline at end of file
@@ -2,38 +2,48 @@
 #![allow(clippy::unreadable_literal)]
 
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::{collections::VecDeque, sync::atomic::{AtomicBool, Ordering}};
 
 use audio_classifier::AudioType;
-use beeper::{generic::Beeper, sound_emitter::SoundEmitter, port_accessor::PortAccessor, iopl_based::BeeperIopl};
+use beeper::{generic::Beeper, sound_emitter::SoundEmitter, iopl_based::BeeperIopl, virtual_speaker::VirtualSpeaker};
+#[cfg(target_os = "windows")]
+use beeper::port_accessor::PortAccessor;
+#[cfg(target_os = "linux")]
+use beeper::linux_ports::{self, LinuxPorts};
+#[cfg(target_os = "windows")]
 use inpout::{Inpout, Interface, interface::PortByte};
 
-use nano_sleep::NanoSleep;
-use wave::{filter::{PositionRecord, FreqRecordFlt}, wav_header::WaveView};
+use nano_sleep::{NanoSleep, NanoWaiter, VirtualSleep};
+use wave::{filter::{PositionRecord, FreqRecordFlt, StreamFilter}, pipeline::Pipeline, wav_extractor::{Downmix, WaveChunks}, wav_header::WaveView};
+#[cfg(target_os = "windows")]
 use winapi::sched;
 
-use crate::wave::filter::{self, HertzInt, Filter};
+use crate::wave::filter::{self, HertzInt};
 
 mod synth;
 mod wave;
 mod converter;
+mod decoder;
 mod audio_classifier;
 mod help;
 
 
 
-static mut STOP_MACHINE: AtomicBool = AtomicBool::new(false);
+static STOP_MACHINE: AtomicBool = AtomicBool::new(false);
 
 
 
+#[cfg(target_os = "windows")]
 struct InpoutDriver(Inpout);
 
+#[cfg(target_os = "windows")]
 impl InpoutDriver {
     pub fn new() -> Result<Self, inpout::error::Error> {
         Ok(Self(Inpout::new()?))
     }
 }
 
+#[cfg(target_os = "windows")]
 impl PortAccessor for InpoutDriver {
     fn read_byte(&self, port: u16) -> Option<u8> {
         self.0.read_port::<PortByte>(port).ok()
@@ -44,8 +54,10 @@ impl PortAccessor for InpoutDriver {
     }
 }
 
+#[cfg(target_os = "windows")]
 struct PhysMapping<'a>(inpout::interface::PhysMapping, &'a InpoutDriver);
 
+#[cfg(target_os = "windows")]
 impl<'a> iopl::windows::phys_mapper::Mapping for PhysMapping<'a> {
     #[allow(clippy::cast_possible_truncation)]
     fn size(&self) -> usize {
@@ -65,6 +77,7 @@ impl<'a> iopl::windows::phys_mapper::Mapping for PhysMapping<'a> {
     }
 }
 
+#[cfg(target_os = "windows")]
 impl<'a> iopl::windows::phys_mapper::Mapper<'a> for InpoutDriver {
     type Type = PhysMapping<'a>;
 
@@ -77,68 +90,119 @@ impl<'a> iopl::windows::phys_mapper::Mapper<'a> for InpoutDriver {
     }
 }
 
-struct AmplitudePeeker<'a, Iter>
-    where Iter: Iterator<Item = &'a PositionRecord>
+#[cfg(target_os = "windows")]
+type PortsDriver = InpoutDriver;
+
+#[cfg(target_os = "linux")]
+type PortsDriver = LinuxPorts;
+
+#[cfg(target_os = "windows")]
+fn open_ports() -> Result<PortsDriver, ()> {
+    InpoutDriver::new().map_err(|err| {
+        eprintln!("Unable to initialize Inpout: {err}");
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn open_ports(access: &linux_ports::Access) -> Result<PortsDriver, ()> {
+    LinuxPorts::new(access).map_err(|err| {
+        eprintln!("Unable to access I/O ports: {err}");
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn raise_iopl(ports: &PortsDriver) -> Result<(), ()> {
+    iopl::windows::Patcher::new(ports).patch(iopl::level::Level::Ring3).map_err(|err| {
+        eprintln!("Unable to patch iopl: {err}");
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn raise_iopl(_ports: &PortsDriver) -> Result<(), ()> {
+    iopl::linux::raise(iopl::level::Level::Ring3).map_err(|err| {
+        eprintln!("Unable to raise iopl: {err}");
+    })
+}
+
+struct AmplitudePeeker<Chunks>
+    where Chunks: Iterator<Item = filter::Data>
 {
-    iter: Iter
+    chunks: Chunks,
+    records: std::vec::IntoIter<PositionRecord>
 }
 
-impl<'a, Iter> AmplitudePeeker<'a, Iter>
-    where Iter: Iterator<Item = &'a PositionRecord>
+impl<Chunks> AmplitudePeeker<Chunks>
+    where Chunks: Iterator<Item = filter::Data>
 {
-    pub fn new(iter: Iter) -> Self {
-        Self { iter }
+    pub fn new(chunks: Chunks) -> Self {
+        Self { chunks, records: Vec::new().into_iter() }
     }
 }
 
-impl<'a, Iter> wave::player::amplitudes::Peeker for AmplitudePeeker<'a, Iter>
-    where Iter: Iterator<Item = &'a PositionRecord>
+impl<Chunks> wave::player::amplitudes::Peeker for AmplitudePeeker<Chunks>
+    where Chunks: Iterator<Item = filter::Data>
 {
     fn peek(&mut self) -> Option<PositionRecord> {
-        if unsafe { STOP_MACHINE.load(Ordering::Relaxed) } {
+        if STOP_MACHINE.load(Ordering::Relaxed) {
             return None;
         }
 
-        self.iter.next().copied()
+        loop {
+            if let Some(record) = self.records.next() {
+                return Some(record);
+            }
+
+            let Some(filter::Data::Position(positions)) = self.chunks.next() else {
+                return None;
+            };
+            self.records = positions.into_iter();
+        }
     }
 }
 
 
-struct FrequencyPeeker<'a, Iter>
-    where Iter: Iterator<Item = &'a FreqRecordFlt>
+struct FrequencyPeeker<Chunks>
+    where Chunks: Iterator<Item = filter::Data>
 {
-    channels: Vec<Iter>
+    chunks: Chunks,
+    channels: Vec<VecDeque<FreqRecordFlt>>
 }
 
-impl<'a, Iter> FrequencyPeeker<'a, Iter>
-    where Iter: Iterator<Item = &'a FreqRecordFlt>
+impl<Chunks> FrequencyPeeker<Chunks>
+    where Chunks: Iterator<Item = filter::Data>
 {
-    pub fn new() -> Self {
-        Self { channels: Vec::new() }
-    }
-
-    pub fn add(&mut self, iter: Iter) {
-        self.channels.push(iter);
+    pub fn new(channel_count: usize, chunks: Chunks) -> Self {
+        Self { chunks, channels: vec![VecDeque::new(); channel_count] }
     }
 }
 
-impl<'a, Iter> wave::player::frequencies::Peeker for FrequencyPeeker<'a, Iter>
-    where Iter: Iterator<Item = &'a FreqRecordFlt>
+impl<Chunks> wave::player::frequencies::Peeker for FrequencyPeeker<Chunks>
+    where Chunks: Iterator<Item = filter::Data>
 {
     #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
     fn peek(&mut self, channel_number: usize) -> Option<filter::FreqRecord<HertzInt>> {
-        if unsafe { STOP_MACHINE.load(Ordering::Relaxed) } {
+        if STOP_MACHINE.load(Ordering::Relaxed) {
             return None;
         }
 
-        self.channels
-            .get_mut(channel_number)
-            .and_then(|channel| channel.next().map(|record|
-                filter::FreqRecordInt {
+        loop {
+            let record = self.channels.get_mut(channel_number)?.pop_front();
+            if let Some(record) = record {
+                return Some(filter::FreqRecordInt {
                     freq: record.freq as HertzInt,
                     duration: record.duration
-                }
-            ))
+                });
+            }
+
+            // Channels are played simultaneously, so the chunk is shared between them:
+            let Some(filter::Data::Frequency(frequencies)) = self.chunks.next() else {
+                return None;
+            };
+
+            for (channel, records) in self.channels.iter_mut().zip(frequencies) {
+                channel.extend(records);
+            }
+        }
     }
 
     fn channel_count(&self) -> usize {
@@ -148,125 +212,314 @@ impl<'a, Iter> wave::player::frequencies::Peeker for FrequencyPeeker<'a, Iter>
 
 
 enum BeeperType {
-    Ioctl,
+    Generic, // Inpout IOCTLs on Windows, ioperm/iopl or /dev/port on Linux
     Iopl
 }
 
+///
+/// Shortest Up or Down the backend plays without distortion: each toggle reads and writes the port 0x61,
+/// it takes a couple of microseconds by itself and much longer with a round trip through the kernel.
+///
+fn min_pulse_ns(play_params: &PlayParams) -> filter::Nsec {
+    const DIRECT_MIN_PULSE_NS: filter::Nsec = 2_000;   // in/out instructions
+    const SYSCALL_MIN_PULSE_NS: filter::Nsec = 10_000; // IOCTLs of Inpout or /dev/port
+
+    let is_direct = match play_params.beeper_type {
+        BeeperType::Iopl => true,
+        #[cfg(target_os = "windows")]
+        BeeperType::Generic => false,
+        // Auto access may fall back to /dev/port, so only the explicit ioperm and iopl are direct:
+        #[cfg(target_os = "linux")]
+        BeeperType::Generic => matches!(play_params.port_access, linux_ports::Access::Ioperm | linux_ports::Access::Iopl)
+    };
+
+    if is_direct { DIRECT_MIN_PULSE_NS } else { SYSCALL_MIN_PULSE_NS }
+}
+
+///
+/// Applies the backend params ahead of the others,
+/// as the backend is known only after all params, but bakeries need it in the order of flags.
+///
+fn select_backend(play_params: &mut PlayParams, params: &[Param]) {
+    for param in params {
+        match param {
+            Param::Iopl => play_params.beeper_type = BeeperType::Iopl,
+            #[cfg(target_os = "linux")]
+            Param::PortAccess(access) => play_params.port_access = access.clone(),
+            _ => ()
+        }
+    }
+}
+
 struct PlayParams {
     beeper_type: BeeperType,
+    #[cfg(target_os = "linux")]
+    port_access: linux_ports::Access,
     switch_interval: u64,
-    filters: Vec::<Box<dyn wave::filter::Filter>>
+    render_path: Option<std::path::PathBuf>,
+    downmix: Downmix,
+    filters: Vec::<Box<dyn wave::filter::StreamFilter>>
+}
+
+impl Default for PlayParams {
+    fn default() -> Self {
+        Self {
+            beeper_type: BeeperType::Generic,
+            #[cfg(target_os = "linux")]
+            port_access: linux_ports::Access::default(),
+            switch_interval: 20 * 1000 * 1000,
+            render_path: None,
+            downmix: Downmix::default(),
+            filters: Vec::new()
+        }
+    }
 }
 
 enum BeeperHolder<'a> {
-    Ioctl(Beeper<'a, InpoutDriver>),
+    Generic(Beeper<'a, PortsDriver>),
     Iopl(BeeperIopl)
 }
 
-fn play_data(mut samples: filter::Data, play_params: &PlayParams) -> Result<(), ()> {
-    for filter in &play_params.filters {
-        samples = if let Some(filtered) = filter.filter(samples) {
-            filtered
-        } else {
-            eprintln!("Mismatched filter type and the filtered data.");
-            return Err(());
+///
+/// Plays the first chunk and then the rest of them, the kind of data is defined by the first chunk.
+///
+fn emit(
+    emitter: &mut impl SoundEmitter,
+    first_chunk: filter::Data,
+    chunks: impl Iterator<Item = filter::Data>,
+    waiter: &impl NanoWaiter,
+    switch_interval: u64)
+{
+    emitter.prepare();
+
+    match first_chunk {
+        filter::Data::Amplitude(_) => unreachable!(),
+        filter::Data::Frequency(ref frequencies) => {
+            let mut freq_peeker = FrequencyPeeker::new(frequencies.len(), std::iter::once(first_chunk).chain(chunks));
+            wave::player::frequencies::play(emitter, &mut freq_peeker, waiter, switch_interval);
+        },
+        filter::Data::Position(_) => {
+            wave::player::amplitudes::play(emitter, &mut AmplitudePeeker::new(std::iter::once(first_chunk).chain(chunks)), waiter);
+        }
+    }
+}
+
+fn render_data(
+    first_chunk: filter::Data,
+    chunks: impl Iterator<Item = filter::Data>,
+    play_params: &PlayParams,
+    path: &std::path::Path) -> Result<(), ()>
+{
+    const RENDER_SAMPLE_RATE: u32 = 44100;
+    const POLL_INTERVAL_NSEC: u64 = 1000;
+
+    let clock = VirtualSleep::new(POLL_INTERVAL_NSEC);
+    let mut speaker = VirtualSpeaker::new(&clock);
+    emit(&mut speaker, first_chunk, chunks, &clock, play_params.switch_interval);
+
+    if let Err(err) = speaker.save_wav(path, RENDER_SAMPLE_RATE) {
+        eprintln!("Unable to save the rendered file {}: {err}", path.to_str().unwrap_or("<???>"));
+        return Err(());
+    }
+
+    println!("Rendered to {}", path.to_str().unwrap_or("<???>"));
+    Ok(())
+}
+
+///
+/// Reports on stderr how much of the source has been pulled through the analysis.
+///
+struct Progress<Source: Iterator<Item = filter::Data>> {
+    source: Source,
+    frame_count: Option<u64>, // The percentage is shown only if the length of the source is known
+    frames: u64,
+    reported: String,
+    is_enabled: bool
+}
+
+impl<Source: Iterator<Item = filter::Data>> Iterator for Progress<Source> {
+    type Item = filter::Data;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = self.source.next()?;
+
+        if let (true, filter::Data::Amplitude(wave)) = (self.is_enabled, &chunk) {
+            self.frames += wave.samples.len() as u64;
+
+            let report = match self.frame_count {
+                Some(frame_count) if frame_count > 0 => format!("{}%", (100 * self.frames / frame_count).min(100)),
+                _ => format!("{} s", self.frames / u64::from(wave.sample_rate.max(1)))
+            };
+
+            if report != self.reported {
+                eprint!("\rAnalyzing: {report}  ");
+                self.reported = report;
+            }
         }
+
+        Some(chunk)
+    }
+}
+
+///
+/// Pins the playback to the last CPU at the realtime priority, so nothing preempts it.
+/// It's done right before the playback, as the analysis runs on all cores.
+/// On Linux the realtime policy needs root or `CAP_SYS_NICE`, without them the playback goes on as is.
+///
+fn raise_priority() {
+    #[cfg(target_os = "windows")]
+    {
+        sched::set_affinity(sched::Affinity::Exact(sched::get_cpu_count() - 1));
+        sched::set_process_priority(sched::Priority::Realtime);
+        sched::set_thread_priority(sched::Priority::Realtime);
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe {
+        let last_cpu = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get) - 1;
+        let mut cpu_set = std::mem::zeroed::<libc::cpu_set_t>();
+        libc::CPU_SET(last_cpu, &mut cpu_set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &raw const cpu_set);
+
+        let param = libc::sched_param { sched_priority: libc::sched_get_priority_max(libc::SCHED_FIFO) };
+        libc::sched_setscheduler(0, libc::SCHED_FIFO, &raw const param);
     }
+}
+
+fn play_data(source: impl Iterator<Item = filter::Data>, frame_count: Option<u64>, mut play_params: PlayParams) -> Result<(), ()> {
+    let started = std::time::Instant::now();
+    let is_analyzed = !play_params.filters.is_empty() && chain_output(&play_params.filters) == filter::Type::Frequency;
 
-    if let filter::Data::Amplitude(_) = &samples {
-        let bakery = wave::bakery::Bakery::new(wave::bakery::Strategy::Differential(5));
-        if let Some(baked) = bakery.filter(samples) {
-            samples = baked;
+    let progress = Progress { source, frame_count, frames: 0, reported: String::new(), is_enabled: is_analyzed };
+    let mut pipeline = Pipeline::new(progress, std::mem::take(&mut play_params.filters));
+
+    let Some(mut first_chunk) = pipeline.next() else {
+        if pipeline.is_mismatched() {
+            eprintln!("Mismatched filter type and the filtered data.");
         } else {
+            eprintln!("There are no data to play.");
+        }
+        return Err(());
+    };
+
+    if let filter::Data::Amplitude(_) = &first_chunk {
+        let mut bakery = wave::bakery::Bakery::new(wave::bakery::Strategy::Differential(5));
+        let Some(baked) = bakery.feed(first_chunk) else {
             eprintln!("Unable to bake samples.");
             return Err(());
-        }
+        };
+        pipeline.push(Box::new(bakery));
+
+        // The bakery may hold the only record of the first chunk until the next one:
+        first_chunk = if baked.is_empty() {
+            let Some(next_chunk) = pipeline.next() else {
+                eprintln!("There are no data to play.");
+                return Err(());
+            };
+            next_chunk
+        } else {
+            baked
+        };
     }
 
-    let inpout = match InpoutDriver::new() {
-        Ok(inpout) => inpout,
-        Err(err) => {
-            eprintln!("Unable to initialize Inpout: {err}");
-            return Err(());
-        }
+    // The whole source is analyzed beforehand, so the playback doesn't stall on the spectra.
+    // It gives up the streaming for the analyzed chain, but only the records are kept, not the samples:
+    // a few channels of 16-byte records at ~40 frames per second take a few KB per second of the track.
+    let chunks: Box<dyn Iterator<Item = filter::Data>> = if is_analyzed {
+        let chunks = pipeline.by_ref().collect::<Vec<filter::Data>>();
+        eprintln!("\rAnalyzed in {:.1} s   ", started.elapsed().as_secs_f32());
+        Box::new(chunks.into_iter())
+    } else {
+        Box::new(pipeline.by_ref())
     };
 
+    if let Some(render_path) = &play_params.render_path {
+        return render_data(first_chunk, chunks, &play_params, render_path);
+    }
+
+    raise_priority();
+
+    #[cfg(target_os = "windows")]
+    let ports = open_ports()?;
+    #[cfg(target_os = "linux")]
+    let ports = open_ports(&play_params.port_access)?;
+
     let mut beeper_holder = match play_params.beeper_type {
-        BeeperType::Ioctl => BeeperHolder::Ioctl(Beeper::new(&inpout)),
+        BeeperType::Generic => BeeperHolder::Generic(Beeper::new(&ports)),
         BeeperType::Iopl => {
-            let patch_status = iopl::windows::Patcher::new(&inpout).patch(iopl::level::Level::Ring3);
-            match patch_status {
-                Ok(()) => (),
-                Err(err) => {
-                    eprintln!("Unable to patch iopl: {err}");
-                    return Err(());
-                }
-            }
+            raise_iopl(&ports)?;
             BeeperHolder::Iopl(BeeperIopl::new())
         }
     };
     
     let waiter = NanoSleep::new(1000);
 
-    match samples {
-        filter::Data::Amplitude(_) => unreachable!(),
-        filter::Data::Frequency(frequencies) => {
-            let mut freq_peeker = FrequencyPeeker::new();
-            for channel in &frequencies {
-                freq_peeker.add(channel.iter());
-            }
+    match beeper_holder {
+        BeeperHolder::Generic(ref mut beeper) => emit(beeper, first_chunk, chunks, &waiter, play_params.switch_interval),
+        BeeperHolder::Iopl(ref mut beeper) => emit(beeper, first_chunk, chunks, &waiter, play_params.switch_interval)
+    }
 
-            match beeper_holder {
-                BeeperHolder::Ioctl(ref mut beeper) => {
-                    beeper.prepare();
-                    wave::player::frequencies::play(beeper, &mut freq_peeker, &waiter, play_params.switch_interval);
-                }
-                BeeperHolder::Iopl(ref mut beeper) => {
-                    beeper.prepare();
-                    wave::player::frequencies::play(beeper, &mut freq_peeker, &waiter, play_params.switch_interval);
-                }
-            }
-        },
-        filter::Data::Position(positions) => {
-            match beeper_holder {
-                BeeperHolder::Ioctl(ref mut beeper) => {
-                    beeper.prepare();
-                    wave::player::amplitudes::play(beeper, &mut AmplitudePeeker::new(positions.iter()), &waiter);
-                }
-                BeeperHolder::Iopl(ref mut beeper) => {
-                    beeper.prepare();
-                    wave::player::amplitudes::play(beeper, &mut AmplitudePeeker::new(positions.iter()), &waiter);
-                }
-            }
-        }
+    if pipeline.is_mismatched() {
+        eprintln!("Mismatched filter type and the filtered data.");
+        return Err(());
     }
 
     println!("Finished");
     Ok(())
 }
 
-fn play_wav(wav: WaveView, play_params: &PlayParams) -> Result<(), ()> {
-    let samples: filter::Data = wav.into();
-    if samples.is_empty() {
-        eprintln!("There are no data to play.");
+const FRAMES_PER_CHUNK: usize = 16384;
+
+fn play_wav(wav: &WaveView, play_params: PlayParams) -> Result<(), ()> {
+    let num_channels = wav.format().num_channels;
+    if !play_params.downmix.is_applicable(num_channels) {
+        eprintln!("Unable to apply {:?} to the file with {num_channels} channel(s).", play_params.downmix);
+        return Err(());
+    }
+
+    let downmix = play_params.downmix;
+    play_data(WaveChunks::new(wav, FRAMES_PER_CHUNK, downmix), u64::try_from(wav.frame_count()).ok(), play_params)
+}
+
+fn play_decoded(mut decoder: decoder::Decoder, play_params: PlayParams) -> Result<(), ()> {
+    // The durations are divided by the rate, the broken headers may have it zeroed:
+    if decoder.sample_rate() == 0 {
+        eprintln!("The sample rate of the track is zero.");
         return Err(());
     }
 
-    play_data(samples, play_params)
+    if let Some(num_channels) = decoder.num_channels() {
+        if !play_params.downmix.is_applicable(num_channels) {
+            eprintln!("Unable to apply {:?} to the file with {num_channels} channel(s).", play_params.downmix);
+            return Err(());
+        }
+    }
+
+    decoder.set_downmix(play_params.downmix);
+    let frame_count = decoder.frame_count();
+    play_data(decoder, frame_count, play_params)
 }
 
 
 fn parse_synth_params(params: Vec<Param>) -> Result<PlayParams, String> {
-    let mut play_params = PlayParams {
-        beeper_type: BeeperType::Ioctl,
-        switch_interval: 20 * 1000 * 1000,
-        filters: Vec::new()
-    };
+    let mut play_params = PlayParams::default();
+    select_backend(&mut play_params, &params);
+    let min_pulse_ns = min_pulse_ns(&play_params);
 
     for param in params {
         match param {
-            Param::Iopl => play_params.beeper_type = BeeperType::Iopl,
+            Param::Iopl => (), // Selected beforehand
+            #[cfg(target_os = "linux")]
+            Param::PortAccess(_) => (),
             Param::SwitchInterval(interval) => play_params.switch_interval = interval,
+            Param::Render(path) => play_params.render_path = Some(path),
+            Param::Pipeline(expression) => {
+                // The notes are already frequencies, so only the stages taking them are applicable:
+                let input = play_params.filters.last().map_or(filter::Type::Frequency, |filter| filter.output_type());
+                let stages = wave::pipeline_parser::parse(&expression, input, min_pulse_ns)
+                    .map_err(|err| format!("Invalid pipeline: {err}"))?;
+                play_params.filters.extend(stages);
+            }
             _ => return Err(format!("Inapplicable param {param:?}"))
         }
     }
@@ -274,52 +527,82 @@ fn parse_synth_params(params: Vec<Param>) -> Result<PlayParams, String> {
     Ok(play_params)
 }
 
-fn parse_wave_params(params: Vec<Param>, sample_rate: u32) -> PlayParams {
-    let mut play_params = PlayParams {
-        beeper_type: BeeperType::Ioctl,
-        switch_interval: 20 * 1000 * 1000,
-        filters: Vec::new()
-    };
+///
+/// Kind of the data produced by the chain, the source is amplitude samples.
+///
+fn chain_output(filters: &[Box<dyn StreamFilter>]) -> filter::Type {
+    filters.last().map_or(filter::Type::Amplitude, |filter| filter.output_type())
+}
+
+///
+/// Appends the filter if it accepts the data produced by the chain.
+///
+fn push_filter(filters: &mut Vec<Box<dyn StreamFilter>>, filter: Box<dyn StreamFilter>, name: &str) -> Result<(), String> {
+    let data_type = chain_output(filters);
+    if filter.input_type() != data_type {
+        return Err(format!(
+            "Misplaced {name}: it expects {:?} data, but the previous filter produces {data_type:?} data",
+            filter.input_type()
+        ));
+    }
+
+    filters.push(filter);
+    Ok(())
+}
+
+fn parse_wave_params(params: Vec<Param>) -> Result<PlayParams, String> {
+    let mut play_params = PlayParams::default();
+    select_backend(&mut play_params, &params);
+    let min_pulse_ns = min_pulse_ns(&play_params);
+    let filters = &mut play_params.filters;
 
     #[allow(clippy::cast_precision_loss)]
     for param in params {
         match param {
-            Param::Iopl => play_params.beeper_type = BeeperType::Iopl,
+            Param::Iopl => (), // Selected beforehand
+            #[cfg(target_os = "linux")]
+            Param::PortAccess(_) => (),
             Param::SwitchInterval(interval) => play_params.switch_interval = interval,
-            Param::LowPass(highest_freq) => play_params.filters.push(
-                Box::new(wave::freq_filters::LowPass::new(sample_rate, highest_freq as f32))
-            ),
-            Param::HighPass(lowest_freq) => play_params.filters.push(
-                Box::new(wave::freq_filters::LowPass::new(sample_rate, lowest_freq as f32))
-            ),
-            Param::BakeSimple => play_params.filters.push(
-                Box::new(wave::bakery::Bakery::new(wave::bakery::Strategy::Simple))
-            ),
-            Param::BakeDifferential(percentage) => play_params.filters.push(
-                Box::new(wave::bakery::Bakery::new(wave::bakery::Strategy::Differential(percentage)))
-            ),
-            Param::ExtractFreq(min, max, sampling_size, step_by, channel_count) => play_params.filters.push(Box::new(
-                wave::freq_extractor::FreqExtractor::new(
-                    min,
-                    max,
-                    sampling_size.unwrap_or(4096),
-                    step_by.unwrap_or(32),
-                    sample_rate, 
-                    channel_count.unwrap_or(2)
-                )
-            )),
-            Param::NoteMatcher => play_params.filters.push(Box::new(wave::note_matcher::NoteMatcher))
+            Param::Render(path) => play_params.render_path = Some(path),
+            Param::Downmix(downmix) => play_params.downmix = downmix,
+            Param::ConvertRate(_) | Param::Ffmpeg(_) | Param::CacheLimit(_) => (), // Applied by the converter
+            Param::Resample(target_rate) => push_filter(
+                filters,
+                Box::new(wave::resampler::Resampler::new(target_rate)),
+                "--resample"
+            )?,
+            Param::LowPass(highest_freq) => push_filter(
+                filters,
+                Box::new(wave::freq_filters::LowPass::new(highest_freq as f32)),
+                "--low-pass"
+            )?,
+            Param::HighPass(lowest_freq) => push_filter(
+                filters,
+                Box::new(wave::freq_filters::HighPass::new(lowest_freq as f32)),
+                "--high-pass"
+            )?,
+            Param::BakeSimple => push_filter(
+                filters,
+                Box::new(wave::bakery::Bakery::new(wave::bakery::Strategy::Simple)),
+                "--bake-simple"
+            )?,
+            Param::BakeDifferential(percentage) => push_filter(
+                filters,
+                Box::new(wave::bakery::Bakery::new(wave::bakery::Strategy::Differential(percentage))),
+                "--bake-diff"
+            )?,
+            Param::Pipeline(expression) => {
+                let stages = wave::pipeline_parser::parse(&expression, chain_output(filters), min_pulse_ns)
+                    .map_err(|err| format!("Invalid pipeline: {err}"))?;
+                filters.extend(stages);
+            }
         }
     }
 
-    play_params
+    Ok(play_params)
 }
 
 fn play_generic(path: &std::path::Path, params: Vec<Param>) -> Result<(), ()> {
-    sched::set_affinity(sched::Affinity::Exact(sched::get_cpu_count() - 1));
-    sched::set_process_priority(sched::Priority::Realtime);
-    sched::set_thread_priority(sched::Priority::Realtime);
-    
     let mut data = match std::fs::read(path) {
         Ok(buf) => buf,
         Err(err) => {
@@ -328,13 +611,24 @@ fn play_generic(path: &std::path::Path, params: Vec<Param>) -> Result<(), ()> {
         }
     };
 
-    let audio_type = AudioType::classify(&data);
-    if let AudioType::Synth = audio_type {
-        let listing = unsafe { std::str::from_utf8_unchecked(&data) };
-        let channels = match synth::parser::Parser::new(listing).parse() {
+    let extension = path.extension().and_then(std::ffi::OsStr::to_str);
+    let audio_type = AudioType::classify(&data, extension);
+    if let AudioType::Synth | AudioType::Rtttl = audio_type {
+        let Ok(listing) = std::str::from_utf8(&data) else {
+            eprintln!("Unable to read the given {audio_type:?} file: it isn't a valid UTF-8 text");
+            return Err(());
+        };
+
+        let channels = if audio_type == AudioType::Synth {
+            synth::parser::Parser::new(listing).parse()
+        } else {
+            synth::rtttl::parse(listing)
+        };
+
+        let channels = match channels {
             Ok(channels) => channels,
             Err(err) => {
-                eprintln!("Unable to parse the given synth-file: {err}");
+                eprintln!("Unable to parse the given {audio_type:?} file: {err}");
                 return Err(());
             }
         };
@@ -347,59 +641,110 @@ fn play_generic(path: &std::path::Path, params: Vec<Param>) -> Result<(), ()> {
             }
         };
 
-        play_data(channels.into(), &play_params)
+        play_data(std::iter::once(channels.into()), None, play_params)
     } else {
-        let wav_header = match audio_type {
-            AudioType::Wav => match wave::wav_header::WaveView::try_from(data.as_slice()) {
-                Ok(header) => header,
-                Err(err) => {
-                    eprintln!("Unable to parse the given wav-file: {err}");
-                    return Err(());
-                }
-            },
-            AudioType::Mp3 | AudioType::Unknown => {
-                let converted_file = converter::convert_to_wav(path, 16, 22050);
-                match converted_file {
-                    Ok(path) => match std::fs::read(&path) {
-                        Ok(buf) => {
-                            data = buf;
-                            match wave::wav_header::WaveView::try_from(data.as_slice()) {
-                                Ok(header) => header,
-                                Err(err) => {
-                                    eprintln!("Unable to parse the given wav-file: {err}");
-                                    return Err(());
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            eprintln!("Unable to read the converted file {}: {err}", path.to_str().unwrap_or("<???>"));
-                            return Err(());
-                        }
-                    }
+        if audio_type.is_sequence() {
+            eprintln!("Unable to play the given {audio_type:?} file: it needs a synthesizer, render it to WAV first");
+            return Err(());
+        }
+
+        // Invalid filter chains are rejected before decoding:
+        let converter_settings = converter_settings(&params);
+        let play_params = match parse_wave_params(params) {
+            Ok(params) => params,
+            Err(err) => {
+                eprintln!("{err}");
+                return Err(());
+            }
+        };
+
+        if audio_type.is_decodable() {
+            match decoder::Decoder::open(path, FRAMES_PER_CHUNK) {
+                Ok(decoder) => return play_decoded(decoder, play_params),
+                Err(err) => eprintln!("Unable to decode the given file: {err}, trying to convert it using ffmpeg.")
+            }
+        }
+
+        // Tracker modules are rendered by the converter:
+        if audio_type != AudioType::Wav {
+            let converted_file = converter::convert_to_wav(path, 16, &converter_settings);
+            data = match converted_file {
+                Ok(path) => match std::fs::read(&path) {
+                    Ok(buf) => buf,
                     Err(err) => {
-                        eprintln!("Unable to convert the given file to WAV: {err}");
+                        eprintln!("Unable to read the converted file {}: {err}", path.to_str().unwrap_or("<???>"));
                         return Err(());
                     }
+                },
+                Err(err) => {
+                    eprintln!("Unable to convert the given {audio_type:?} file to WAV: {err}");
+                    return Err(());
                 }
-            },
-            AudioType::Synth => unreachable!() // Handled above
+            };
+        }
+
+        let wav_header = match wave::wav_header::WaveView::try_from(data.as_slice()) {
+            Ok(header) => header,
+            Err(err) => {
+                eprintln!("Unable to parse the given wav-file: {err}");
+                return Err(());
+            }
         };
 
-        let play_params = parse_wave_params(params, wav_header.header().sample_rate);
-        play_wav(wav_header, &play_params)
+        play_wav(&wav_header, play_params)
     }
 }
 
-fn mute() -> Result<(), ()> {
-    let inpout = match InpoutDriver::new() {
-        Ok(inpout) => inpout,
-        Err(err) => {
-            eprintln!("Unable to initialize Inpout: {err}");
-            return Err(());
+fn converter_settings(params: &[Param]) -> converter::Settings {
+    let mut settings = converter::Settings::default();
+
+    for param in params {
+        match param {
+            Param::ConvertRate(sample_rate) => settings.sample_rate = Some(*sample_rate),
+            Param::Ffmpeg(path) => settings.ffmpeg_path = Some(path.clone()),
+            Param::CacheLimit(limit) => settings.cache_limit = *limit,
+            _ => ()
         }
-    };
+    }
+
+    settings
+}
+
+///
+/// Handles `cache list` and `cache clear` commands.
+///
+fn manage_cache(command: &[String]) -> Result<(), ()> {
+    match command.first().map(String::as_str) {
+        Some("list") => {
+            let entries = converter::list_cache().map_err(|err| eprintln!("{err}"))?;
+            for entry in &entries {
+                let age = entry.last_used.elapsed().unwrap_or_default().as_secs();
+                println!("{:>10} KiB  used {:>6} min ago  {}", entry.size / 1024, age / 60, entry.path.display());
+            }
+
+            let total_size = entries.iter().map(|entry| entry.size).sum::<u64>();
+            println!("{} file(s), {} MiB in total", entries.len(), total_size / (1024 * 1024));
+            Ok(())
+        },
+        Some("clear") => {
+            let removed = converter::clear_cache().map_err(|err| eprintln!("{err}"))?;
+            println!("Removed {removed} file(s)");
+            Ok(())
+        },
+        _ => {
+            eprintln!("Usage: cache <list|clear>");
+            Err(())
+        }
+    }
+}
+
+fn mute(#[cfg(target_os = "linux")] access: &linux_ports::Access) -> Result<(), ()> {
+    #[cfg(target_os = "windows")]
+    let ports = open_ports()?;
+    #[cfg(target_os = "linux")]
+    let ports = open_ports(access)?;
 
-    let mut beeper = Beeper::new(&inpout);
+    let mut beeper = Beeper::new(&ports);
     beeper.prepare();
     beeper.mute();
 
@@ -409,19 +754,24 @@ fn mute() -> Result<(), ()> {
 #[derive(Debug)]
 enum Param {
     Iopl,                                  // --iopl
+    #[cfg(target_os = "linux")]
+    PortAccess(linux_ports::Access),       // --port-access=auto|ioperm|iopl|dev-port or --dev-port=path
     SwitchInterval(u64 /* Msec */),        // --switch-interval=msec
+    Render(std::path::PathBuf),            // --render=path
+    Downmix(Downmix),                      // --downmix=mono|mid|side|left|right|index
+    ConvertRate(u32 /* Hz */),             // --convert-rate=hz
+    Ffmpeg(std::path::PathBuf),            // --ffmpeg=path
+    CacheLimit(u64 /* Bytes */),           // --cache-limit=mib
+    Resample(u32 /* Hz */),                // --resample=hz
     LowPass(u32 /* Hz */),                 // --low-pass=hz
     HighPass(u32 /* Hz */),                // --high-pass=hz
     BakeSimple,                            // --bake-simple
     BakeDifferential(u8 /* Percentage */), // --bake-diff=percentage
-    ExtractFreq(                           // --extract-freq=[...]
-        Option<u32> /* Min Hz */,              // min=N
-        Option<u32> /* Max Hz */,              // max=N
-        Option<u32> /* Sampling size */,       // sampling=N
-        Option<u32> /* Step by */,             // step=N
-        Option<u8>  /* Number of channels */,  // channels=N
-    ),
-    NoteMatcher                            // --note-matcher
+    Pipeline(String)                       // --pipeline=expression or --pipeline expression,
+                                           // --biquad, --butterworth, --linkwitz-riley, --normalize,
+                                           // --compress, --limit, --gate, --bake-sigma-delta, --bake-pwm, --note-matcher,
+                                           // --extract-freq, --track-pitch, --extract-notes, --transpose, --vibrato,
+                                           // --glide and --arpeggio as single stages
 }
 
 #[derive(Debug)]
@@ -437,10 +787,12 @@ impl std::error::Error for ParseError {}
 
 
 
+#[allow(clippy::too_many_lines)]
 fn parse_params(params: &[String]) -> Result<(std::path::PathBuf, Vec<Param>), ParseError> {
     let mut path = std::path::PathBuf::new();
     let mut result = Vec::<Param>::new();
-    for param in params {
+    let mut params = params.iter();
+    while let Some(param) = params.next() {
         if param.starts_with("--") {
             let mut parts = param.splitn(2, '=');
             let name = parts.next().unwrap();
@@ -449,11 +801,70 @@ fn parse_params(params: &[String]) -> Result<(std::path::PathBuf, Vec<Param>), P
                 "--iopl" => {
                     result.push(Param::Iopl);
                 }
+                #[cfg(target_os = "linux")]
+                "--port-access" => {
+                    let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
+                    let access = match value {
+                        "auto" => linux_ports::Access::Auto,
+                        "ioperm" => linux_ports::Access::Ioperm,
+                        "iopl" => linux_ports::Access::Iopl,
+                        "dev-port" => linux_ports::Access::DevPort(std::path::PathBuf::from(LinuxPorts::DEV_PORT_PATH)),
+                        _ => return Err(ParseError(format!("Unknown port access method {value}")))
+                    };
+                    result.push(Param::PortAccess(access));
+                }
+                #[cfg(target_os = "linux")]
+                "--dev-port" => {
+                    let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
+                    result.push(Param::PortAccess(linux_ports::Access::DevPort(std::path::PathBuf::from(value))));
+                }
                 "--switch-interval" => {
                     let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
                     let value = value.parse::<u64>().map_err(|err| ParseError(format!("Unable to parse {value} as u64: {err}")))?;
                     result.push(Param::SwitchInterval(value));
                 }
+                "--render" => {
+                    let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
+                    result.push(Param::Render(std::path::PathBuf::from(value)));
+                }
+                "--downmix" => {
+                    let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
+                    let downmix = match value {
+                        "mono" => Downmix::Mono,
+                        "mid" => Downmix::Mid,
+                        "side" => Downmix::Side,
+                        "left" => Downmix::Left,
+                        "right" => Downmix::Right,
+                        _ => Downmix::Channel(value.parse::<u16>().map_err(|err| ParseError(format!("Unknown downmix {value}: {err}")))?)
+                    };
+                    result.push(Param::Downmix(downmix));
+                }
+                "--convert-rate" => {
+                    let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
+                    let value = value.parse::<u32>().map_err(|err| ParseError(format!("Unable to parse {value} as u32: {err}")))?;
+                    if value == 0 {
+                        return Err(ParseError(format!("Invalid sample rate {value}")));
+                    }
+                    result.push(Param::ConvertRate(value));
+                }
+                "--ffmpeg" => {
+                    let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
+                    result.push(Param::Ffmpeg(std::path::PathBuf::from(value)));
+                }
+                "--cache-limit" => {
+                    let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
+                    let value = value.parse::<u64>().map_err(|err| ParseError(format!("Unable to parse {value} as u64: {err}")))?;
+                    let bytes = value.checked_mul(1024 * 1024).ok_or(ParseError(format!("Cache limit of {value} MiB is too large")))?;
+                    result.push(Param::CacheLimit(bytes));
+                }
+                "--resample" => {
+                    let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
+                    let value = value.parse::<u32>().map_err(|err| ParseError(format!("Unable to parse {value} as u32: {err}")))?;
+                    if value == 0 {
+                        return Err(ParseError(format!("Invalid sample rate {value}")));
+                    }
+                    result.push(Param::Resample(value));
+                }
                 "--low-pass" => {
                     let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
                     let value = value.parse::<u32>().map_err(|err| ParseError(format!("Unable to parse {value} as u32: {err}")))?;
@@ -473,48 +884,38 @@ fn parse_params(params: &[String]) -> Result<(std::path::PathBuf, Vec<Param>), P
                     result.push(Param::BakeDifferential(value));
                 }
                 "--note-matcher" => {
-                    result.push(Param::NoteMatcher);
+                    result.push(Param::Pipeline(format!("notes({})", value.unwrap_or_default())));
+                }
+                "--biquad" | "--butterworth" | "--linkwitz-riley" | "--transpose" => {
+                    // The same arguments as the stage of the pipeline:
+                    let value = value.ok_or(ParseError(format!("Missing value for {name}")))?;
+                    result.push(Param::Pipeline(format!("{}({value})", &name[2..])));
+                }
+                "--normalize" | "--compress" | "--limit" | "--gate" | "--vibrato" | "--glide" | "--arpeggio" => {
+                    // All arguments are optional:
+                    let stage = &name[2..];
+                    result.push(Param::Pipeline(format!("{stage}({})", value.unwrap_or_default())));
+                }
+                "--bake-sigma-delta" | "--bake-pwm" => {
+                    let value = value.map(|value| format!(",{value}")).unwrap_or_default();
+                    result.push(Param::Pipeline(format!("bake({}{value})", &name[7..])));
+                }
+                "--pipeline" => {
+                    // The expression contains spaces, so it's usually passed as the next argument:
+                    let value = value
+                        .or_else(|| params.next().map(String::as_str))
+                        .ok_or(ParseError(format!("Missing value for {name}")))?;
+                    result.push(Param::Pipeline(value.to_string()));
                 }
                 "--extract-freq" => {
-                    let mut min = None;
-                    let mut max = None;
-                    let mut sampling = None;
-                    let mut step = None;
-                    let mut channels = None;
-                    if let Some(value) = value {
-                        let parts = value.split(',');
-                        for part in parts {
-                            let mut subparts = part.split('=');
-                            let name = subparts.next().unwrap();
-                            let value = subparts.next().ok_or(ParseError(format!("Missing value for {name}")))?;
-                            match name {
-                                "min" => {
-                                    let value = value.parse::<u32>().map_err(|err| ParseError(format!("Unable to parse {value} as u32: {err}")))?;
-                                    min = Some(value);
-                                }
-                                "max" => {
-                                    let value = value.parse::<u32>().map_err(|err| ParseError(format!("Unable to parse {value} as u32: {err}")))?;
-                                    max = Some(value);
-                                }
-                                "sampling" => {
-                                    let value = value.parse::<u32>().map_err(|err| ParseError(format!("Unable to parse {value} as u32: {err}")))?;
-                                    sampling = Some(value);
-                                }
-                                "step" => {
-                                    let value = value.parse::<u32>().map_err(|err| ParseError(format!("Unable to parse {value} as u32: {err}")))?;
-                                    step = Some(value);
-                                }
-                                "channels" => {
-                                    let value = value.parse::<u8>().map_err(|err| ParseError(format!("Unable to parse {value} as u8: {err}")))?;
-                                    channels = Some(value);
-                                }
-                                _ => {
-                                    return Err(ParseError(format!("Unknown parameter {name}")));
-                                }
-                            }
-                        }
-                    }
-                    result.push(Param::ExtractFreq(min, max, sampling, step, channels));
+                    // The same sub-options as the arguments of the stage:
+                    result.push(Param::Pipeline(format!("extract({})", value.unwrap_or_default())));
+                }
+                "--track-pitch" => {
+                    result.push(Param::Pipeline(format!("pitch({})", value.unwrap_or_default())));
+                }
+                "--extract-notes" => {
+                    result.push(Param::Pipeline(format!("cqt({})", value.unwrap_or_default())));
                 }
                 "--help" => {
                     help::print_help();
@@ -533,23 +934,45 @@ fn parse_params(params: &[String]) -> Result<(std::path::PathBuf, Vec<Param>), P
 }
 
 fn main() -> Result<(), ()> {
-    ctrlc::set_handler(move || {
-        unsafe { STOP_MACHINE.store(true, Ordering::Relaxed) };
-        let _result = mute();
-        std::process::exit(0);
-    }).expect("Unable to set the Ctrl-C handler");
-
     let args: Vec<String> = std::env::args().collect();
 
-    if args.len() == 1 {
-        return mute();
+    if args.get(1).is_some_and(|command| command == "cache") {
+        return manage_cache(&args[2..]);
     }
 
-    let (path, filter_params) = parse_params(&args[1..]).map_err(|err| {
+    let (path, params) = parse_params(&args[1..]).map_err(|err| {
         eprintln!("{err}");
     })?;
 
-    play_generic(&path, filter_params)?;
+    // The speaker is muted through the same ports as it's played, and the rendering doesn't touch them at all:
+    #[cfg(target_os = "linux")]
+    let access = params
+        .iter()
+        .rev()
+        .find_map(|param| if let Param::PortAccess(access) = param { Some(access.clone()) } else { None })
+        .unwrap_or_default();
+    let is_rendering = params.iter().any(|param| matches!(param, Param::Render(_)));
+
+    // Without the file the speaker is just muted:
+    if path.as_os_str().is_empty() {
+        #[cfg(target_os = "linux")]
+        return mute(&access);
+        #[cfg(target_os = "windows")]
+        return mute();
+    }
+
+    ctrlc::set_handler(move || {
+        STOP_MACHINE.store(true, Ordering::Relaxed);
+        if !is_rendering {
+            #[cfg(target_os = "linux")]
+            let _result = mute(&access);
+            #[cfg(target_os = "windows")]
+            let _result = mute();
+        }
+        std::process::exit(0);
+    }).expect("Unable to set the Ctrl-C handler");
+
+    play_generic(&path, params)?;
 
     Ok(())
 }
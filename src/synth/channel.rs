@@ -1,18 +1,24 @@
-use crate::wave::filter::{FreqRecord, FreqData};
+use crate::wave::{filter::{Filter, FreqRecord, FreqData}, transposer::Transposer};
 
 use super::note_record::{NoteRecord, NoteStyle};
 
 pub type Channel = Vec<NoteRecord>;
 
+#[allow(clippy::struct_field_names)]
 pub struct Channels {
     channels: Vec<Channel>,
-    bpm: u16 // Bits per minute
+    bpm: u16, // Bits per minute
+    transposer: Option<Transposer>
 }
 
 impl Channels {
     #[must_use]
     pub fn new(bpm: u16) -> Channels {
-        Channels { channels: Vec::new(), bpm }
+        Channels { channels: Vec::new(), bpm, transposer: None }
+    }
+
+    pub fn set_transposer(&mut self, transposer: Transposer) {
+        self.transposer = Some(transposer);
     }
 
     pub fn push(&mut self, channel: Channel) {
@@ -61,6 +67,12 @@ impl From<Channels> for crate::wave::filter::Data {
             freq_data.push(freq_channel);
         }
 
-        crate::wave::filter::Data::Frequency(freq_data)
+        let data = crate::wave::filter::Data::Frequency(freq_data);
+        let Some(transposer) = &channels.transposer else {
+            return data;
+        };
+
+        // The transposer takes the frequency data, so there is always some output:
+        transposer.filter(data).unwrap_or_else(|| crate::wave::filter::Data::Frequency(FreqData::default()))
     }
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
 pub mod note_record;
 pub mod channel;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod rtttl;
\ No newline at end of file
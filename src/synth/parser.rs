@@ -1,46 +1,53 @@
-///
-/// Supported formats:
-/// 
-///     Comments:
-///         ; Something
-/// 
-///     A standalone note format (square brackets are optional):
-///         [Style]Duration:Note
-/// 
-///     Grouped notes format:
-///         GroupFactor:[Note, Note, Note, ...]
-/// 
-///     Style (optional):
-///         Default is non-legato (without a prefix)
-///         ~ = Legato (a marked note with the next one)
-///         ! = Staccato
-///         . = Prolongated by half of the note
-/// 
-///     Duration (required):
-///         W = Whole
-///         H = Half (1/2 of Whole)
-///         Q = Quarter (1/4 of Whole)
-///         O = Eighth (1/8 of Whole)
-///         S = Sixtinth (1/16 of Whole)
-///         T = Thirty-second (1/32 of Whole)
-///         X = Sixty-fourth (1/64 of Whole)
-///
-///     _____________________________________________
-///     Examples:
-///         !Q:E3 - Play the quarter note E3 using staccato
-///         ~Q:E3 Q:F3 - Play the sequence using legato from E3 to F3
-/// 
-/// #!/bin/beesynth
-/// @name Test
-/// @bpm: 120
-/// @channels: ch1
-/// @ch1: !Q:E3 ~Q:E3 Q:F3
-/// 
+//!
+//! Supported formats:
+//! 
+//!     Comments:
+//!         ; Something
+//! 
+//!     A standalone note format (square brackets are optional):
+//!         [Style]Duration:Note
+//! 
+//!     Grouped notes format:
+//!         GroupFactor:[Note, Note, Note, ...]
+//! 
+//!     Style (optional):
+//!         Default is non-legato (without a prefix)
+//!         ~ = Legato (a marked note with the next one)
+//!         ! = Staccato
+//!         . = Prolongated by half of the note
+//! 
+//!     Duration (required):
+//!         W = Whole
+//!         H = Half (1/2 of Whole)
+//!         Q = Quarter (1/4 of Whole)
+//!         O = Eighth (1/8 of Whole)
+//!         S = Sixtinth (1/16 of Whole)
+//!         T = Thirty-second (1/32 of Whole)
+//!         X = Sixty-fourth (1/64 of Whole)
+//!
+//!     Chord symbols (e.g. Cmaj7) are not notes: a channel is monophonic, so the tones
+//!     of a chord are written in separate channels, the chord and scale names are only
+//!     taken by the arpeggio and notes stages.
+//!
+//!     _____________________________________________
+//!     Examples:
+//!         !Q:E3 - Play the quarter note E3 using staccato
+//!         ~Q:E3 Q:F3 - Play the sequence using legato from E3 to F3
+//! 
+//! #!/bin/beesynth
+//! @name Test
+//! @bpm: 120
+//! @transpose: -12,fit ; Optional, the same arguments as of the transpose stage
+//! @channels: ch1
+//! @ch1: !Q:E3 ~Q:E3 Q:F3
+//! 
 
 use std::{collections::{BTreeMap, BTreeSet}, str::FromStr};
 
 
 
+use crate::wave::{pipeline_parser, transposer::Transposer};
+
 use super::{channel::{Channel, Channels}, note_record::NoteRecord};
 
 
@@ -72,6 +79,7 @@ pub struct Parser<'a> {
     current_channel: (String, Channel),
     name: String,
     bpm: Option<u16>,
+    transposer: Option<Transposer>
 }
 
 impl<'a> Parser<'a> {
@@ -83,6 +91,7 @@ impl<'a> Parser<'a> {
             current_channel: (String::new(), Channel::new()),
             name: String::new(),
             bpm: None,
+            transposer: None
         }
     }
 
@@ -92,7 +101,7 @@ impl<'a> Parser<'a> {
             match note {
                 Ok(note) => self.current_channel.1.push(note),
                 Err(err) => return Err(ParseError::new(format!("Unable to parse the note: {err}")))
-            };
+            }
         }
 
         Ok(())
@@ -114,6 +123,13 @@ impl<'a> Parser<'a> {
                 }
                 self.bpm = Some(bpm);
             },
+            "transpose" => {
+                let transposer = pipeline_parser::parse_transposer(value).map_err(|err| ParseError::new(format!("Invalid transposition {value}: {err}")))?;
+                if self.transposer.is_some() {
+                    return Err(ParseError::new(String::from("Transposition is already set")));
+                }
+                self.transposer = Some(transposer);
+            },
             "channels" => {
                 self.active_channels = value.split(' ').map(ToString::to_string).collect();
             },
@@ -196,6 +212,9 @@ impl<'a> Parser<'a> {
         };
 
         let mut channels = Channels::new(bpm);
+        if let Some(transposer) = self.transposer {
+            channels.set_transposer(transposer);
+        }
         for channel_name in self.active_channels {
             if let Some(channel) = self.channels.remove(&channel_name) {
                 channels.push(channel);
@@ -216,7 +235,7 @@ fn test() {
     use note::Note;
     use crate::synth::note_record::{NoteDivisor, NoteStyle};
 
-    let str = r#"#!/bin/beesynth
+    let str = r"#!/bin/beesynth
         @name  :sample   
         @bpm: 120
 
@@ -227,7 +246,7 @@ fn test() {
 @ch2  : ~E:E4 ~E:0  H:E3
 !Q:E3 ~Q:E3 Q:F3
 @ch1  : !Q:E3 ~Q:E3 Q:F3
-    "#;
+    ";
     
     let channels = Parser::new(str).parse().unwrap();
     assert_eq!(channels.bpm(), 120);
@@ -252,4 +271,15 @@ fn test() {
         NoteRecord::new(Some(Note::E(3)), NoteDivisor::Quarter, NoteStyle::Legato),
         NoteRecord::new(Some(Note::F(3)), NoteDivisor::Quarter, NoteStyle::NonLegato)
     ]);
-}
\ No newline at end of file
+
+    // The transposition is applied to the frequencies of all channels:
+    let transposed = "#!/bin/beesynth\n@bpm: 120\n@transpose: P8\n@channels: ch1\n@ch1: Q:A4 Q:0";
+    let crate::wave::filter::Data::Frequency(freq_data) = Parser::new(transposed).parse().unwrap().into() else {
+        panic!("Unexpected data type");
+    };
+    assert!((freq_data[0][0].freq - 880.0).abs() < 0.01);
+    assert!(freq_data[0][1..].iter().all(|record| record.freq == 0.0));
+
+    assert!(Parser::new("#!/bin/beesynth\n@bpm: 120\n@transpose: P3").parse().is_err());
+    assert!(Parser::new("#!/bin/beesynth\n@bpm: 120\n@transpose: 12\n@transpose: 12").parse().is_err());
+}
@@ -0,0 +1,168 @@
+//!
+//! Ring Tone Text Transfer Language, the single-channel melody format of the old mobile phones:
+//!
+//!     Name:d=4,o=5,b=63:8p,16a#.6,c,32d#6
+//!
+//!     Defaults section (any order, all optional):
+//!         d = Duration of notes without one (1, 2, 4, 8, 16 or 32), 4 by default
+//!         o = Octave of notes without one (4..7), 6 by default
+//!         b = Beats per minute, 63 by default
+//!
+//!     Note: [Duration]Letter[#][.][Octave][.]
+//!         Letter is one of c, d, e, f, g, a, b (h is the German b) or p for a pause.
+//!         The dot prolongates the note by half of its duration.
+//!
+
+use std::str::FromStr;
+
+use note::Note;
+
+use super::{
+    channel::{Channel, Channels},
+    note_record::{NoteDivisor, NoteRecord, NoteStyle},
+    parser::ParseError
+};
+
+const DEFAULT_DURATION: &str = "4";
+const DEFAULT_OCTAVE: u8 = 6;
+const DEFAULT_BPM: u16 = 63;
+
+struct Defaults {
+    divisor: NoteDivisor,
+    octave: u8,
+    bpm: u16
+}
+
+fn parse_defaults(section: &str) -> Result<Defaults, ParseError> {
+    let mut defaults = Defaults {
+        divisor: NoteDivisor::from_str(DEFAULT_DURATION).map_err(ParseError::new)?,
+        octave: DEFAULT_OCTAVE,
+        bpm: DEFAULT_BPM
+    };
+
+    for entry in section.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let Some((name, value)) = entry.split_once('=') else {
+            return Err(ParseError::new(format!("Invalid default value: {entry}")));
+        };
+
+        let value = value.trim();
+        match name.trim() {
+            "d" => defaults.divisor = NoteDivisor::from_str(value).map_err(ParseError::new)?,
+            "o" => defaults.octave = value.parse().map_err(|_| ParseError::new(format!("Invalid octave: {value}")))?,
+            "b" => defaults.bpm = value.parse()
+                .ok()
+                .filter(|bpm| *bpm > 0)
+                .ok_or_else(|| ParseError::new(format!("Invalid BPM value: {value}")))?,
+            _ => return Err(ParseError::new(format!("Unknown default value: {entry}")))
+        }
+    }
+
+    Ok(defaults)
+}
+
+fn parse_note(token: &str, defaults: &Defaults) -> Result<NoteRecord, ParseError> {
+    let invalid = || ParseError::new(format!("Invalid note: {token}"));
+
+    let duration_length = token.chars().take_while(char::is_ascii_digit).count();
+    let divisor = if duration_length == 0 {
+        defaults.divisor
+    } else {
+        NoteDivisor::from_str(&token[..duration_length]).map_err(ParseError::new)?
+    };
+
+    let mut rest = token[duration_length..].chars().peekable();
+    let semitone = match rest.next().map(|letter| letter.to_ascii_lowercase()) {
+        Some('p') => None,
+        Some('c') => Some(0),
+        Some('d') => Some(2),
+        Some('e') => Some(4),
+        Some('f') => Some(5),
+        Some('g') => Some(7),
+        Some('a') => Some(9),
+        Some('b' | 'h') => Some(11),
+        _ => return Err(invalid())
+    };
+
+    let is_sharp = rest.next_if_eq(&'#').is_some();
+    let mut is_dotted = rest.next_if_eq(&'.').is_some();
+
+    let octave = rest
+        .next_if(char::is_ascii_digit)
+        .and_then(|digit| digit.to_digit(10))
+        .and_then(|octave| u8::try_from(octave).ok())
+        .unwrap_or(defaults.octave);
+
+    // The dot is allowed both before and after the octave:
+    is_dotted |= rest.next_if_eq(&'.').is_some();
+    if rest.next().is_some() {
+        return Err(invalid());
+    }
+
+    let style = if is_dotted { NoteStyle::Prolongate } else { NoteStyle::NonLegato };
+    let note = semitone
+        .map(|semitone| octave
+            .checked_mul(Note::SEMITONE_COUNT)
+            .and_then(|base| base.checked_add(semitone + u8::from(is_sharp)))
+            .map(Note::from_semitone)
+            .ok_or_else(invalid)
+        )
+        .transpose()?;
+
+    Ok(NoteRecord::new(note, divisor, style))
+}
+
+///
+/// Whether the token is a valid note, the defaults don't matter here.
+///
+#[must_use]
+pub fn is_note(token: &str) -> bool {
+    let defaults = Defaults { divisor: NoteDivisor::Quarter, octave: DEFAULT_OCTAVE, bpm: DEFAULT_BPM };
+    parse_note(token.trim(), &defaults).is_ok()
+}
+
+///
+/// Parses the whole RTTTL listing into a single channel.
+///
+pub fn parse(listing: &str) -> Result<Channels, ParseError> {
+    let mut sections = listing.trim().splitn(3, ':');
+    let (Some(_name), Some(defaults), Some(notes)) = (sections.next(), sections.next(), sections.next()) else {
+        return Err(ParseError::new(String::from("RTTTL must consist of the name, defaults and notes sections")));
+    };
+
+    let defaults = parse_defaults(defaults)?;
+    let channel = notes
+        .split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(|token| parse_note(token, &defaults))
+        .collect::<Result<Channel, ParseError>>()?;
+
+    if channel.is_empty() {
+        return Err(ParseError::new(String::from("There are no notes")));
+    }
+
+    let mut channels = Channels::new(defaults.bpm);
+    channels.push(channel);
+    Ok(channels)
+}
+
+
+
+#[test]
+fn test() {
+    let channels = parse("Test:d=4,o=5,b=120:8c6,p,16d#.,a.4,h").unwrap();
+    assert_eq!(channels.bpm(), 120);
+
+    let channel = &channels.channels()[0];
+    assert_eq!(channel, &vec![
+        NoteRecord::new(Some(Note::C(6)), NoteDivisor::Eighth, NoteStyle::NonLegato),
+        NoteRecord::new(None, NoteDivisor::Quarter, NoteStyle::NonLegato),
+        NoteRecord::new(Some(Note::Ds(5)), NoteDivisor::Sixtinth, NoteStyle::Prolongate),
+        NoteRecord::new(Some(Note::A(4)), NoteDivisor::Quarter, NoteStyle::Prolongate),
+        NoteRecord::new(Some(Note::B(5)), NoteDivisor::Quarter, NoteStyle::NonLegato)
+    ]);
+
+    assert!(parse("Test:d=4,o=5,b=120:").is_err());
+    assert!(parse("Test:d=3:c").is_err());
+    assert!(parse("Test::x").is_err());
+}
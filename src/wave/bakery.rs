@@ -1,73 +1,163 @@
 use crate::wave::filter::PositionRecord;
 
-use super::filter::{Filter, Type, Data, PositionData, Position};
+use super::filter::{Filter, StreamFilter, Type, Data, HertzInt, Nsec, PositionData, Position};
 
 pub type Percentage = u8;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modulator {
+    FirstOrder,
+    SecondOrder // Pushes more quantization noise to high frequencies, but needs more headroom
+}
+
 pub enum Strategy {
     Simple, // Up if the sample is > 0, Down otherwise
-    Differential(Percentage)
+    Differential(Percentage),
+    SigmaDelta {         // 1-bit noise-shaped quantization: the density of Up positions encodes the amplitude
+        modulator: Modulator,
+        rate: HertzInt   // Oversampled rate of the modulator, the samples are interpolated linearly
+    },
+    Pwm {                // Pulse of each carrier period is as wide as the amplitude, the carrier itself is inaudible
+        carrier_hz: HertzInt,
+        levels: u16      // Number of distinct pulse widths, at least 2
+    }
 }
 
-pub struct Bakery {
-    strategy: Strategy
+///
+/// Baking state that survives between chunks:
+/// the previous sample and the last record which may be prolonged by the next chunk.
+///
+#[derive(Default)]
+struct Oven {
+    previous: f32,
+    last: Option<PositionRecord>,
+    integrators: [f32; 2], // State of the sigma-delta modulator
+    phase: f64,            // Position of the next tick (modulator step or carrier period) between the previous and the current samples
+    ticks: u64             // Ticks since the beginning, their durations are rounded without drift
 }
 
-impl Bakery {
-    pub fn new(strategy: Strategy) -> Self {
-        Self { strategy }
+///
+/// Width of the Up part of the carrier period.
+/// Both parts are either absent or at least `min_pulse_ns` long, so the backend is able to toggle the speaker in time,
+/// a period too short for two pulses is a single one.
+///
+fn pulse_width(sample: f32, period_ns: Nsec, levels: u16, min_pulse_ns: Nsec) -> Nsec {
+    let steps = u64::from(levels.max(2) - 1);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    let level = (f32::midpoint(sample.clamp(-1.0, 1.0), 1.0) * steps as f32).round() as u64;
+    let mut width = period_ns * level / steps;
+
+    // The period doesn't fit both pulses, so it's either completely Up or Down:
+    if period_ns < min_pulse_ns.saturating_mul(2) {
+        return if width * 2 < period_ns { 0 } else { period_ns };
     }
+
+    // Too short pulses are rounded to the nearest feasible width:
+    if width < min_pulse_ns {
+        width = if width * 2 < min_pulse_ns { 0 } else { min_pulse_ns };
+    }
+
+    let gap = period_ns - width;
+    if gap < min_pulse_ns {
+        width = if gap * 2 < min_pulse_ns { period_ns } else { period_ns.saturating_sub(min_pulse_ns) };
+    }
+
+    width
 }
 
-impl Filter for Bakery {
-    fn filter_type(&self) -> Type {
-        Type::Amplitude
+impl Oven {
+    fn push(&mut self, position: Position, duration: u64, position_data: &mut PositionData) {
+        match self.last {
+            Some(ref mut last) if last.position == position => last.duration += duration,
+            Some(last) => {
+                position_data.push(last);
+                self.last = Some(PositionRecord { position, duration });
+            },
+            None => self.last = Some(PositionRecord { position, duration })
+        }
     }
 
-    fn filter(&self, data: Data) -> Option<Data> {
-        const NS_IN_SEC: u64 = 1_000_000_000;
+    fn prolong(&mut self, duration: u64) {
+        if let Some(ref mut last) = self.last {
+            last.duration += duration;
+        }
+    }
 
-        let Data::Amplitude(wave) = data else {
-            return None;
-        };
+    fn sigma_delta(&mut self, modulator: Modulator, sample: f32) -> Position {
+        const LIMIT: f32 = 4.0; // Integrators are clamped to recover from the overload quickly
+
+        let feedback = if self.integrators[modulator as usize] >= 0.0 { 1.0 } else { -1.0 };
+        let input = sample.clamp(-1.0, 1.0);
+
+        self.integrators[0] = (self.integrators[0] + input - feedback).clamp(-LIMIT, LIMIT);
+        if modulator == Modulator::SecondOrder {
+            self.integrators[1] = (self.integrators[1] + self.integrators[0] - feedback).clamp(-LIMIT, LIMIT);
+        }
+
+        if self.integrators[modulator as usize] >= 0.0 {
+            Position::Up
+        } else {
+            Position::Down
+        }
+    }
+
+    fn tick_duration(&mut self, rate: HertzInt) -> u64 {
+        let rate = u128::from(rate);
+        let start = u128::from(self.ticks) * u128::from(NS_IN_SEC) / rate;
+        self.ticks += 1;
+        let end = u128::from(self.ticks) * u128::from(NS_IN_SEC) / rate;
 
-        let sample_duration_ns = NS_IN_SEC / u64::from(wave.sample_rate);
+        u64::try_from(end - start).unwrap_or(u64::MAX)
+    }
+
+    ///
+    /// Calls `tick` with the linearly interpolated sample at each tick of the given rate.
+    ///
+    fn oversample(
+        &mut self,
+        samples: &[f32],
+        sample_rate: u32,
+        rate: HertzInt,
+        position_data: &mut PositionData,
+        mut tick: impl FnMut(&mut Self, f32, Nsec, &mut PositionData))
+    {
+        let step = f64::from(sample_rate) / f64::from(rate.max(1));
+        for &sample in samples {
+            while self.phase < 1.0 {
+                #[allow(clippy::cast_possible_truncation)]
+                let value = self.previous + (sample - self.previous) * self.phase as f32;
+                let duration = self.tick_duration(rate);
+                tick(self, value, duration, position_data);
+                self.phase += step;
+            }
+
+            self.phase -= 1.0;
+            self.previous = sample;
+        }
+    }
+
+    fn bake(&mut self, strategy: &Strategy, min_pulse_ns: Nsec, samples: &[f32], sample_rate: u32) -> PositionData {
+        let sample_duration_ns = NS_IN_SEC / u64::from(sample_rate);
         let mut position_data = PositionData::default();
 
-        match self.strategy {
+        match *strategy {
             Strategy::Simple => {
-                for sample in wave.samples {
+                for &sample in samples {
                     let position = if sample > 0.0_f32 {
                         Position::Up
                     } else {
                         Position::Down
                     };
 
-                    if let Some(last) = position_data.last_mut() {
-                        if last.position == position {
-                            last.duration += sample_duration_ns;
-                        } else {
-                            position_data.push(PositionRecord {
-                                position,
-                                duration: sample_duration_ns
-                            });
-                        }
-                    } else {
-                        position_data.push(PositionRecord {
-                            position,
-                            duration: sample_duration_ns
-                        });
-                    }
+                    self.push(position, sample_duration_ns, &mut position_data);
                 }
             },
             Strategy::Differential(switch_percentage) => {
-                let mut previous = 0.0_f32;
+                for &sample in samples {
+                    if self.last.is_some() {
+                        let diff = sample - self.previous;
+                        let diff_percentage = (self.previous + diff) * 100_f32 / self.previous;
 
-                for sample in wave.samples {
-                    if let Some(last) = position_data.last_mut() {
-                        let diff = sample - previous;
-                        let diff_percentage = (previous + diff) * 100_f32 / previous;
-                        
                         if diff_percentage > f32::from(switch_percentage) {
                             let position = if diff > 0.0_f32 {
                                 Position::Up
@@ -75,16 +165,9 @@ impl Filter for Bakery {
                                 Position::Down
                             };
 
-                            if last.position == position {
-                                last.duration += sample_duration_ns;
-                            } else {
-                                position_data.push(PositionRecord {
-                                    position,
-                                    duration: sample_duration_ns
-                                });
-                            }
+                            self.push(position, sample_duration_ns, &mut position_data);
                         } else {
-                            last.duration += sample_duration_ns;
+                            self.prolong(sample_duration_ns);
                         }
                     } else {
                         let position = if sample > 0.0_f32 {
@@ -93,17 +176,222 @@ impl Filter for Bakery {
                             Position::Down
                         };
 
-                        position_data.push(PositionRecord {
-                            position,
-                            duration: sample_duration_ns
-                        });
+                        self.push(position, sample_duration_ns, &mut position_data);
                     }
 
-                    previous = sample;
+                    self.previous = sample;
                 }
+            },
+            Strategy::SigmaDelta { modulator, rate } => {
+                self.oversample(samples, sample_rate, rate, &mut position_data, |oven, value, duration, position_data| {
+                    let position = oven.sigma_delta(modulator, value);
+                    oven.push(position, duration, position_data);
+                });
+            },
+            Strategy::Pwm { carrier_hz, levels } => {
+                self.oversample(samples, sample_rate, carrier_hz, &mut position_data, |oven, value, period_ns, position_data| {
+                    let width = pulse_width(value, period_ns, levels, min_pulse_ns);
+                    if width > 0 {
+                        oven.push(Position::Up, width, position_data);
+                    }
+                    if width < period_ns {
+                        oven.push(Position::Down, period_ns - width, position_data);
+                    }
+                });
             }
         }
 
+        position_data
+    }
+
+    fn finish(&mut self) -> PositionData {
+        let last = self.last.take();
+        *self = Self::default();
+        last.into_iter().collect()
+    }
+}
+
+pub struct Bakery {
+    strategy: Strategy,
+    min_pulse_ns: Nsec, // Shortest Up or Down the backend is able to play
+    oven: Oven
+}
+
+impl Bakery {
+    pub fn new(strategy: Strategy) -> Self {
+        Self { strategy, min_pulse_ns: 0, oven: Oven::default() }
+    }
+
+    ///
+    /// Limits the width of pulses made by the strategy,
+    /// the port access through the kernel toggles the speaker much slower than the direct one.
+    ///
+    #[must_use]
+    pub fn with_min_pulse(mut self, min_pulse_ns: Nsec) -> Self {
+        self.min_pulse_ns = min_pulse_ns;
+        self
+    }
+}
+
+const NS_IN_SEC: u64 = 1_000_000_000;
+
+impl Filter for Bakery {
+    fn input_type(&self) -> Type {
+        Type::Amplitude
+    }
+
+    fn output_type(&self) -> Type {
+        Type::Position
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = data else {
+            return None;
+        };
+
+        let mut oven = Oven::default();
+        let mut position_data = oven.bake(&self.strategy, self.min_pulse_ns, &wave.samples, wave.sample_rate);
+        position_data.extend(oven.finish());
+
         Some(Data::Position(position_data))
     }
-}
\ No newline at end of file
+}
+
+impl StreamFilter for Bakery {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = chunk else {
+            return None;
+        };
+
+        Some(Data::Position(self.oven.bake(&self.strategy, self.min_pulse_ns, &wave.samples, wave.sample_rate)))
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        Some(Data::Position(self.oven.finish()))
+    }
+}
+
+
+
+#[test]
+#[allow(clippy::cast_precision_loss)]
+fn test() {
+    use super::filter::WaveData;
+
+    const SAMPLE_RATE: u32 = 8000;
+    const RATE: u32 = 64000;
+
+    // Share of the Up time within each window of the given duration:
+    let densities = |records: &PositionData, window_ns: u64| -> Vec<f32> {
+        let mut densities = Vec::new();
+        let (mut up, mut total) = (0, 0);
+        for record in records {
+            let mut duration = record.duration;
+            while duration > 0 {
+                let part = duration.min(window_ns - total);
+                if record.position == Position::Up {
+                    up += part;
+                }
+                total += part;
+                duration -= part;
+
+                if total == window_ns {
+                    densities.push(up as f32 / total as f32);
+                    (up, total) = (0, 0);
+                }
+            }
+        }
+        densities
+    };
+
+    for modulator in [Modulator::FirstOrder, Modulator::SecondOrder] {
+        let strategy = Strategy::SigmaDelta { modulator, rate: RATE };
+
+        // Constant level: 0.5 is Up for 75% of the time:
+        let constant = WaveData { samples: vec![0.5; SAMPLE_RATE as usize], sample_rate: SAMPLE_RATE };
+        let Some(Data::Position(records)) = Bakery::new(strategy).filter(Data::Amplitude(constant)) else {
+            panic!("Unexpected data type");
+        };
+
+        assert_eq!(records.iter().map(|record| record.duration).sum::<u64>(), NS_IN_SEC);
+        assert!(records.windows(2).all(|pair| pair[0].position != pair[1].position));
+        let density = densities(&records, NS_IN_SEC / 10);
+        assert!(density.iter().all(|density| (density - 0.75).abs() < 0.01), "{modulator:?}: {density:?}");
+
+        // The density follows the slow sine:
+        let sine = WaveData {
+            samples: (0..SAMPLE_RATE).map(|index| 0.5 * (std::f32::consts::TAU * 10.0 * index as f32 / SAMPLE_RATE as f32).sin()).collect(),
+            sample_rate: SAMPLE_RATE
+        };
+        let mut bakery = Bakery::new(Strategy::SigmaDelta { modulator, rate: RATE });
+        let mut records = PositionData::new();
+        for chunk in sine.samples.chunks(1000) {
+            if let Some(Data::Position(baked)) = bakery.feed(Data::Amplitude(WaveData { samples: chunk.to_vec(), sample_rate: SAMPLE_RATE })) {
+                records.extend(baked);
+            }
+        }
+        if let Some(Data::Position(baked)) = bakery.finish() {
+            records.extend(baked);
+        }
+
+        let window_ns = NS_IN_SEC / 200;
+        for (index, density) in densities(&records, window_ns).into_iter().enumerate() {
+            let time = (index as f32 + 0.5) * window_ns as f32 / NS_IN_SEC as f32;
+            let expected = 0.5 + 0.25 * (std::f32::consts::TAU * 10.0 * time).sin();
+            assert!((density - expected).abs() < 0.05, "{modulator:?} at {time}: {density} vs {expected}");
+        }
+    }
+    // Each carrier period is Up for 3/4 of it, the first sample interval rises from the silence:
+    let constant = WaveData { samples: vec![0.5; 800], sample_rate: SAMPLE_RATE };
+    let bakery = Bakery::new(Strategy::Pwm { carrier_hz: 20_000, levels: 5 });
+    let Some(Data::Position(records)) = bakery.filter(Data::Amplitude(constant)) else {
+        panic!("Unexpected data type");
+    };
+    assert_eq!(records.len(), 2 * 2000);
+    assert!(records[6..].chunks(2).all(|pair| pair[0].position == Position::Up && pair[0].duration == 37_500 && pair[1].duration == 12_500));
+
+    // Streaming by uneven chunks gives the same records as the whole wave:
+    let sine = (0..SAMPLE_RATE / 4).map(|index| 0.8 * (std::f32::consts::TAU * 440.0 * index as f32 / SAMPLE_RATE as f32).sin()).collect::<Vec<f32>>();
+    let records = |data: Option<Data>| -> Vec<(bool, Nsec)> {
+        let Some(Data::Position(records)) = data else {
+            panic!("Unexpected data type");
+        };
+        records.iter().map(|record| (record.position == Position::Up, record.duration)).collect()
+    };
+    let strategies = || [
+        Strategy::Simple,
+        Strategy::Differential(5),
+        Strategy::SigmaDelta { modulator: Modulator::SecondOrder, rate: RATE },
+        Strategy::Pwm { carrier_hz: 20_000, levels: 16 }
+    ];
+    for (batch_strategy, stream_strategy) in strategies().into_iter().zip(strategies()) {
+        let batch = records(Bakery::new(batch_strategy).filter(Data::Amplitude(WaveData { samples: sine.clone(), sample_rate: SAMPLE_RATE })));
+
+        let mut bakery = Bakery::new(stream_strategy);
+        let mut streamed = Vec::new();
+        for chunk in sine.chunks(333) {
+            streamed.extend(records(bakery.feed(Data::Amplitude(WaveData { samples: chunk.to_vec(), sample_rate: SAMPLE_RATE }))));
+        }
+        streamed.extend(bakery.finish().map_or_else(Vec::new, |data| records(Some(data))));
+
+        // The same position may be split between the chunks:
+        let merged = |records: Vec<(bool, Nsec)>| records.into_iter().fold(Vec::new(), |mut merged: Vec<(bool, Nsec)>, (is_up, duration)| {
+            match merged.last_mut() {
+                Some((last, total)) if *last == is_up => *total += duration,
+                _ => merged.push((is_up, duration))
+            }
+            merged
+        });
+        assert_eq!(merged(streamed), merged(batch));
+    }
+
+    // Pulses shorter than the backend allows are either dropped or widened:
+    assert_eq!(pulse_width(-0.95, 50_000, 64, 10_000), 0);
+    assert_eq!(pulse_width(-0.7, 50_000, 64, 10_000), 10_000);
+    assert_eq!(pulse_width(0.7, 50_000, 64, 10_000), 40_000);
+    assert_eq!(pulse_width(0.95, 50_000, 64, 10_000), 50_000);
+    assert_eq!(pulse_width(0.0, 50_000, 3, 10_000), 25_000);
+    assert_eq!(pulse_width(-0.2, 15_000, 64, 10_000), 0);
+    assert_eq!(pulse_width(0.2, 15_000, 64, 10_000), 15_000);
+    assert_eq!(pulse_width(0.2, 5_000, 64, 10_000), 5_000);
+}
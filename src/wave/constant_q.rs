@@ -0,0 +1,354 @@
+use std::sync::Arc;
+
+use note::Note;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use super::{
+    filter::{Type, Data, Filter, StreamFilter, FreqRecord, FreqChannel, FreqData, Nsec},
+    partials::{Partials, Peak},
+    parallel
+};
+
+///
+/// Constant-Q transform (Brown and Puckette, 1992): the bins are spaced by the fraction
+/// of a semitone from the lowest note to the highest one and each bin has the window
+/// of the same number of periods, so the bass is resolved as well as the treble.
+/// The peaks are snapped to the nearest notes, so the output is already on the musical grid.
+///
+pub struct ConstantQ {
+    lowest: Note,
+    highest: Note,
+    bins_per_octave: u8, // Multiple of 12, so each semitone has a bin at its pitch
+    step_by: u32,        // In samples
+    number_of_peaks: u8,
+    min_track_ms: u32,   // Voices which don't last as long are dropped
+    silence_db: f32,     // Peaks quieter than this level in dBFS are dropped
+    stream: Option<Stream>
+}
+
+///
+/// Spectral kernels of all bins: the FFT of the frame multiplied by the kernel
+/// gives the bin, the kernels are sparse as each one has a narrow passband.
+///
+struct Kernels {
+    fft_processor: Arc<dyn Fft<f32>>,
+    bins: Vec<Vec<(usize, Complex<f32>)>>,
+    lowest_freq: f32
+}
+
+///
+/// State of the incremental extraction: samples that haven't been covered
+/// by the frame yet and the tracks which continue in the next chunk.
+///
+struct Stream {
+    kernels: Kernels,
+    samples: Vec<f32>,
+    skip: usize, // Samples of the next chunks to skip if the step is longer than the frame
+    frames: u64, // Analyzed so far
+    partials: Partials
+}
+
+const NANOS_IN_SEC: u64 = 1_000_000_000;
+const SPARSITY: f32 = 0.005; // Parts of the kernel weaker than this fraction of its peak are dropped
+const NYQUIST_MARGIN: f32 = 0.45; // Bins above this fraction of the sample rate are dropped
+
+impl Kernels {
+    fn new(extractor: &ConstantQ, sample_rate: u32) -> Self {
+        #![allow(clippy::cast_precision_loss)]
+        #![allow(clippy::cast_possible_truncation)]
+        #![allow(clippy::cast_sign_loss)]
+
+        let bins_per_octave = f32::from(extractor.bins_per_octave);
+        let quality = 1.0 / (2_f32.powf(1.0 / bins_per_octave) - 1.0);
+        let lowest_freq = extractor.lowest.freq();
+        let semitones = usize::try_from(extractor.highest.position() - extractor.lowest.position()).unwrap_or_default();
+        let bin_count = semitones * usize::from(extractor.bins_per_octave / 12) + 1;
+
+        let freqs = (0..bin_count)
+            .map(|bin| lowest_freq * 2_f32.powf(bin as f32 / bins_per_octave))
+            .take_while(|freq| *freq < NYQUIST_MARGIN * sample_rate as f32)
+            .collect::<Vec<f32>>();
+
+        // The lowest bin has the longest window, it defines the size of the frame:
+        let longest = (quality * sample_rate as f32 / lowest_freq).ceil() as usize;
+        let size = longest.next_power_of_two();
+        let fft_processor = FftPlanner::new().plan_fft_forward(size);
+
+        let bins = freqs
+            .iter()
+            .map(|freq| {
+                // The windows of all bins are centred in the frame, so they describe the same moment:
+                let length = ((quality * sample_rate as f32 / freq).ceil() as usize).min(size);
+                let start = (size - length) / 2;
+                let weights = (0..length)
+                    .map(|index| 0.5 - 0.5 * (std::f32::consts::TAU * index as f32 / length as f32).cos())
+                    .collect::<Vec<f32>>();
+                let total = weights.iter().sum::<f32>();
+
+                let mut kernel = vec![Complex::default(); size];
+                for (index, weight) in weights.iter().enumerate() {
+                    let phase = std::f32::consts::TAU * freq * index as f32 / sample_rate as f32;
+                    kernel[start + index] = Complex::from_polar(weight / total, phase);
+                }
+                fft_processor.process(&mut kernel);
+
+                // Parseval: sum(x * conj(k)) = sum(X * conj(K)) / size
+                let peak = kernel.iter().map(|value| value.norm()).fold(0.0, f32::max);
+                kernel
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, value)| value.norm() >= SPARSITY * peak)
+                    .map(|(index, value)| (index, value.conj() / size as f32))
+                    .collect()
+            })
+            .collect();
+
+        Self { fft_processor, bins, lowest_freq }
+    }
+
+    fn frame_size(&self) -> usize {
+        self.fft_processor.len()
+    }
+}
+
+impl ConstantQ {
+    #[must_use]
+    pub fn new(lowest: Note, highest: Note, bins_per_octave: u8, step_by: u32, number_of_peaks: u8) -> Self {
+        Self {
+            lowest,
+            highest,
+            bins_per_octave: bins_per_octave.max(12) / 12 * 12,
+            step_by,
+            number_of_peaks,
+            min_track_ms: 0,
+            silence_db: f32::NEG_INFINITY,
+            stream: None
+        }
+    }
+
+    ///
+    /// Each channel follows a single voice, the voices shorter than the given duration
+    /// are replaced with pauses.
+    ///
+    #[must_use]
+    pub fn with_min_track(mut self, min_track_ms: u32) -> Self {
+        self.min_track_ms = min_track_ms;
+        self
+    }
+
+    ///
+    /// Peaks quieter than the given level in dBFS are dropped, a full-scale sine is at 0 dBFS.
+    ///
+    #[must_use]
+    pub fn with_silence(mut self, silence_db: f32) -> Self {
+        self.silence_db = silence_db;
+        self
+    }
+
+    ///
+    /// Duration of the given frame, the frames end at the exact multiples of the step.
+    ///
+    fn frame_duration(&self, frame: u64, sample_rate: u32) -> Nsec {
+        let end = |frame: u64| u128::from(frame) * u128::from(self.step_by) * u128::from(NANOS_IN_SEC) / u128::from(sample_rate);
+        Nsec::try_from(end(frame + 1) - end(frame)).unwrap_or(Nsec::MAX)
+    }
+
+    ///
+    /// Each frame sounds at the centre of its window, so the channels start with a half of the frame of silence.
+    ///
+    fn lead_in(kernels: &Kernels, sample_rate: u32, channels: &mut FreqData<f32>) {
+        let duration = NANOS_IN_SEC * (kernels.frame_size() / 2) as u64 / u64::from(sample_rate);
+        for channel in channels {
+            channel.push(FreqRecord { freq: 0.0, duration });
+        }
+    }
+
+    ///
+    /// Peaks of the frame snapped to the notes, the loudest first.
+    ///
+    fn extract_peaks(&self, kernels: &Kernels, frame: &[f32]) -> Vec<Peak> {
+        #![allow(clippy::cast_precision_loss)]
+
+        let mut spectrum = frame.iter().map(|sample| Complex::new(*sample, 0_f32)).collect::<Vec<Complex<f32>>>();
+        kernels.fft_processor.process(&mut spectrum);
+
+        // A full-scale sine gives the half in its bin:
+        let magnitudes = kernels.bins
+            .iter()
+            .map(|kernel| kernel.iter().map(|(index, weight)| spectrum[*index] * weight).sum::<Complex<f32>>())
+            .map(|bin| 20.0 * (2.0 * bin.norm() + f32::MIN_POSITIVE).log10())
+            .collect::<Vec<f32>>();
+
+        let bins_per_octave = f32::from(self.bins_per_octave);
+        let mut peaks = Vec::<(Note, Peak)>::new();
+
+        for bin in 1..magnitudes.len().saturating_sub(1) {
+            let (left, centre, right) = (magnitudes[bin - 1], magnitudes[bin], magnitudes[bin + 1]);
+            if centre <= left || centre < right || centre < self.silence_db {
+                continue;
+            }
+
+            let offset = 0.5 * (left - right) / (left - 2.0 * centre + right);
+            let offset = if offset.is_finite() { offset.clamp(-1.0, 1.0) } else { 0.0 };
+            let note = Note::find_nearest(kernels.lowest_freq * 2_f32.powf((bin as f32 + offset) / bins_per_octave));
+
+            // Finer bins may give several peaks within the same semitone:
+            match peaks.iter_mut().find(|(found, _)| *found == note) {
+                Some((_, peak)) => peak.magnitude_db = peak.magnitude_db.max(centre),
+                None => peaks.push((note, Peak { freq: note.freq(), magnitude_db: centre }))
+            }
+        }
+
+        let mut peaks = peaks.into_iter().map(|(_, peak)| peak).collect::<Vec<Peak>>();
+        peaks.sort_by(|left, right| right.magnitude_db.total_cmp(&left.magnitude_db));
+        peaks
+    }
+
+    ///
+    /// Peaks of all complete frames of the samples analyzed on all cores,
+    /// returns them along with the number of samples the frames have been shifted by.
+    ///
+    fn analyze(&self, kernels: &Kernels, samples: &[f32]) -> (Vec<Vec<Peak>>, usize) {
+        let frame_size = kernels.frame_size();
+        let step_by = (self.step_by as usize).max(1);
+
+        let frames = (0..)
+            .step_by(step_by)
+            .take_while(|offset| offset + frame_size <= samples.len())
+            .map(|offset| &samples[offset..offset + frame_size])
+            .collect::<Vec<&[f32]>>();
+
+        let peaks = parallel::map(&frames, |frame| self.extract_peaks(kernels, frame));
+        (peaks, frames.len() * step_by)
+    }
+
+    fn channels(&self) -> FreqData<f32> {
+        let mut channels = FreqData::default();
+        channels.resize_with(usize::from(self.number_of_peaks), FreqChannel::default);
+        channels
+    }
+
+    fn partials(&self, sample_rate: u32) -> Partials {
+        let frame_ms = u64::from(self.step_by.max(1)) * 1000 / u64::from(sample_rate);
+        let min_frames = u64::from(self.min_track_ms).div_ceil(frame_ms.max(1));
+        Partials::new(usize::from(self.number_of_peaks), u32::try_from(min_frames).unwrap_or(u32::MAX))
+    }
+}
+
+impl Filter for ConstantQ {
+    fn input_type(&self) -> Type {
+        Type::Amplitude
+    }
+
+    fn output_type(&self) -> Type {
+        Type::Frequency
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = data else {
+            return None;
+        };
+
+        let kernels = Kernels::new(self, wave.sample_rate);
+        let mut partials = self.partials(wave.sample_rate);
+        let mut channels = self.channels();
+        Self::lead_in(&kernels, wave.sample_rate, &mut channels);
+
+        let (frames, _) = self.analyze(&kernels, &wave.samples);
+        for (frame, peaks) in (0..).zip(frames) {
+            partials.advance(&peaks, self.frame_duration(frame, wave.sample_rate), &mut channels);
+        }
+
+        partials.finish(&mut channels);
+        Some(Data::Frequency(channels))
+    }
+}
+
+impl StreamFilter for ConstantQ {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = chunk else {
+            return None;
+        };
+
+        let mut channels = self.channels();
+        let mut stream = self.stream.take().unwrap_or_else(|| {
+            let kernels = Kernels::new(self, wave.sample_rate);
+            Self::lead_in(&kernels, wave.sample_rate, &mut channels);
+
+            Stream {
+                samples: Vec::with_capacity(kernels.frame_size() * 2),
+                kernels,
+                skip: 0,
+                frames: 0,
+                partials: self.partials(wave.sample_rate)
+            }
+        });
+
+        let skipped = stream.skip.min(wave.samples.len());
+        stream.skip -= skipped;
+        stream.samples.extend_from_slice(&wave.samples[skipped..]);
+
+        let (frames, offset) = self.analyze(&stream.kernels, &stream.samples);
+        for peaks in frames {
+            stream.partials.advance(&peaks, self.frame_duration(stream.frames, wave.sample_rate), &mut channels);
+            stream.frames += 1;
+        }
+
+        let drained = offset.min(stream.samples.len());
+        stream.samples.drain(..drained);
+        stream.skip += offset - drained;
+        self.stream = Some(stream);
+
+        Some(Data::Frequency(channels))
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        let mut stream = self.stream.take()?;
+
+        let mut channels = self.channels();
+        stream.partials.finish(&mut channels);
+        Some(Data::Frequency(channels))
+    }
+}
+
+
+
+#[test]
+#[allow(clippy::cast_precision_loss)]
+fn test() {
+    use super::filter::WaveData;
+    use super::freq_extractor::FreqExtractor;
+    use super::note_matcher::NoteMatcher;
+
+    const SAMPLE_RATE: u32 = 22050;
+
+    // A minor third in the bass with the A 15 cents sharp, and the melody:
+    let notes = [Note::A(1).freq() * 2_f32.powf(15.0 / 1200.0), Note::C(2).freq(), Note::E(5).freq()];
+    let samples = (0..2 * SAMPLE_RATE)
+        .map(|index| {
+            let time = index as f32 / SAMPLE_RATE as f32;
+            notes.iter().map(|freq| 0.25 * (std::f32::consts::TAU * freq * time).sin()).sum()
+        })
+        .collect::<Vec<f32>>();
+
+    let tones = |data: Option<Data>| -> Vec<f32> {
+        let Some(Data::Frequency(channels)) = data else {
+            panic!("Unexpected data type");
+        };
+
+        let mut tones = channels.iter().flatten().map(|record| record.freq).filter(|freq| *freq > 0.0).collect::<Vec<f32>>();
+        tones.sort_by(f32::total_cmp);
+        tones.dedup();
+        tones
+    };
+
+    // Both bass notes are resolved and all peaks are the exact pitches of the notes:
+    let extractor = ConstantQ::new(Note::G(1), Note::C(7), 24, 512, 3).with_silence(-40.0);
+    let wave = || Data::Amplitude(WaveData { samples: samples.clone(), sample_rate: SAMPLE_RATE });
+    let expected = [Note::A(1).freq(), Note::C(2).freq(), Note::E(5).freq()];
+    assert_eq!(tones(extractor.filter(wave())), expected);
+
+    // While the linear bins of the usual window are too wide for the bass, its notes are wrong:
+    let linear = FreqExtractor::new(Some(30), Some(2000), 4096, 512, 3).filter(wave());
+    assert!(tones(NoteMatcher::new().filter(linear.unwrap())).iter().any(|freq| !expected.contains(freq)));
+}
@@ -0,0 +1,335 @@
+//!
+//! Level control of amplitude samples: normalization, compression and gating.
+//! Levels are in dBFS, where 0 dB is the full scale of [-1.0, 1.0] samples.
+//!
+
+use super::filter::{Type, Data, Filter, StreamFilter, WaveData};
+
+#[must_use]
+fn to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(f32::MIN_POSITIVE).log10()
+}
+
+#[must_use]
+fn from_db(level_db: f32) -> f32 {
+    10_f32.powf(level_db / 20.0)
+}
+
+///
+/// One-pole smoothing coefficient for the given time constant.
+///
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+fn time_coefficient(time_ms: f32, sample_rate: u32) -> f32 {
+    if time_ms <= 0.0 {
+        return 0.0;
+    }
+
+    (-1000.0 / (time_ms * sample_rate as f32)).exp()
+}
+
+fn amplitude_only(data: Data, process: impl FnOnce(&mut WaveData)) -> Option<Data> {
+    let Data::Amplitude(mut wave) = data else {
+        return None;
+    };
+
+    process(&mut wave);
+    Some(Data::Amplitude(wave))
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Measure {
+    Peak,
+    Rms
+}
+
+///
+/// Brings the level of the track to the target one.
+/// The whole data passed at once is scaled by the single gain. The stream can't be
+/// measured in advance, so its level is followed with the instant attack and the slow release.
+///
+pub struct Normalizer {
+    measure: Measure,
+    target_db: f32,
+    envelope: Option<f32> // Level of the stream so far
+}
+
+impl Normalizer {
+    const RELEASE_MS: f32 = 3000.0;
+    const RMS_WINDOW_MS: f32 = 300.0;
+    const MAX_GAIN_DB: f32 = 30.0; // Don't turn the noise of silent parts into the full-scale signal
+
+    #[must_use]
+    pub fn new(measure: Measure, target_db: f32) -> Self {
+        Self { measure, target_db, envelope: None }
+    }
+
+    fn gain(&self, level: f32) -> f32 {
+        from_db((self.target_db - to_db(level)).min(Self::MAX_GAIN_DB))
+    }
+}
+
+impl Filter for Normalizer {
+    fn input_type(&self) -> Type {
+        Type::Amplitude
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn filter(&self, data: Data) -> Option<Data> {
+        amplitude_only(data, |wave| {
+            let level = match self.measure {
+                Measure::Peak => wave.samples.iter().fold(0.0_f32, |max, sample| max.max(sample.abs())),
+                Measure::Rms => (wave.samples.iter().map(|sample| sample * sample).sum::<f32>() / wave.samples.len().max(1) as f32).sqrt()
+            };
+
+            let gain = self.gain(level);
+            wave.samples.iter_mut().for_each(|sample| *sample = (*sample * gain).clamp(-1.0, 1.0));
+        })
+    }
+}
+
+impl StreamFilter for Normalizer {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        amplitude_only(chunk, |wave| {
+            let release = time_coefficient(Self::RELEASE_MS, wave.sample_rate);
+            let window = time_coefficient(Self::RMS_WINDOW_MS, wave.sample_rate);
+
+            let mut envelope = self.envelope.unwrap_or(0.0);
+            let mut mean_square = envelope * envelope;
+            for sample in &mut wave.samples {
+                let level = match self.measure {
+                    Measure::Peak => sample.abs(),
+                    Measure::Rms => {
+                        mean_square = window * mean_square + (1.0 - window) * *sample * *sample;
+                        mean_square.sqrt()
+                    }
+                };
+
+                envelope = if level > envelope { level } else { release * envelope + (1.0 - release) * level };
+                *sample = (*sample * self.gain(envelope)).clamp(-1.0, 1.0);
+            }
+
+            self.envelope = Some(envelope);
+        })
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        self.envelope = None;
+        None
+    }
+}
+
+
+
+///
+/// Feed-forward compressor: the level above the threshold is divided by the ratio.
+/// The infinite ratio turns it into the limiter.
+///
+pub struct Compressor {
+    threshold_db: f32,
+    ratio: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    makeup_db: f32,
+    envelope_db: Option<f32> // Smoothed level of the stream
+}
+
+impl Compressor {
+    #[must_use]
+    pub fn new(threshold_db: f32, ratio: f32, attack_ms: f32, release_ms: f32, makeup_db: f32) -> Self {
+        Self { threshold_db, ratio: ratio.max(1.0), attack_ms, release_ms, makeup_db, envelope_db: None }
+    }
+
+    #[must_use]
+    pub fn limiter(threshold_db: f32, release_ms: f32) -> Self {
+        Self::new(threshold_db, f32::INFINITY, 0.0, release_ms, 0.0)
+    }
+
+    fn apply(&self, wave: &mut WaveData, envelope_db: Option<f32>) -> f32 {
+        let attack = time_coefficient(self.attack_ms, wave.sample_rate);
+        let release = time_coefficient(self.release_ms, wave.sample_rate);
+        let slope = 1.0 - 1.0 / self.ratio;
+
+        let mut envelope_db = envelope_db.unwrap_or(f32::NEG_INFINITY);
+        for sample in &mut wave.samples {
+            let level_db = to_db(sample.abs());
+            let coefficient = if level_db > envelope_db { attack } else { release };
+            envelope_db = if envelope_db.is_finite() {
+                coefficient * envelope_db + (1.0 - coefficient) * level_db
+            } else {
+                level_db
+            };
+
+            let reduction_db = (envelope_db - self.threshold_db).max(0.0) * slope;
+            *sample *= from_db(self.makeup_db - reduction_db);
+        }
+
+        envelope_db
+    }
+}
+
+impl Filter for Compressor {
+    fn input_type(&self) -> Type {
+        Type::Amplitude
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        amplitude_only(data, |wave| {
+            self.apply(wave, None);
+        })
+    }
+}
+
+impl StreamFilter for Compressor {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let envelope_db = self.envelope_db;
+        let mut updated = envelope_db;
+        let filtered = amplitude_only(chunk, |wave| updated = Some(self.apply(wave, envelope_db)));
+        self.envelope_db = updated;
+        filtered
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        self.envelope_db = None;
+        None
+    }
+}
+
+
+
+///
+/// Noise gate: mutes the signal which stays below the threshold longer than the hold time,
+/// so the noise of quiet passages and fade-ins isn't baked into random switches.
+///
+pub struct Gate {
+    threshold_db: f32,
+    attack_ms: f32,
+    hold_ms: f32,
+    release_ms: f32,
+    state: Option<GateState>
+}
+
+#[derive(Clone, Copy)]
+struct GateState {
+    envelope: f32,
+    gain: f32,
+    below: u64 // Samples since the envelope fell below the threshold
+}
+
+impl Gate {
+    const DETECTOR_MS: f32 = 5.0; // Smoothing of the level so the single zero crossing doesn't close the gate
+
+    #[must_use]
+    pub fn new(threshold_db: f32, attack_ms: f32, hold_ms: f32, release_ms: f32) -> Self {
+        Self { threshold_db, attack_ms, hold_ms, release_ms, state: None }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    fn apply(&self, wave: &mut WaveData, state: Option<GateState>) -> GateState {
+        let detector = time_coefficient(Self::DETECTOR_MS, wave.sample_rate);
+        let attack = time_coefficient(self.attack_ms, wave.sample_rate);
+        let release = time_coefficient(self.release_ms, wave.sample_rate);
+        let hold = (self.hold_ms.max(0.0) * wave.sample_rate as f32 / 1000.0) as u64;
+        let threshold = from_db(self.threshold_db);
+
+        // The gate is closed at the beginning until the signal reaches the threshold:
+        let mut state = state.unwrap_or(GateState { envelope: 0.0, gain: 0.0, below: u64::MAX });
+        for sample in &mut wave.samples {
+            state.envelope = sample.abs().max(detector * state.envelope + (1.0 - detector) * sample.abs());
+            state.below = if state.envelope >= threshold { 0 } else { state.below.saturating_add(1) };
+
+            let (target, coefficient) = if state.below <= hold { (1.0, attack) } else { (0.0, release) };
+            state.gain = coefficient * state.gain + (1.0 - coefficient) * target;
+            *sample *= state.gain;
+        }
+
+        state
+    }
+}
+
+impl Filter for Gate {
+    fn input_type(&self) -> Type {
+        Type::Amplitude
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        amplitude_only(data, |wave| {
+            self.apply(wave, None);
+        })
+    }
+}
+
+impl StreamFilter for Gate {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let state = self.state;
+        let mut updated = state;
+        let filtered = amplitude_only(chunk, |wave| updated = Some(self.apply(wave, state)));
+        self.state = updated;
+        filtered
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        self.state = None;
+        None
+    }
+}
+
+
+
+#[test]
+#[allow(clippy::cast_precision_loss)]
+fn test() {
+    const SAMPLE_RATE: u32 = 8000;
+
+    let sine = |amplitude: f32, length: u32| -> Vec<f32> {
+        (0..length).map(|index| amplitude * (std::f32::consts::TAU * 100.0 * index as f32 / SAMPLE_RATE as f32).sin()).collect()
+    };
+    let peak = |samples: &[f32]| samples.iter().fold(0.0_f32, |max, sample| max.max(sample.abs()));
+    let run = |filter: &dyn Filter, samples: Vec<f32>| -> Vec<f32> {
+        match filter.filter(Data::Amplitude(WaveData { samples, sample_rate: SAMPLE_RATE })) {
+            Some(Data::Amplitude(wave)) => wave.samples,
+            _ => panic!("Unexpected data type")
+        }
+    };
+    let stream = |filter: &mut dyn StreamFilter, samples: &[f32]| -> Vec<f32> {
+        samples
+            .chunks(1000)
+            .flat_map(|chunk| match filter.feed(Data::Amplitude(WaveData { samples: chunk.to_vec(), sample_rate: SAMPLE_RATE })) {
+                Some(Data::Amplitude(wave)) => wave.samples,
+                _ => Vec::new()
+            })
+            .collect()
+    };
+
+    // Peak normalization of the whole data:
+    let normalized = run(&Normalizer::new(Measure::Peak, -6.0), sine(0.1, SAMPLE_RATE));
+    assert!((peak(&normalized) - from_db(-6.0)).abs() < 0.001);
+
+    // The stream follows the level, the loud part is brought down right away:
+    let mut quiet_then_loud = sine(0.1, SAMPLE_RATE);
+    quiet_then_loud.extend(sine(0.9, SAMPLE_RATE));
+    let streamed = stream(&mut Normalizer::new(Measure::Rms, -10.0), &quiet_then_loud);
+    let rms = |samples: &[f32]| (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt();
+    assert!((to_db(rms(&streamed[4000..8000])) + 10.0).abs() < 1.0);
+    assert!((to_db(rms(&streamed[12000..])) + 10.0).abs() < 1.0);
+
+    // 4:1 above -20 dB: the 0 dB signal comes out at -15 dB:
+    let compressor = Compressor::new(-20.0, 4.0, 1.0, 50.0, 0.0);
+    let compressed = run(&compressor, sine(1.0, SAMPLE_RATE));
+    assert!((to_db(peak(&compressed[4000..])) + 15.0).abs() < 1.0);
+    assert_eq!(stream(&mut Compressor::new(-20.0, 4.0, 1.0, 50.0, 0.0), &sine(1.0, SAMPLE_RATE)), compressed);
+
+    let limited = run(&Compressor::limiter(-3.0, 50.0), sine(1.0, SAMPLE_RATE));
+    assert!(peak(&limited[4000..]) <= from_db(-3.0) * 1.01);
+
+    // The gate mutes the quiet part and opens for the loud one:
+    let mut noise_then_tone = sine(0.001, SAMPLE_RATE);
+    noise_then_tone.extend(sine(0.5, SAMPLE_RATE));
+    let gate = Gate::new(-40.0, 1.0, 50.0, 20.0);
+    let gated = run(&gate, noise_then_tone.clone());
+    assert!(peak(&gated[..8000]) < 0.0001);
+    assert!((peak(&gated[9000..]) - 0.5).abs() < 0.01);
+    assert_eq!(stream(&mut Gate::new(-40.0, 1.0, 50.0, 20.0), &noise_then_tone), gated);
+}
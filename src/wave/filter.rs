@@ -4,9 +4,11 @@ pub enum Position {
     Up
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
     Amplitude,
-    Frequency
+    Frequency,
+    Position
 }
 
 pub type Nsec = u64; // Nanoseconds
@@ -17,7 +19,7 @@ pub type HertzInt = u32;
 #[derive(Default)]
 pub struct WaveData {
     pub samples: Vec<f32>,
-    pub sample_rate: u16
+    pub sample_rate: u32
 }
 
 #[derive(Default, Clone, Copy)]
@@ -50,13 +52,46 @@ impl Data {
     pub fn is_empty(&self) -> bool {
         match self {
             Data::Amplitude(wave) => wave.samples.is_empty(),
-            Data::Frequency(freq) => freq.is_empty(),
+            Data::Frequency(freq) => freq.iter().all(Vec::is_empty),
             Data::Position(pos) => pos.is_empty()
         }
     }
+
+    pub fn data_type(&self) -> Type {
+        match self {
+            Data::Amplitude(_) => Type::Amplitude,
+            Data::Frequency(_) => Type::Frequency,
+            Data::Position(_) => Type::Position
+        }
+    }
 }
 
+///
+/// Filters declare the kinds of their input and output, so chains can be validated
+/// before any data is passed through them.
+///
 pub trait Filter {
-    fn filter_type(&self) -> Type;
+    /// Kind of the data accepted by the filter.
+    fn input_type(&self) -> Type;
+
+    /// Kind of the produced data, the same as the input by default.
+    fn output_type(&self) -> Type {
+        self.input_type()
+    }
+
     fn filter(&self, data: Data) -> Option<Data>;
 }
+
+///
+/// Incremental variant of the `Filter`: takes the data chunk by chunk
+/// and keeps its state between chunks, so the output of the chunked stream
+/// matches the output of the whole data passed at once.
+///
+pub trait StreamFilter: Filter {
+    /// Returns the filtered part of the chunk (it may be empty if the filter waits for more data)
+    /// or `None` if the chunk has an inappropriate type.
+    fn feed(&mut self, chunk: Data) -> Option<Data>;
+
+    /// Returns the data that is still held by the filter at the end of the stream.
+    fn finish(&mut self) -> Option<Data>;
+}
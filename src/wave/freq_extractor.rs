@@ -1,14 +1,136 @@
-use rustfft::{FftPlanner, num_complex::Complex};
+use std::sync::Arc;
 
-use super::filter::{Type, Data, Filter, FreqRecord, FreqChannel, FreqData};
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use super::{
+    filter::{Type, Data, Filter, StreamFilter, FreqChannel, FreqData, Nsec},
+    partials::{Partials, Peak},
+    parallel
+};
+
+///
+/// Weighting of the sampling window, the smoother ones leak less to the neighbour bins
+/// at the cost of the wider peaks.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,   // No weighting, the narrowest peaks and the highest leakage
+    Hann,
+    Hamming,       // Lower first sidelobe than Hann, but the far ones don't decay
+    BlackmanHarris // 4-term, sidelobes are below -92 dB
+}
+
+///
+/// Refines the frequency of the peak between the centres of bins.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    None,      // Centre of the peak bin
+    Parabolic, // Vertex of the parabola through the peak bin and its neighbours in dB
+    Phase      // Phase advance of the peak bin between the windows shifted by 1/8 of the size
+}
+
+impl Window {
+    fn coefficients(self, size: usize) -> Vec<f32> {
+        #![allow(clippy::cast_precision_loss)]
+        #![allow(clippy::cast_possible_truncation)]
+
+        let terms: &[f64] = match self {
+            Window::Rectangular => &[1.0],
+            Window::Hann => &[0.5, 0.5],
+            Window::Hamming => &[0.54, 0.46],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168]
+        };
+
+        // Periodic form of the cosine sum, the alternating sign makes it zero at the edges:
+        (0..size)
+            .map(|index| {
+                let angle = std::f64::consts::TAU * index as f64 / size as f64;
+                terms
+                    .iter()
+                    .enumerate()
+                    .map(|(order, term)| if order % 2 == 0 { 1.0 } else { -1.0 } * term * (angle * order as f64).cos())
+                    .sum::<f64>() as f32
+            })
+            .collect()
+    }
+}
+
+///
+/// Levels of the silence in dB, the frames quieter than them are pauses.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Silence {
+    pub threshold: f32,        // RMS level of the frame in dBFS
+    pub relative: Option<f32>, // Level in dB relative to the loudest frame (so far when streaming), e.g. -40
+    pub hysteresis: f32        // The sound stops only this many dB below the threshold, so it doesn't chatter
+}
+
+///
+/// Whether the sound goes on and the loudest level so far.
+///
+struct Gate {
+    is_open: bool,
+    loudest_db: f32
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        Self::new(f32::NEG_INFINITY)
+    }
+}
+
+impl Gate {
+    fn new(loudest_db: f32) -> Self {
+        Self { is_open: false, loudest_db }
+    }
+
+    fn pass(&mut self, silence: &Silence, level_db: f32) -> bool {
+        self.loudest_db = self.loudest_db.max(level_db);
+
+        let threshold_db = silence.relative.map_or(silence.threshold, |relative| silence.threshold.max(self.loudest_db + relative));
+        let hysteresis_db = if self.is_open { silence.hysteresis } else { 0.0 };
+
+        self.is_open = level_db >= threshold_db - hysteresis_db;
+        self.is_open
+    }
+}
 
 pub struct FreqExtractor {
     lower_bound_hz: Option<u32>,
     upper_bound_hz: Option<u32>,
     sampling_size: u32,
     step_by: u32,
-    sample_rate: u32,
-    number_of_peaks: u8
+    number_of_peaks: u8,
+    window: Window,
+    padding: u8, // The FFT is this many times longer than the window, the rest is filled with zeros
+    interpolation: Interpolation,
+    min_track_ms: u32, // Voices which don't last as long are dropped
+    silence: Option<Silence>,
+    stream: Option<Stream>
+}
+
+///
+/// FFT of the padded size and the weights of the window,
+/// the plan is made once and shared by all threads of the analysis.
+///
+struct Analyzer {
+    fft_processor: Arc<dyn Fft<f32>>,
+    weights: Vec<f32>,
+    lag: usize // Shift of the second window for the phase interpolation, zero for others
+}
+
+///
+/// State of the incremental extraction: samples that haven't been covered
+/// by the window yet and the tracks which continue in the next chunk.
+///
+struct Stream {
+    analyzer: Analyzer,
+    samples: Vec<f32>,
+    skip: usize, // Samples of the next chunks to skip if the step is longer than the window
+    frames: u64, // Analyzed so far
+    gate: Gate,
+    partials: Partials
 }
 
 impl FreqExtractor {
@@ -18,95 +140,470 @@ impl FreqExtractor {
         upper_bound_hz: Option<u32>,
         sampling_size: u32, // E.g., 4096
         step_by: u32,       // E.g., 1024
-        sample_rate: u32,   // E.g., 44100
         number_of_peaks: u8
     ) -> Self {
-        Self { lower_bound_hz, upper_bound_hz, sampling_size, step_by, sample_rate, number_of_peaks }
+        Self {
+            lower_bound_hz,
+            upper_bound_hz,
+            sampling_size,
+            step_by,
+            number_of_peaks,
+            window: Window::Hann,
+            padding: 1,
+            interpolation: Interpolation::Parabolic,
+            min_track_ms: 0,
+            silence: None,
+            stream: None
+        }
     }
-}
 
-#[derive(Default, Clone, Copy)]
-struct Peak {
-    index: usize,
-    magnitude: f32
+    #[must_use]
+    pub fn with_window(mut self, window: Window) -> Self {
+        self.window = window;
+        self
+    }
+
+    ///
+    /// Zero-padding makes the bins narrower, which separates close peaks
+    /// and helps the interpolation, but doesn't add the resolution by itself.
+    ///
+    #[must_use]
+    pub fn with_padding(mut self, padding: u8) -> Self {
+        self.padding = padding.max(1);
+        self
+    }
+
+    #[must_use]
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    ///
+    /// Each channel follows a single voice, the voices shorter than the given duration
+    /// are replaced with pauses, so the channels don't flicker between the spurious peaks.
+    ///
+    #[must_use]
+    pub fn with_min_track(mut self, min_track_ms: u32) -> Self {
+        self.min_track_ms = min_track_ms;
+        self
+    }
+
+    ///
+    /// Quiet windows become pauses instead of the peaks of the noise.
+    ///
+    #[must_use]
+    pub fn with_silence(mut self, silence: Silence) -> Self {
+        self.silence = Some(silence);
+        self
+    }
+
+    fn fft_size(&self) -> usize {
+        self.sampling_size as usize * usize::from(self.padding)
+    }
+
+    fn analyzer(&self) -> Analyzer {
+        // The phase is compared between two shorter windows within the same samples,
+        // the longer the lag the lower the error, but the narrower the unambiguous range of 8 bins:
+        let lag = if self.interpolation == Interpolation::Phase {
+            (self.sampling_size as usize / 8).max(1)
+        } else {
+            0
+        };
+
+        Analyzer {
+            fft_processor: FftPlanner::new().plan_fft_forward(self.fft_size()),
+            weights: self.window.coefficients(self.sampling_size as usize - lag),
+            lag
+        }
+    }
+
+    #[must_use]
+    pub fn sampling_size(&self) -> u32 {
+        self.sampling_size
+    }
+
+    ///
+    /// Duration of the given frame. The frames end at the exact multiples of the step,
+    /// so the rounding doesn't accumulate and the extractors with different steps stay in sync.
+    ///
+    fn frame_duration(&self, frame: u64, sample_rate: u32) -> Nsec {
+        let end = |frame: u64| u128::from(frame) * u128::from(self.step_by) * u128::from(NANOS_IN_SEC) / u128::from(sample_rate);
+        Nsec::try_from(end(frame + 1) - end(frame)).unwrap_or(Nsec::MAX)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn bounds(&self, sample_rate: u32) -> (usize, usize) {
+        let fft_size = self.fft_size() as u64;
+        let index = |freq: u32| (u64::from(freq) * fft_size / u64::from(sample_rate)) as usize;
+
+        // The bounds above the Nyquist frequency leave the range empty:
+        let lower_index = self.lower_bound_hz.map_or(0, index).min(self.fft_size() / 2);
+        let upper_index = self.upper_bound_hz.map_or(self.fft_size() / 2, index).min(self.fft_size() / 2);
+
+        (lower_index, upper_index.max(lower_index))
+    }
+
+    ///
+    /// Spectrum of the weighted window padded with zeros.
+    ///
+    fn spectrum(analyzer: &Analyzer, window: &[f32]) -> Vec<Complex<f32>> {
+        let mut spectrum = window
+            .iter()
+            .zip(&analyzer.weights)
+            .map(|(sample, weight)| Complex::new(sample * weight, 0_f32))
+            .collect::<Vec<Complex<f32>>>()
+            ;
+
+        spectrum.resize(analyzer.fft_processor.len(), Complex::default());
+        analyzer.fft_processor.process(&mut spectrum);
+        spectrum
+    }
+
+    ///
+    /// Offset of the peak from the centre of its bin in the range of [-1, 1] bins.
+    ///
+    fn refine(&self, lag: usize, spectrum: &[Complex<f32>], shifted: &[Complex<f32>], magnitudes: &[f32], index: usize) -> f32 {
+        #![allow(clippy::cast_precision_loss)]
+
+        let offset = match self.interpolation {
+            Interpolation::None => 0.0,
+            Interpolation::Parabolic => {
+                let (Some(left), Some(right)) = (index.checked_sub(1).and_then(|left| magnitudes.get(left)), magnitudes.get(index + 1)) else {
+                    return 0.0;
+                };
+
+                let centre = magnitudes[index];
+                0.5 * (left - right) / (left - 2.0 * centre + right)
+            },
+            Interpolation::Phase => {
+                // The sinusoid of the peak advances its phase by 2*PI*freq/size per sample:
+                let cycles = lag as f32 / spectrum.len() as f32;
+                let advance = (shifted[index].arg() - spectrum[index].arg()) / std::f32::consts::TAU - index as f32 * cycles;
+                (advance - advance.round()) / cycles
+            }
+        };
+
+        if offset.is_finite() { offset.clamp(-1.0, 1.0) } else { 0.0 }
+    }
+
+    ///
+    /// Performs FFT of the window and returns its peaks, the loudest first.
+    ///
+    fn extract_peaks(&self, analyzer: &Analyzer, window: &[f32], sample_rate: u32) -> Vec<Peak> {
+        #![allow(clippy::cast_precision_loss)]
+
+        let (lower_index, upper_index) = self.bounds(sample_rate);
+        if lower_index == upper_index {
+            return Vec::new();
+        }
+
+        let spectrum = Self::spectrum(analyzer, &window[..analyzer.weights.len()]);
+        let shifted = if self.interpolation == Interpolation::Phase {
+            Self::spectrum(analyzer, &window[analyzer.lag..])
+        } else {
+            Vec::new()
+        };
+
+        // The neighbours of the bounds are kept for the interpolation:
+        let magnitudes = spectrum[..=self.fft_size() / 2]
+            .iter()
+            .map(|value| 10_f32 * (value.norm_sqr() + f32::MIN_POSITIVE).log10()) // Convert to dB: 10 * log(Re^2 + Im^2)
+            .collect::<Vec<f32>>()
+            ;
+
+        let peaks = find_peaks::PeakFinder::new(&magnitudes[lower_index..upper_index]);
+        let bin_width = sample_rate as f32 / self.fft_size() as f32;
+
+        let mut peaks = peaks.find_peaks()
+            .iter()
+            .map(|peak| {
+                let index = lower_index + peak.middle_position();
+                let offset = self.refine(analyzer.lag, &spectrum, &shifted, &magnitudes, index);
+                Peak { freq: (index as f32 + offset) * bin_width, magnitude_db: magnitudes[index] }
+            })
+            .collect::<Vec<Peak>>();
+
+        peaks.sort_by(|left, right| right.magnitude_db.total_cmp(&left.magnitude_db));
+        peaks
+    }
+
+    ///
+    /// Whether the window isn't silent, the gate keeps the state between the windows.
+    ///
+    fn is_audible(&self, gate: &mut Gate, window: &[f32]) -> bool {
+        let Some(silence) = &self.silence else {
+            return true;
+        };
+
+        gate.pass(silence, Self::level_db(window))
+    }
+
+    fn level_db(window: &[f32]) -> f32 {
+        #![allow(clippy::cast_precision_loss)]
+
+        let power = window.iter().map(|sample| sample * sample).sum::<f32>() / window.len() as f32;
+        10.0 * power.log10()
+    }
+
+    ///
+    /// Gate of the whole wave: the relative silence is measured against its loudest window
+    /// rather than the loudest one so far, so the quiet beginning of a crescendo is a pause too.
+    ///
+    fn gate(&self, samples: &[f32]) -> Gate {
+        let sampling_size = self.sampling_size as usize;
+
+        let loudest_db = match self.silence {
+            Some(Silence { relative: Some(_), .. }) => (0..)
+                .step_by((self.step_by as usize).max(1))
+                .take_while(|offset| offset + sampling_size <= samples.len())
+                .map(|offset| Self::level_db(&samples[offset..offset + sampling_size]))
+                .fold(f32::NEG_INFINITY, f32::max),
+            _ => f32::NEG_INFINITY
+        };
+
+        Gate::new(loudest_db)
+    }
+
+    ///
+    /// Peaks of all complete windows of the samples, the silent ones have none.
+    /// The windows are transformed on all cores with the same FFT plan, only the gate goes in order.
+    /// Returns the peaks and the number of samples the windows have been shifted by.
+    ///
+    fn analyze(&self, analyzer: &Analyzer, gate: &mut Gate, samples: &[f32], sample_rate: u32) -> (Vec<Vec<Peak>>, usize) {
+        let sampling_size = self.sampling_size as usize;
+        let step_by = (self.step_by as usize).max(1);
+
+        let windows = (0..)
+            .step_by(step_by)
+            .take_while(|offset| offset + sampling_size <= samples.len())
+            .map(|offset| {
+                let window = &samples[offset..offset + sampling_size];
+                (window, self.is_audible(gate, window))
+            })
+            .collect::<Vec<(&[f32], bool)>>();
+
+        let peaks = parallel::map(&windows, |(window, is_audible)| if *is_audible {
+            self.extract_peaks(analyzer, window, sample_rate)
+        } else {
+            Vec::new()
+        });
+
+        (peaks, windows.len() * step_by)
+    }
+
+    fn channels(&self) -> FreqData<f32> {
+        let mut channels = FreqData::default();
+        channels.resize_with(usize::from(self.number_of_peaks), FreqChannel::default);
+        channels
+    }
+
+    fn partials(&self, sample_rate: u32) -> Partials {
+        let frame_ms = u64::from(self.step_by.max(1)) * 1000 / u64::from(sample_rate);
+        let min_frames = u64::from(self.min_track_ms).div_ceil(frame_ms.max(1));
+        Partials::new(usize::from(self.number_of_peaks), u32::try_from(min_frames).unwrap_or(u32::MAX))
+    }
 }
 
 const NANOS_IN_SEC: u32 = 1_000_000_000;
 
 impl Filter for FreqExtractor {
-    #[must_use]
-    fn filter_type(&self) -> Type {
+    fn input_type(&self) -> Type {
         Type::Amplitude
     }
 
-    #[must_use]
+    fn output_type(&self) -> Type {
+        Type::Frequency
+    }
+
     fn filter(&self, data: Data) -> Option<Data> {
-        #![allow(clippy::cast_possible_truncation)]
-        #![allow(clippy::cast_precision_loss)]
-        #![allow(clippy::cast_sign_loss)]
-        
         let Data::Amplitude(wave) = data else {
             return None;
         };
 
-        let duration = (u64::from(NANOS_IN_SEC) * u64::from(self.step_by)) / u64::from(self.sample_rate);
+        let mut partials = self.partials(wave.sample_rate);
+        let mut channels = self.channels();
 
-        let fft_processor = FftPlanner::new().plan_fft_forward(self.sampling_size as usize);
+        let (frames, _) = self.analyze(&self.analyzer(), &mut self.gate(&wave.samples), &wave.samples, wave.sample_rate);
+        for (frame, peaks) in (0..).zip(frames) {
+            partials.advance(&peaks, self.frame_duration(frame, wave.sample_rate), &mut channels);
+        }
 
-        let mut channels = FreqData::default();
-        channels.resize_with(usize::from(self.number_of_peaks), FreqChannel::default);
+        partials.finish(&mut channels);
+        Some(Data::Frequency(channels))
+    }
+}
 
-        let lower_index = match self.lower_bound_hz {
-            Some(freq) => (freq * self.sampling_size) / self.sample_rate,
-            None => 0
-        } as usize;
+impl StreamFilter for FreqExtractor {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = chunk else {
+            return None;
+        };
 
-        let upper_index = match self.upper_bound_hz {
-            Some(freq) => (freq * self.sampling_size) / self.sample_rate,
-            None => self.sampling_size / 2
-        } as usize;
+        let sampling_size = self.sampling_size as usize;
 
-        for i in (0..wave.samples.len() - (self.sampling_size as usize)).step_by(self.step_by as usize) {
-            let chunk = &wave.samples[i..i + self.sampling_size as usize];
-            if chunk.len() < self.sampling_size as usize {
-                break;
-            }
+        let mut stream = self.stream.take().unwrap_or_else(|| Stream {
+            analyzer: self.analyzer(),
+            samples: Vec::with_capacity(sampling_size * 2),
+            skip: 0,
+            frames: 0,
+            gate: Gate::default(),
+            partials: self.partials(wave.sample_rate)
+        });
 
-            let mut samples = chunk
-                .iter()
-                .map(|sample| Complex::new(*sample, 0_f32))
-                .collect::<Vec<Complex<f32>>>()
-                ;
-
-            fft_processor.process(&mut samples);
-
-            let magnitudes = samples
-                .into_iter()
-                .skip(lower_index)
-                .take(upper_index - lower_index)
-                .map(|value| 20_f32 * (value.re.powi(2) + value.im.powi(2)).sqrt().log10()) // Convert to dB: 20 * log(sqrt(Re^2 + Im^2))
-                .collect::<Vec<f32>>()
-                ;
-
-            let peaks = find_peaks::PeakFinder::new(&magnitudes);
-
-            for (peak, channel) in peaks.find_peaks().iter().zip(&mut channels) {
-                let freq = (self.sample_rate * (peak.middle_position() + lower_index) as u32) as f32 / self.sampling_size as f32;
-
-                if let Some(last_sample) = channel.last_mut() {
-                    let diff_percentage = freq * 100_f32 / last_sample.freq;
-                    
-                    if diff_percentage < 5_f32 {
-                        last_sample.duration += duration;
-                    } else {
-                        channel.push(FreqRecord { freq, duration });
-                    }
-                } else {
-                    channel.push(FreqRecord { freq, duration });
-                }
-            }            
+        let skipped = stream.skip.min(wave.samples.len());
+        stream.skip -= skipped;
+        stream.samples.extend_from_slice(&wave.samples[skipped..]);
+
+        let mut channels = self.channels();
+
+        let (frames, offset) = self.analyze(&stream.analyzer, &mut stream.gate, &stream.samples, wave.sample_rate);
+        for peaks in frames {
+            stream.partials.advance(&peaks, self.frame_duration(stream.frames, wave.sample_rate), &mut channels);
+            stream.frames += 1;
         }
 
+        let drained = offset.min(stream.samples.len());
+        stream.samples.drain(..drained);
+        stream.skip += offset - drained;
+        self.stream = Some(stream);
+
         Some(Data::Frequency(channels))
     }
-}
\ No newline at end of file
+
+    fn finish(&mut self) -> Option<Data> {
+        let mut stream = self.stream.take()?;
+
+        let mut channels = self.channels();
+        stream.partials.finish(&mut channels);
+        Some(Data::Frequency(channels))
+    }
+}
+
+
+
+#[test]
+#[allow(clippy::cast_precision_loss, clippy::too_many_lines)]
+fn test() {
+    use super::filter::WaveData;
+
+    const SAMPLE_RATE: u32 = 22050;
+    const FREQ: f32 = 110.3; // Between the bins of 5.38 Hz
+
+    let samples = (0..SAMPLE_RATE)
+        .map(|index| (std::f32::consts::TAU * FREQ * index as f32 / SAMPLE_RATE as f32).sin())
+        .collect::<Vec<f32>>();
+
+    let error = |extractor: FreqExtractor| -> f32 {
+        let wave = WaveData { samples: samples.clone(), sample_rate: SAMPLE_RATE };
+        let Some(Data::Frequency(channels)) = extractor.filter(Data::Amplitude(wave)) else {
+            panic!("Unexpected data type");
+        };
+
+        channels[0].iter().map(|record| (record.freq - FREQ).abs()).fold(0.0, f32::max)
+    };
+
+    let extractor = || FreqExtractor::new(Some(50), Some(1000), 4096, 1024, 1);
+    let bin_width = SAMPLE_RATE as f32 / 4096.0;
+
+    assert!(error(extractor().with_window(Window::Rectangular).with_interpolation(Interpolation::None)) < bin_width / 2.0);
+    assert!(error(extractor().with_interpolation(Interpolation::Parabolic)) < bin_width / 10.0);
+    assert!(error(extractor().with_window(Window::BlackmanHarris).with_padding(4)) < bin_width / 50.0);
+    assert!(error(extractor().with_window(Window::Hamming).with_interpolation(Interpolation::Phase)) < bin_width / 100.0);
+
+    // The fade-out becomes a pause once it's 30 dB below the loudest window, but doesn't chatter around the threshold:
+    let fading = samples
+        .iter()
+        .enumerate()
+        .map(|(index, sample)| sample * 10_f32.powf(-3.0 * index as f32 / SAMPLE_RATE as f32)) // -60 dB per second
+        .collect::<Vec<f32>>();
+    let silence = Silence { threshold: -80.0, relative: Some(-30.0), hysteresis: 6.0 };
+    let Some(Data::Frequency(channels)) = extractor()
+        .with_silence(silence)
+        .filter(Data::Amplitude(WaveData { samples: fading, sample_rate: SAMPLE_RATE }))
+    else {
+        panic!("Unexpected data type");
+    };
+    let [tone, pause] = channels[0].as_slice() else {
+        panic!("Expected the tone and the pause, got {} records", channels[0].len());
+    };
+    assert!(tone.freq > 0.0 && pause.freq <= 0.0);
+
+    // The relative level is taken from the loudest window of the whole wave, so the quiet beginning of a crescendo is a pause:
+    let crescendo = samples
+        .iter()
+        .enumerate()
+        .map(|(index, sample)| sample * 10_f32.powf(3.0 * (index as f32 / SAMPLE_RATE as f32 - 1.0))) // From -60 dB to 0 dB
+        .collect::<Vec<f32>>();
+    let Some(Data::Frequency(channels)) = extractor()
+        .with_silence(silence)
+        .filter(Data::Amplitude(WaveData { samples: crescendo, sample_rate: SAMPLE_RATE }))
+    else {
+        panic!("Unexpected data type");
+    };
+    let [pause, tone] = channels[0].as_slice() else {
+        panic!("Expected the pause and the tone, got {} records", channels[0].len());
+    };
+    assert!(pause.freq <= 0.0 && tone.freq > 0.0);
+
+    // Streaming by uneven chunks gives the same records as the whole wave, the windows don't depend on the chunks:
+    let merged = |channels: FreqData<f32>| -> Vec<Vec<(f32, Nsec)>> {
+        channels
+            .into_iter()
+            .map(|records| records.into_iter().fold(Vec::new(), |mut merged: Vec<(f32, Nsec)>, record| {
+                match merged.last_mut() {
+                    Some((freq, duration)) if freq.to_bits() == record.freq.to_bits() => *duration += record.duration,
+                    _ => merged.push((record.freq, record.duration))
+                }
+                merged
+            }))
+            .collect()
+    };
+    let Some(Data::Frequency(batch)) = extractor().filter(Data::Amplitude(WaveData { samples: samples.clone(), sample_rate: SAMPLE_RATE })) else {
+        panic!("Unexpected data type");
+    };
+    let mut streaming = extractor();
+    let mut streamed = vec![Vec::new()];
+    for chunk in samples.chunks(1000).chain(std::iter::once(&[][..])) {
+        let data = if chunk.is_empty() {
+            streaming.finish()
+        } else {
+            streaming.feed(Data::Amplitude(WaveData { samples: chunk.to_vec(), sample_rate: SAMPLE_RATE }))
+        };
+        if let Some(Data::Frequency(channels)) = data {
+            streamed[0].extend(channels.into_iter().flatten());
+        }
+    }
+    assert_eq!(merged(streamed), merged(batch));
+
+    // The bounds above the Nyquist frequency find nothing rather than slice past the spectrum:
+    let Some(Data::Frequency(channels)) = FreqExtractor::new(Some(20000), None, 4096, 1024, 1)
+        .filter(Data::Amplitude(WaveData { samples: samples.clone(), sample_rate: SAMPLE_RATE }))
+    else {
+        panic!("Unexpected data type");
+    };
+    assert!(channels[0].iter().all(|record| record.freq <= 0.0));
+
+    // The windows analyzed on all cores keep the order and the peaks of the sequential analysis, a chirp makes each window differ:
+    let chirp = (0..SAMPLE_RATE * 2)
+        .map(|index| index as f32 / SAMPLE_RATE as f32)
+        .map(|time| (std::f32::consts::TAU * (100.0 + 200.0 * time) * time).sin())
+        .collect::<Vec<f32>>();
+    let extractor = FreqExtractor::new(Some(50), Some(2000), 2048, 128, 1);
+    let analyzer = extractor.analyzer();
+    let (parallel, shifted_by) = extractor.analyze(&analyzer, &mut Gate::default(), &chirp, SAMPLE_RATE);
+    let sequential = chirp
+        .windows(2048)
+        .step_by(128)
+        .map(|window| extractor.extract_peaks(&analyzer, window, SAMPLE_RATE))
+        .collect::<Vec<Vec<Peak>>>();
+    let bits = |frames: &[Vec<Peak>]| -> Vec<Vec<(u32, u32)>> {
+        frames.iter().map(|peaks| peaks.iter().map(|peak| (peak.freq.to_bits(), peak.magnitude_db.to_bits())).collect()).collect()
+    };
+    assert!(parallel.len() > 16 * 4 && shifted_by == parallel.len() * 128);
+    assert_eq!(bits(&parallel), bits(&sequential));
+
+    // The weights vanish at the edges:
+    let weights = Window::BlackmanHarris.coefficients(8);
+    assert!(weights[0].abs() < 1e-4 && (weights[4] - 1.0).abs() < 1e-4);
+}
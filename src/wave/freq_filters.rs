@@ -1,7 +1,7 @@
 #![allow(clippy::cast_possible_truncation)]
 
 use std::f32::consts::PI;
-use super::filter::{Type, Data, Filter};
+use super::filter::{Type, Data, Filter, StreamFilter};
 
 #[inline]
 #[must_use]
@@ -17,22 +17,33 @@ fn calc_dt(sample_rate_hz: u32) -> f32 {
 }
 
 
+///
+/// The time step is taken from the sample rate of the filtered data,
+/// so the filter may follow the resampler.
+///
 pub struct HighPass {
-    dt: f32,
-    rc: f32
+    rc: f32,
+    last: Option<(f32, f32)> // The last sample and its filtered value in the stream
 }
 
 impl HighPass {
     #[must_use]
-    pub fn new(sample_rate_hz: u32, freq_hz: f32) -> Self {
-        Self { dt: calc_dt(sample_rate_hz), rc: calc_rc(freq_hz) }
+    pub fn new(freq_hz: f32) -> Self {
+        Self { rc: calc_rc(freq_hz), last: None }
     }
 
-    pub fn apply(&self, samples: &mut [f32]) {
-        let alpha = self.rc / (self.rc + self.dt);
+    pub fn apply(&self, samples: &mut [f32], sample_rate_hz: u32) {
+        self.apply_after(samples, sample_rate_hz, None);
+    }
+
+    fn apply_after(&self, samples: &mut [f32], sample_rate_hz: u32, last: Option<(f32, f32)>) -> Option<(f32, f32)> {
+        let alpha = self.rc / (self.rc + calc_dt(sample_rate_hz));
 
-        let mut prev_sample = samples[0];
-        let mut prev_filtered = prev_sample;
+        let Some(&first_sample) = samples.first() else {
+            return last;
+        };
+
+        let (mut prev_sample, mut prev_filtered) = last.unwrap_or((first_sample, first_sample));
     
         for sample in samples {
             let current_sample = *sample;
@@ -43,20 +54,20 @@ impl HighPass {
             prev_sample = current_sample;
             prev_filtered = current_filtered;
         }
+
+        Some((prev_sample, prev_filtered))
     }
 }
 
 impl Filter for HighPass {
-    #[must_use]
-    fn filter_type(&self) -> super::filter::Type {
+    fn input_type(&self) -> Type {
         Type::Amplitude
     }
 
-    #[must_use]
     fn filter(&self, data: Data) -> Option<Data> {
         match data {
             Data::Amplitude(mut data) => {
-                self.apply(&mut data.samples);
+                self.apply(&mut data.samples, data.sample_rate);
                 Some(Data::Amplitude(data))
             },
             _ => None
@@ -64,23 +75,49 @@ impl Filter for HighPass {
     }
 }
 
+impl StreamFilter for HighPass {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        match chunk {
+            Data::Amplitude(mut data) => {
+                self.last = self.apply_after(&mut data.samples, data.sample_rate, self.last);
+                Some(Data::Amplitude(data))
+            },
+            _ => None
+        }
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        self.last = None;
+        None
+    }
+}
+
 
 
 pub struct LowPass {
-    dt: f32,
-    rc: f32
+    rc: f32,
+    last_filtered: Option<f32> // The last filtered value in the stream
 }
 
 impl LowPass {
     #[must_use]
-    pub fn new(sample_rate_hz: u32, freq_hz: f32) -> Self {
-        Self { dt: calc_dt(sample_rate_hz), rc: calc_rc(freq_hz) }
+    pub fn new(freq_hz: f32) -> Self {
+        Self { rc: calc_rc(freq_hz), last_filtered: None }
+    }
+
+    pub fn apply(&self, samples: &mut [f32], sample_rate_hz: u32) {
+        self.apply_after(samples, sample_rate_hz, None);
     }
 
-    pub fn apply(&self, samples: &mut [f32]) {
-        let alpha = self.dt / (self.rc + self.dt);
+    fn apply_after(&self, samples: &mut [f32], sample_rate_hz: u32, last_filtered: Option<f32>) -> Option<f32> {
+        let dt = calc_dt(sample_rate_hz);
+        let alpha = dt / (self.rc + dt);
+
+        let Some(&first_sample) = samples.first() else {
+            return last_filtered;
+        };
 
-        let mut prev_filtered: f32 = alpha * samples[0];
+        let mut prev_filtered: f32 = last_filtered.unwrap_or(alpha * first_sample);
     
         for sample in samples {
             let current_sample = *sample;
@@ -90,23 +127,342 @@ impl LowPass {
     
             prev_filtered = current_filtered;
         }
+
+        Some(prev_filtered)
     }
 }
 
 impl Filter for LowPass {
-    #[must_use]
-    fn filter_type(&self) -> super::filter::Type {
+    fn input_type(&self) -> Type {
         Type::Amplitude
     }
 
-    #[must_use]
     fn filter(&self, data: Data) -> Option<Data> {
         match data {
             Data::Amplitude(mut data) => {
-                self.apply(&mut data.samples);
+                self.apply(&mut data.samples, data.sample_rate);
+                Some(Data::Amplitude(data))
+            },
+            _ => None
+        }
+    }
+}
+
+impl StreamFilter for LowPass {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        match chunk {
+            Data::Amplitude(mut data) => {
+                self.last_filtered = self.apply_after(&mut data.samples, data.sample_rate, self.last_filtered);
                 Some(Data::Amplitude(data))
             },
             _ => None
         }
     }
+
+    fn finish(&mut self) -> Option<Data> {
+        self.last_filtered = None;
+        None
+    }
+}
+
+
+///
+/// Frequency response of the single biquad section (RBJ Audio EQ Cookbook).
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Response {
+    LowPass,
+    HighPass,
+    BandPass,          // Constant 0 dB peak gain at the center frequency
+    BandStop,          // Notch
+    Peaking(f32),      // Gain in dB at the center frequency
+    LowShelf(f32),     // Gain in dB below the corner frequency
+    HighShelf(f32)     // Gain in dB above the corner frequency
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    Low,
+    High
+}
+
+///
+/// Section of the cascade before the sample rate is known.
+///
+#[derive(Clone, Copy)]
+enum Design {
+    FirstOrder(Pass),
+    SecondOrder(Response, f64 /* Q */)
+}
+
+///
+/// Normalized coefficients (a0 = 1) and the state of the transposed direct form II.
+///
+#[derive(Clone, Copy, Default)]
+struct Section {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64
+}
+
+impl Section {
+    fn new(design: Design, freq_hz: f32, sample_rate_hz: u32) -> Self {
+        // Keep the frequency below Nyquist, the coefficients are undefined above it:
+        let nyquist = f64::from(sample_rate_hz) / 2.0;
+        let w0 = 2.0 * std::f64::consts::PI * f64::from(freq_hz).min(nyquist * 0.999) / f64::from(sample_rate_hz);
+        let (sin, cos) = w0.sin_cos();
+
+        let Design::SecondOrder(response, q) = design else {
+            // Bilinear transform of the one-pole prototype with the prewarped frequency:
+            let k = (w0 / 2.0).tan();
+            let a1 = (k - 1.0) / (k + 1.0);
+            let (b0, b1) = match design {
+                Design::FirstOrder(Pass::Low) => (k / (k + 1.0), k / (k + 1.0)),
+                _ => (1.0 / (k + 1.0), -1.0 / (k + 1.0))
+            };
+            return Self { b0, b1, a1, ..Self::default() };
+        };
+
+        let alpha = sin / (2.0 * q);
+        let shelf = |gain_db: f32| {
+            let a = 10_f64.powf(f64::from(gain_db) / 40.0);
+            (a, 2.0 * a.sqrt() * alpha)
+        };
+
+        let (b0, b1, b2, a0, a1, a2) = match response {
+            Response::LowPass => {
+                let b = (1.0 - cos) / 2.0;
+                (b, 2.0 * b, b, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            },
+            Response::HighPass => {
+                let b = f64::midpoint(1.0, cos);
+                (b, -2.0 * b, b, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            },
+            Response::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            Response::BandStop => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            Response::Peaking(gain_db) => {
+                let a = 10_f64.powf(f64::from(gain_db) / 40.0);
+                (1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a)
+            },
+            Response::LowShelf(gain_db) => {
+                let (a, beta) = shelf(gain_db);
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - beta),
+                    (a + 1.0) + (a - 1.0) * cos + beta,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - beta
+                )
+            },
+            Response::HighShelf(gain_db) => {
+                let (a, beta) = shelf(gain_db);
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + beta),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - beta),
+                    (a + 1.0) - (a - 1.0) * cos + beta,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - beta
+                )
+            }
+        };
+
+        Self { b0: b0 / a0, b1: b1 / a0, b2: b2 / a0, a1: a1 / a0, a2: a2 / a0, z1: 0.0, z2: 0.0 }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        let output = self.b0 * sample + self.z1;
+        self.z1 = self.b1 * sample - self.a1 * output + self.z2;
+        self.z2 = self.b2 * sample - self.a2 * output;
+        output
+    }
+}
+
+///
+/// Cascade of biquad sections: a single section of the given response
+/// or a higher-order Butterworth or Linkwitz-Riley low/high-pass filter.
+/// The coefficients are calculated for the sample rate of the filtered data.
+///
+pub struct Biquad {
+    freq_hz: f32,
+    designs: Vec<Design>,
+    stream: Option<(u32, Vec<Section>)> // Sample rate and the sections with their state
+}
+
+impl Biquad {
+    pub const DEFAULT_Q: f32 = std::f32::consts::FRAC_1_SQRT_2; // Flat passband of the single section
+
+    #[must_use]
+    pub fn new(response: Response, freq_hz: f32, q: f32) -> Self {
+        Self { freq_hz, designs: vec![Design::SecondOrder(response, f64::from(q))], stream: None }
+    }
+
+    ///
+    /// Maximally flat filter of the given order, its roll-off is 6 dB per octave per order.
+    ///
+    #[must_use]
+    pub fn butterworth(pass: Pass, freq_hz: f32, order: u8) -> Self {
+        Self { freq_hz, designs: Self::butterworth_designs(pass, order), stream: None }
+    }
+
+    ///
+    /// Two cascaded Butterworth filters of the half order, -6 dB at the cutoff,
+    /// so the low and high parts of the same frequency sum up flat.
+    /// Returns None if the order is odd.
+    ///
+    #[must_use]
+    pub fn linkwitz_riley(pass: Pass, freq_hz: f32, order: u8) -> Option<Self> {
+        if !order.is_multiple_of(2) {
+            return None;
+        }
+
+        let half = Self::butterworth_designs(pass, order / 2);
+        Some(Self { freq_hz, designs: [half.as_slice(), half.as_slice()].concat(), stream: None })
+    }
+
+    fn butterworth_designs(pass: Pass, order: u8) -> Vec<Design> {
+        let response = match pass {
+            Pass::Low => Response::LowPass,
+            Pass::High => Response::HighPass
+        };
+
+        // Poles of the prototype are spread evenly over the half of the circle,
+        // each conjugate pair becomes a section with its own Q:
+        let order_flt = f64::from(order);
+        let mut designs = (0..order / 2)
+            .map(|index| {
+                let angle = f64::from(2 * index + 1) * std::f64::consts::PI / (2.0 * order_flt);
+                Design::SecondOrder(response, 1.0 / (2.0 * angle.sin()))
+            })
+            .collect::<Vec<Design>>();
+
+        if !order.is_multiple_of(2) {
+            designs.push(Design::FirstOrder(pass));
+        }
+
+        designs
+    }
+
+    fn sections(&self, sample_rate_hz: u32) -> Vec<Section> {
+        self.designs.iter().map(|design| Section::new(*design, self.freq_hz, sample_rate_hz)).collect()
+    }
+
+    fn apply(sections: &mut [Section], samples: &mut [f32]) {
+        for sample in samples {
+            let filtered = sections.iter_mut().fold(f64::from(*sample), |value, section| section.process(value));
+            *sample = filtered as f32;
+        }
+    }
+}
+
+impl Filter for Biquad {
+    fn input_type(&self) -> Type {
+        Type::Amplitude
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        let Data::Amplitude(mut wave) = data else {
+            return None;
+        };
+
+        Self::apply(&mut self.sections(wave.sample_rate), &mut wave.samples);
+        Some(Data::Amplitude(wave))
+    }
+}
+
+impl StreamFilter for Biquad {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Amplitude(mut wave) = chunk else {
+            return None;
+        };
+
+        // The state is dropped if the rate changes between songs:
+        if self.stream.as_ref().is_none_or(|(sample_rate, _)| *sample_rate != wave.sample_rate) {
+            self.stream = Some((wave.sample_rate, self.sections(wave.sample_rate)));
+        }
+
+        if let Some((_, sections)) = self.stream.as_mut() {
+            Self::apply(sections, &mut wave.samples);
+        }
+
+        Some(Data::Amplitude(wave))
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        self.stream = None;
+        None
+    }
+}
+
+
+
+#[test]
+#[allow(clippy::cast_precision_loss)]
+fn test() {
+    use super::filter::WaveData;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    let sine = |freq: f32| -> Vec<f32> {
+        (0..SAMPLE_RATE).map(|index| (2.0 * PI * freq * index as f32 / SAMPLE_RATE as f32).sin()).collect()
+    };
+
+    // Peak amplitude after the transient:
+    let gain = |filter: &dyn Filter, freq: f32| -> f32 {
+        let Some(Data::Amplitude(wave)) = filter.filter(Data::Amplitude(WaveData { samples: sine(freq), sample_rate: SAMPLE_RATE })) else {
+            panic!("Unexpected data type");
+        };
+        wave.samples[SAMPLE_RATE as usize / 2..].iter().fold(0.0, |max, sample| sample.abs().max(max))
+    };
+
+    let butterworth = Biquad::butterworth(Pass::Low, 1000.0, 4);
+    assert!((gain(&butterworth, 100.0) - 1.0).abs() < 0.01);
+    assert!((gain(&butterworth, 1000.0) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01);
+    assert!(gain(&butterworth, 4000.0) < 0.005); // -24 dB per octave
+
+    let butterworth = Biquad::butterworth(Pass::High, 1000.0, 3);
+    assert!((gain(&butterworth, 1000.0) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01);
+    assert!(gain(&butterworth, 250.0) < 0.02);
+
+    let crossover = Biquad::linkwitz_riley(Pass::High, 1000.0, 4).unwrap();
+    assert!((gain(&crossover, 1000.0) - 0.5).abs() < 0.01);
+    assert!(Biquad::linkwitz_riley(Pass::High, 1000.0, 3).is_none());
+
+    let band_pass = Biquad::new(Response::BandPass, 1000.0, 2.0);
+    assert!((gain(&band_pass, 1000.0) - 1.0).abs() < 0.01);
+    assert!(gain(&band_pass, 4000.0) < 0.2);
+
+    let notch = Biquad::new(Response::BandStop, 1000.0, 2.0);
+    assert!(gain(&notch, 1000.0) < 0.01);
+
+    let peaking = Biquad::new(Response::Peaking(6.0), 1000.0, 1.0);
+    assert!((gain(&peaking, 1000.0) - 10_f32.powf(6.0 / 20.0)).abs() < 0.02);
+
+    let low_shelf = Biquad::new(Response::LowShelf(-12.0), 500.0, Biquad::DEFAULT_Q);
+    assert!((gain(&low_shelf, 50.0) - 0.25).abs() < 0.01);
+    assert!((gain(&low_shelf, 10000.0) - 1.0).abs() < 0.01);
+
+    let high_shelf = Biquad::new(Response::HighShelf(6.0), 2000.0, Biquad::DEFAULT_Q);
+    assert!((gain(&high_shelf, 15000.0) - 2.0).abs() < 0.03);
+
+    // Streaming by chunks gives the same result:
+    let samples = sine(3000.0);
+    let mut stream = Biquad::butterworth(Pass::Low, 2000.0, 5);
+    let streamed = samples
+        .chunks(1000)
+        .flat_map(|chunk| match stream.feed(Data::Amplitude(WaveData { samples: chunk.to_vec(), sample_rate: SAMPLE_RATE })) {
+            Some(Data::Amplitude(wave)) => wave.samples,
+            _ => Vec::new()
+        })
+        .collect::<Vec<f32>>();
+
+    let Some(Data::Amplitude(batch)) = Biquad::butterworth(Pass::Low, 2000.0, 5).filter(Data::Amplitude(WaveData { samples, sample_rate: SAMPLE_RATE })) else {
+        panic!("Unexpected data type");
+    };
+    assert_eq!(streamed, batch.samples);
 }
@@ -1,14 +1,14 @@
-///                            𝘵
-/// 𝒇(𝘵) = 𝘈𝘮𝘱 * 𝘤𝘰𝘴(𝟸𝜋𝛺𝘵 + 𝟸𝜋𝜔⎰𝘈(𝜏)ⅆ𝜏)
-///                           𝟶
-/// Where:
-///   𝒇(𝘵) - frequency-modulated signal.
-///   𝘵 - time.
-///   𝘈𝘮𝘱 - amplitude of the desired signal (1.0 in our case).
-///   𝛺 - frequency of the carrier signal.
-///   𝜔 - available deviation from the carrier frequency.
-///   𝘈(𝜏) - original amplitude-modulated signal.
-/// 
+//!                            𝘵
+//! 𝒇(𝘵) = 𝘈𝘮𝘱 * 𝘤𝘰𝘴(𝟸𝜋𝛺𝘵 + 𝟸𝜋𝜔⎰𝘈(𝜏)ⅆ𝜏)
+//!                           𝟶
+//! Where:
+//!   𝒇(𝘵) - frequency-modulated signal.
+//!   𝘵 - time.
+//!   𝘈𝘮𝘱 - amplitude of the desired signal (1.0 in our case).
+//!   𝛺 - frequency of the carrier signal.
+//!   𝜔 - available deviation from the carrier frequency.
+//!   𝘈(𝜏) - original amplitude-modulated signal.
+//! 
 
 use std::f32::consts::TAU;
 use super::filter::{Type, Data, Filter, WaveData};
@@ -45,19 +45,16 @@ impl FreqModulation {
 }
 
 impl Default for FreqModulation {
-    #[must_use]
     fn default() -> Self {
         Self::new(0.0_f32, 1.0_f32, 0.0005_f32)
     }
 }
 
 impl Filter for FreqModulation {
-    #[must_use]
-    fn filter_type(&self) -> Type {
+    fn input_type(&self) -> Type {
         Type::Amplitude
     }
 
-    #[must_use]
     fn filter(&self, data: Data) -> Option<Data> {
         match data {
             Data::Amplitude(wave) => Some(
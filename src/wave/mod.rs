@@ -9,4 +9,15 @@ pub mod bakery;
 pub mod wav_header;
 pub mod player;
 pub mod filter;
-pub mod wav_extractor;
\ No newline at end of file
+pub mod pipeline;
+pub mod pipeline_parser;
+pub mod wav_extractor;
+pub mod resampler;
+pub mod dynamics;
+pub mod pitch_tracker;
+pub mod partials;
+pub mod parallel;
+pub mod multi_band;
+pub mod constant_q;
+pub mod transposer;
+pub mod pitch_effects;
\ No newline at end of file
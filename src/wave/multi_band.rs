@@ -0,0 +1,158 @@
+use super::{
+    filter::{Type, Data, Filter, StreamFilter, FreqRecord, FreqData, WaveData, Nsec},
+    freq_extractor::FreqExtractor
+};
+
+///
+/// Extracts each band of the spectrum with its own window: the long ones resolve the bass,
+/// the short ones follow the fast treble. The channels of all bands are played together,
+/// the ones of the first band go first.
+///
+pub struct MultiBandExtractor {
+    bands: Vec<FreqExtractor>, // Each one has its own bounds, window, step and number of channels
+    is_started: bool           // The lead-in pauses have been emitted
+}
+
+const NANOS_IN_SEC: u64 = 1_000_000_000;
+
+impl MultiBandExtractor {
+    #[must_use]
+    pub fn new(bands: Vec<FreqExtractor>) -> Self {
+        Self { bands, is_started: false }
+    }
+
+    ///
+    /// Joins the channels produced by the bands for the same samples. The first output of each band
+    /// is delayed by half of its window (at the given sample rate), so each frame sounds at the centre
+    /// of its window and the onsets of all bands coincide no matter how long the windows are.
+    ///
+    fn join(outputs: Vec<(u32, Option<Data>)>, lead_in: Option<u32>) -> Option<Data> {
+        let mut joined = FreqData::default();
+
+        for (sampling_size, output) in outputs {
+            let Data::Frequency(mut channels) = output? else {
+                return None;
+            };
+
+            if let Some(sample_rate) = lead_in {
+                let duration: Nsec = NANOS_IN_SEC * u64::from(sampling_size / 2) / u64::from(sample_rate);
+                for channel in &mut channels {
+                    channel.insert(0, FreqRecord { freq: 0.0, duration });
+                }
+            }
+
+            joined.extend(channels);
+        }
+
+        Some(Data::Frequency(joined))
+    }
+}
+
+fn copy(wave: &WaveData) -> Data {
+    Data::Amplitude(WaveData { samples: wave.samples.clone(), sample_rate: wave.sample_rate })
+}
+
+impl Filter for MultiBandExtractor {
+    fn input_type(&self) -> Type {
+        Type::Amplitude
+    }
+
+    fn output_type(&self) -> Type {
+        Type::Frequency
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = data else {
+            return None;
+        };
+
+        let outputs = self.bands
+            .iter()
+            .map(|band| (band.sampling_size(), band.filter(copy(&wave))))
+            .collect();
+
+        Self::join(outputs, Some(wave.sample_rate))
+    }
+}
+
+impl StreamFilter for MultiBandExtractor {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = chunk else {
+            return None;
+        };
+
+        let outputs = self.bands
+            .iter_mut()
+            .map(|band| (band.sampling_size(), band.feed(copy(&wave))))
+            .collect();
+
+        let lead_in = (!self.is_started).then_some(wave.sample_rate);
+        self.is_started = true;
+        Self::join(outputs, lead_in)
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        // Each band has got the samples, so each one has its channels to flush:
+        if !std::mem::take(&mut self.is_started) {
+            return None;
+        }
+
+        let outputs = self.bands
+            .iter_mut()
+            .map(|band| (band.sampling_size(), band.finish()))
+            .collect();
+
+        Self::join(outputs, None)
+    }
+}
+
+
+
+#[test]
+#[allow(clippy::cast_precision_loss)]
+fn test() {
+    use super::freq_extractor::Silence;
+
+    const SAMPLE_RATE: u32 = 22050;
+    const BASS: f32 = 55.0;
+    const TREBLE: f32 = 3000.0;
+
+    // Half a second of silence, then both tones start at once:
+    let onset = SAMPLE_RATE as usize / 2;
+    let samples = (0..3 * onset)
+        .map(|index| if index < onset {
+            0.0
+        } else {
+            let time = index as f32 / SAMPLE_RATE as f32;
+            0.5 * (std::f32::consts::TAU * BASS * time).sin() + 0.5 * (std::f32::consts::TAU * TREBLE * time).sin()
+        })
+        .collect::<Vec<f32>>();
+
+    // The gate opens once the tones fill a half of the window:
+    let silence = Silence { threshold: -9.5, relative: None, hysteresis: 0.0 };
+    let band = |min, max, sampling, step| FreqExtractor::new(Some(min), Some(max), sampling, step, 1).with_silence(silence);
+    let mut extractor = MultiBandExtractor::new(vec![band(20, 200, 8192, 256), band(200, 8000, 1024, 64)]);
+
+    let mut channels = vec![Vec::new(); 2];
+    for chunk in samples.chunks(5000) {
+        let Some(Data::Frequency(merged)) = extractor.feed(Data::Amplitude(WaveData { samples: chunk.to_vec(), sample_rate: SAMPLE_RATE })) else {
+            panic!("Unexpected data type");
+        };
+        assert_eq!(merged.len(), 2);
+        channels.iter_mut().zip(merged).for_each(|(channel, records)| channel.extend(records));
+    }
+    if let Some(Data::Frequency(merged)) = extractor.finish() {
+        channels.iter_mut().zip(merged).for_each(|(channel, records)| channel.extend(records));
+    }
+
+    let onset_ns = |channel: &Vec<FreqRecord<f32>>| channel.iter().take_while(|record| record.freq <= 0.0).map(|record| record.duration).sum::<Nsec>();
+    let tone = |channel: &Vec<FreqRecord<f32>>| channel.iter().find(|record| record.freq > 0.0).map_or(0.0, |record| record.freq);
+
+    assert!((tone(&channels[0]) - BASS).abs() < 1.0, "{}", tone(&channels[0]));
+    assert!((tone(&channels[1]) - TREBLE).abs() < 5.0, "{}", tone(&channels[1]));
+
+    // Without the lead-in the bass would come 160 ms earlier than the treble:
+    let (bass_onset, treble_onset) = (onset_ns(&channels[0]), onset_ns(&channels[1]));
+    assert!(bass_onset.abs_diff(treble_onset) < 30_000_000, "{bass_onset} vs {treble_onset}");
+    assert!(bass_onset.abs_diff(500_000_000) < 30_000_000, "{bass_onset}");
+}
@@ -1,23 +1,248 @@
-use note::Note;
+use note::{Note, Scale};
 
-use super::filter::{Type, Data, Filter};
+use super::filter::{Type, Data, Filter, StreamFilter, FreqData};
 
-pub struct NoteMatcher;
+///
+/// Pitches of the 12 degrees above the tonic.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Temperament {
+    Equal,
+    Just,         // 5-limit ratios, the pure thirds and fifths of the key
+    Pythagorean,  // Stacked pure fifths
+    Custom([f32; 12]) // Cents of each degree above the tonic
+}
+
+impl Temperament {
+    #[must_use]
+    pub fn cents(self) -> [f32; 12] {
+        let ratios: [f32; 12] = match self {
+            Temperament::Equal => return std::array::from_fn(|degree| 100.0 * f32::from(u8::try_from(degree).unwrap_or_default())),
+            Temperament::Just => [1.0, 16.0 / 15.0, 9.0 / 8.0, 6.0 / 5.0, 5.0 / 4.0, 4.0 / 3.0, 45.0 / 32.0, 3.0 / 2.0, 8.0 / 5.0, 5.0 / 3.0, 9.0 / 5.0, 15.0 / 8.0],
+            Temperament::Pythagorean => [
+                1.0, 256.0 / 243.0, 9.0 / 8.0, 32.0 / 27.0, 81.0 / 64.0, 4.0 / 3.0,
+                729.0 / 512.0, 3.0 / 2.0, 128.0 / 81.0, 27.0 / 16.0, 16.0 / 9.0, 243.0 / 128.0
+            ],
+            Temperament::Custom(cents) => return cents
+        };
+
+        ratios.map(|ratio| 1200.0 * ratio.log2())
+    }
+}
+
+///
+/// Snaps the frequencies to the degrees of the scale in the given temperament.
+/// The default one is the chromatic scale in the equal temperament with A4 = 440 Hz,
+/// i.e. the nearest note. The key is estimated from the whole data if it matters
+/// (a scale other than the chromatic one or an unequal temperament) but isn't given.
+///
+pub struct NoteMatcher {
+    tonic: Option<u8>,    // Pitch class of the key, 0 is C
+    scale: Option<Scale>, // Either major or minor, whichever fits the data better
+    a4_freq: f32,
+    temperament: Temperament,
+    pending: Option<FreqData<f32>> // The whole stream is gathered to estimate the key
+}
+
+const TONIC_WEIGHT: f32 = 1.0; // Extra weight of the tonic and of the fifth for the key estimation,
+const FIFTH_WEIGHT: f32 = 0.5; // so the relative keys sharing the same notes are told apart
+
+impl Default for NoteMatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NoteMatcher {
+    #[must_use]
+    pub fn new() -> Self {
+        Self { tonic: None, scale: Some(Scale::Chromatic), a4_freq: 440.0, temperament: Temperament::Equal, pending: None }
+    }
+
+    ///
+    /// The tonic is the pitch class of the key (0 is C, 11 is B), it's estimated
+    /// from the data if absent as well as the choice between the major and minor scales.
+    ///
+    #[must_use]
+    pub fn with_key(mut self, tonic: Option<u8>, scale: Option<Scale>) -> Self {
+        self.tonic = tonic.map(|tonic| tonic % Note::SEMITONE_COUNT);
+        self.scale = scale;
+        self
+    }
+
+    #[must_use]
+    pub fn with_tuning(mut self, a4_freq: f32, temperament: Temperament) -> Self {
+        self.a4_freq = a4_freq;
+        self.temperament = temperament;
+        self
+    }
+
+    fn needs_estimation(&self) -> bool {
+        self.tonic.is_none() && (self.scale != Some(Scale::Chromatic) || self.temperament != Temperament::Equal)
+    }
+
+    ///
+    /// Pitch class of the equal-tempered note nearest to the frequency.
+    ///
+    fn pitch_class(&self, freq: f32) -> usize {
+        usize::from(Note::from_freq_at(freq, self.a4_freq).0.pitch_class())
+    }
+
+    ///
+    /// The key whose scale covers the most of the sounding time, the scale is chosen from
+    /// the major and minor ones if any scale would do. Returns the tonic and the scale.
+    ///
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    fn estimate_key(&self, channels: &FreqData<f32>) -> (u8, Scale) {
+        let mut durations = [0_f32; 12];
+        for record in channels.iter().flatten().filter(|record| record.freq > 0.0) {
+            durations[self.pitch_class(record.freq)] += record.duration as f32;
+        }
+
+        let scales = match self.scale {
+            None | Some(Scale::Chromatic) => &[Scale::Major, Scale::Minor][..],
+            Some(ref scale) => std::slice::from_ref(scale)
+        };
+        let score = |tonic: usize, scale: Scale| -> f32 {
+            (0..12)
+                .filter(|degree| scale.contains(*degree))
+                .map(|degree| {
+                    let emphasis = match degree {
+                        0 => TONIC_WEIGHT,
+                        7 => FIFTH_WEIGHT,
+                        _ => 0.0
+                    };
+                    (1.0 + emphasis) * durations[(tonic + degree) % 12]
+                })
+                .sum()
+        };
+
+        let (tonic, scale) = scales
+            .iter()
+            .flat_map(|scale| (0..12).map(move |tonic| (tonic, *scale)))
+            .max_by(|left, right| score(left.0, left.1).total_cmp(&score(right.0, right.1)))
+            .unwrap_or((0, Scale::Major));
+
+        // The chromatic scale stays chromatic, only its tonic matters for the temperament:
+        (tonic as u8, if self.scale == Some(Scale::Chromatic) { Scale::Chromatic } else { scale })
+    }
+
+    ///
+    /// Nearest degree of the scale in the octave of the frequency or in the neighbour ones.
+    ///
+    #[allow(clippy::cast_possible_truncation)]
+    fn snap(&self, freq: f32, tonic: u8, scale: Scale) -> f32 {
+        let cents = self.temperament.cents();
+        let tonic_freq = Note::from_semitone(tonic + 4 * Note::SEMITONE_COUNT).freq_at(self.a4_freq);
+        let offset = 1200.0 * (freq / tonic_freq).log2();
+        let octave = (offset / 1200.0).floor();
+
+        let nearest = [octave - 1.0, octave, octave + 1.0]
+            .into_iter()
+            .flat_map(|octave| (0..12).filter(|degree| scale.contains(*degree)).map(move |degree| 1200.0 * octave + cents[degree]))
+            .min_by(|left, right| (left - offset).abs().total_cmp(&(right - offset).abs()));
+
+        nearest.map_or(freq, |nearest| tonic_freq * 2_f32.powf(nearest / 1200.0))
+    }
+
+    fn apply(&self, mut channels: FreqData<f32>) -> FreqData<f32> {
+        let (tonic, scale) = match (self.tonic, self.scale) {
+            (Some(tonic), Some(scale)) => (tonic, scale),
+            (Some(tonic), None) => (tonic, Scale::Major),
+            (None, _) if self.needs_estimation() => self.estimate_key(&channels),
+            (None, scale) => (0, scale.unwrap_or(Scale::Chromatic))
+        };
+
+        // An empty scale would swallow everything:
+        let scale = if scale.degrees() == 0 { Scale::Chromatic } else { scale };
+
+        // Pauses stay silent:
+        channels.iter_mut().flatten().filter(|freq_record| freq_record.freq > 0.0).for_each(|freq_record| {
+            freq_record.freq = self.snap(freq_record.freq, tonic, scale);
+        });
+
+        channels
+    }
+}
 
 impl Filter for NoteMatcher {
-    fn filter_type(&self) -> Type {
+    fn input_type(&self) -> Type {
         Type::Frequency
     }
 
     fn filter(&self, data: Data) -> Option<Data> {
-        let Data::Frequency(mut channels) = data else {
+        let Data::Frequency(channels) = data else {
             return None;
         };
 
-        channels.iter_mut().flatten().for_each(|freq_record| {
-            freq_record.freq = Note::find_nearest(freq_record.freq).freq();
-        });
+        Some(Data::Frequency(self.apply(channels)))
+    }
+}
+
+impl StreamFilter for NoteMatcher {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        if !self.needs_estimation() {
+            return self.filter(chunk);
+        }
+
+        let Data::Frequency(channels) = chunk else {
+            return None;
+        };
 
-        Some(Data::Frequency(channels))
+        let pending = self.pending.get_or_insert_with(FreqData::default);
+        pending.resize_with(pending.len().max(channels.len()), Vec::new);
+        for (pending, records) in pending.iter_mut().zip(channels) {
+            pending.extend(records);
+        }
+
+        Some(Data::Frequency(FreqData::default()))
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        let pending = self.pending.take()?;
+        Some(Data::Frequency(self.apply(pending)))
     }
-}
\ No newline at end of file
+}
+
+
+
+#[test]
+fn test() {
+    use super::filter::FreqRecord;
+
+    let data = |freqs: &[f32]| vec![freqs.iter().map(|freq| FreqRecord { freq: *freq, duration: 100 }).collect::<Vec<FreqRecord<f32>>>()];
+    let snapped = |matcher: &NoteMatcher, freqs: &[f32]| {
+        let Some(Data::Frequency(channels)) = matcher.filter(Data::Frequency(data(freqs))) else {
+            panic!("Unexpected data type");
+        };
+        channels[0].iter().map(|record| record.freq).collect::<Vec<f32>>()
+    };
+    let close = |left: &[f32], right: &[f32]| left.iter().zip(right).all(|(left, right)| (left - right).abs() < 0.01);
+
+    // The nearest note by default, pauses stay:
+    let chromatic = NoteMatcher::new();
+    assert!(close(&snapped(&chromatic, &[0.0, 445.0, 270.0]), &[0.0, 440.0, Note::Cs(4).freq()]));
+
+    // C# is out of the key of C major, so it goes to the nearer of C and D:
+    let c_major = NoteMatcher::new().with_key(Some(0), Some(Scale::Major));
+    assert!(close(&snapped(&c_major, &[275.0, 282.0]), &[Note::C(4).freq(), Note::D(4).freq()]));
+
+    // The pure major third of A is 386 cents above it, and A4 is 432 Hz:
+    let just = NoteMatcher::new().with_key(Some(9), Some(Scale::Major)).with_tuning(432.0, Temperament::Just);
+    assert!(close(&snapped(&just, &[540.0, 865.0]), &[540.0, 864.0]));
+
+    // The melody of A minor with no key given:
+    let melody = [440.0, 493.9, 523.3, 587.3, 659.3, 523.3, 440.0, 329.6, 440.0, 466.2];
+    let mut matcher = NoteMatcher::new().with_key(None, Some(Scale::MinorPentatonic));
+    assert!(matcher.feed(Data::Frequency(data(&melody))).is_some_and(|chunk| chunk.is_empty()));
+    assert_eq!(matcher.estimate_key(&data(&melody)), (9, Scale::MinorPentatonic));
+
+    assert_eq!(NoteMatcher::new().with_key(None, None).estimate_key(&data(&melody)), (9, Scale::Minor));
+
+    let Some(Data::Frequency(channels)) = matcher.finish() else {
+        panic!("Unexpected data type");
+    };
+    // B and A# aren't in the pentatonic scale of A minor:
+    assert!(close(&channels[0].iter().map(|record| record.freq).collect::<Vec<f32>>()[..2], &[440.0, Note::C(5).freq()]));
+    assert!((channels[0][9].freq - 440.0).abs() < 0.01);
+}
@@ -0,0 +1,68 @@
+use std::sync::{OnceLock, atomic::{AtomicUsize, Ordering}};
+
+const BATCH_SIZE: usize = 16; // Items claimed by the worker at once, so the workers don't contend for each one
+
+fn worker_count() -> usize {
+    static WORKER_COUNT: OnceLock<usize> = OnceLock::new();
+    *WORKER_COUNT.get_or_init(|| std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get))
+}
+
+///
+/// Maps the items on all available cores preserving their order.
+/// The workers claim batches of items one by one, so the cheap items don't leave the cores idle,
+/// and the inputs of a single batch are mapped in place without spawning any threads.
+///
+pub fn map<Item, Output, Map>(items: &[Item], map: Map) -> Vec<Output>
+where
+    Item: Sync,
+    Output: Send,
+    Map: Fn(&Item) -> Output + Sync
+{
+    let workers = worker_count().min(items.len().div_ceil(BATCH_SIZE));
+    if workers <= 1 {
+        return items.iter().map(map).collect();
+    }
+
+    let next_batch = AtomicUsize::new(0);
+    let work = || {
+        let mut batches = Vec::new();
+        loop {
+            let start = next_batch.fetch_add(BATCH_SIZE, Ordering::Relaxed);
+            if start >= items.len() {
+                return batches;
+            }
+
+            let end = (start + BATCH_SIZE).min(items.len());
+            batches.push((start, items[start..end].iter().map(&map).collect::<Vec<Output>>()));
+        }
+    };
+
+    let mut batches = std::thread::scope(|scope| {
+        let workers = (0..workers).map(|_| scope.spawn(work)).collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect::<Vec<(usize, Vec<Output>)>>()
+    });
+
+    batches.sort_unstable_by_key(|(start, _)| *start);
+    batches.into_iter().flat_map(|(_, outputs)| outputs).collect()
+}
+
+
+
+#[test]
+fn test() {
+    let items = (0..1000).collect::<Vec<u32>>();
+    assert_eq!(map(&items, |item| item * 2), items.iter().map(|item| item * 2).collect::<Vec<u32>>());
+    assert!(map(&[] as &[u32], |item| *item).is_empty());
+
+    // The batches finish out of order when the first ones are slower, the outputs keep the order of the inputs:
+    let slow = |item: &u32| {
+        if *item < 32 {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        item + 1
+    };
+    assert_eq!(map(&items, slow), items.iter().map(|item| item + 1).collect::<Vec<u32>>());
+}
@@ -0,0 +1,198 @@
+use super::filter::{FreqRecord, FreqRecordFlt, FreqChannel, FreqData, Nsec};
+
+///
+/// Spectral peak of a single frame.
+///
+#[derive(Clone, Copy)]
+pub struct Peak {
+    pub freq: f32,
+    pub magnitude_db: f32
+}
+
+///
+/// Partial tracking in the style of McAulay-Quatieri: each channel follows a single voice
+/// from frame to frame by the nearest frequency, the voice dies when nothing continues it
+/// and a new one is born from the strongest of the unclaimed peaks.
+///
+pub struct Partials {
+    slots: Vec<Slot>,
+    min_frames: u32 // Shorter tracks are played as pauses
+}
+
+struct Track {
+    freq: f32,
+    frames: u32
+}
+
+struct Slot {
+    track: Option<Track>,
+    pending: Vec<FreqRecordFlt>, // Frames of the track which isn't long enough yet
+    last_record: Option<FreqRecordFlt>
+}
+
+const MAX_JUMP_CENTS: f32 = 100.0; // The track doesn't continue with peaks further than a semitone
+const SAME_FREQ_CENTS: f32 = 10.0; // Consecutive frames closer than this are merged into a single record
+
+fn cents(freq: f32, base: f32) -> f32 {
+    1200.0 * (freq / base).log2()
+}
+
+impl Slot {
+    ///
+    /// Appends the frame to the channel, prolonging the last record if the frequency is the same.
+    ///
+    fn emit(&mut self, freq: f32, duration: Nsec, channel: &mut FreqChannel<f32>) {
+        if let Some(last) = &mut self.last_record {
+            let is_same = match (freq > 0.0, last.freq > 0.0) {
+                (true, true) => cents(freq, last.freq).abs() < SAME_FREQ_CENTS,
+                (is_sound, was_sound) => is_sound == was_sound
+            };
+
+            if is_same {
+                last.duration += duration;
+                return;
+            }
+        }
+
+        channel.extend(self.last_record.replace(FreqRecord { freq, duration }));
+    }
+
+    fn end(&mut self, channel: &mut FreqChannel<f32>) {
+        // The track turned out too short, so its frames are silent:
+        for record in std::mem::take(&mut self.pending) {
+            self.emit(0.0, record.duration, channel);
+        }
+
+        self.track = None;
+    }
+
+    fn frame(&mut self, duration: Nsec, min_frames: u32, channel: &mut FreqChannel<f32>) {
+        let Some(track) = &mut self.track else {
+            self.emit(0.0, duration, channel);
+            return;
+        };
+
+        track.frames += 1;
+        let freq = track.freq;
+
+        if track.frames < min_frames {
+            self.pending.push(FreqRecord { freq, duration });
+            return;
+        }
+
+        for record in std::mem::take(&mut self.pending) {
+            self.emit(record.freq, record.duration, channel);
+        }
+        self.emit(freq, duration, channel);
+    }
+}
+
+impl Partials {
+    #[must_use]
+    pub fn new(channel_count: usize, min_frames: u32) -> Self {
+        let slots = (0..channel_count)
+            .map(|_| Slot { track: None, pending: Vec::new(), last_record: None })
+            .collect();
+
+        Self { slots, min_frames }
+    }
+
+    ///
+    /// Continues the tracks with the peaks of the next frame sorted by magnitude
+    /// and appends the frame of the given duration to each channel.
+    ///
+    pub fn advance(&mut self, peaks: &[Peak], duration: Nsec, channels: &mut FreqData<f32>) {
+        // A track may continue with a weaker peak, but only the strongest ones give birth.
+        // The peak at DC has no pitch and would make the distance in cents NaN:
+        let candidates = peaks
+            .iter()
+            .filter(|peak| peak.freq > 0.0 && peak.freq.is_finite())
+            .take(2 * self.slots.len())
+            .copied()
+            .collect::<Vec<Peak>>();
+
+        let mut pairs = Vec::new();
+        for (slot_index, slot) in self.slots.iter().enumerate() {
+            if let Some(track) = &slot.track {
+                for (peak_index, peak) in candidates.iter().enumerate() {
+                    let distance = cents(peak.freq, track.freq).abs();
+                    if distance <= MAX_JUMP_CENTS {
+                        pairs.push((distance, slot_index, peak_index));
+                    }
+                }
+            }
+        }
+
+        // The closest pairs are matched first:
+        pairs.sort_by(|left, right| left.0.total_cmp(&right.0));
+
+        let mut claimed = vec![false; candidates.len()];
+        let mut continuations = vec![None; self.slots.len()];
+        for (_, slot_index, peak_index) in pairs {
+            if continuations[slot_index].is_none() && !claimed[peak_index] {
+                continuations[slot_index] = Some(candidates[peak_index].freq);
+                claimed[peak_index] = true;
+            }
+        }
+
+        let mut births = candidates
+            .iter()
+            .zip(&claimed)
+            .take(self.slots.len())
+            .filter(|(_, is_claimed)| !**is_claimed)
+            .map(|(peak, _)| peak.freq);
+
+        for ((slot, continuation), channel) in self.slots.iter_mut().zip(continuations).zip(channels.iter_mut()) {
+            if let (Some(freq), Some(track)) = (continuation, &mut slot.track) {
+                track.freq = freq;
+            } else {
+                slot.end(channel);
+                slot.track = births.next().map(|freq| Track { freq, frames: 0 });
+            }
+
+            slot.frame(duration, self.min_frames, channel);
+        }
+    }
+
+    ///
+    /// Ends all tracks and flushes the last records.
+    ///
+    pub fn finish(&mut self, channels: &mut FreqData<f32>) {
+        for (slot, channel) in self.slots.iter_mut().zip(channels.iter_mut()) {
+            slot.end(channel);
+            channel.extend(slot.last_record.take());
+        }
+    }
+}
+
+
+
+#[test]
+fn test() {
+    let peak = |freq, magnitude_db| Peak { freq, magnitude_db };
+    let mut partials = Partials::new(2, 3);
+    let mut channels = vec![FreqChannel::default(); 2];
+
+    // The bass and the melody swap their ranks, but each one stays in its channel;
+    // the short blip at 3000 Hz is dropped:
+    partials.advance(&[peak(440.0, -10.0), peak(110.0, -20.0)], 10, &mut channels);
+    partials.advance(&[peak(110.0, -10.0), peak(442.0, -20.0)], 10, &mut channels);
+    partials.advance(&[peak(110.0, -10.0), peak(450.0, -20.0)], 10, &mut channels);
+    partials.advance(&[peak(3000.0, -5.0), peak(110.0, -10.0)], 10, &mut channels);
+    partials.advance(&[peak(110.0, -10.0)], 10, &mut channels);
+    partials.advance(&[peak(110.0, -10.0)], 10, &mut channels);
+    partials.finish(&mut channels);
+
+    let summary = |channel: &FreqChannel<f32>| channel.iter().map(|record| (record.freq, record.duration)).collect::<Vec<(f32, u64)>>();
+    assert_eq!(summary(&channels[0]), [(440.0, 20), (450.0, 10), (0.0, 30)]);
+    assert_eq!(summary(&channels[1]), [(110.0, 60)]);
+
+    // The peaks without a pitch neither continue nor give birth to the tracks:
+    let mut partials = Partials::new(1, 1);
+    let mut channels = vec![FreqChannel::default(); 1];
+    partials.advance(&[peak(0.0, -5.0), peak(220.0, -10.0)], 10, &mut channels);
+    partials.advance(&[peak(0.0, -5.0), peak(f32::NAN, -6.0), peak(221.0, -10.0)], 10, &mut channels);
+    partials.advance(&[peak(0.0, -5.0)], 10, &mut channels);
+    partials.finish(&mut channels);
+    assert_eq!(summary(&channels[0]), [(220.0, 20), (0.0, 10)]);
+}
@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+
+use super::filter::{Data, StreamFilter};
+
+///
+/// Pulls chunks from the source lazily and passes them through the chain of filters.
+/// Yields non-empty filtered chunks, so the playback can start after the first chunk
+/// and the memory consumption doesn't depend on the length of the source.
+///
+pub struct Pipeline<Source: Iterator<Item = Data>> {
+    source: Source,
+    filters: Vec<Box<dyn StreamFilter>>,
+    ready: VecDeque<Data>,
+    is_finished: bool,
+    is_mismatched: bool
+}
+
+impl<Source: Iterator<Item = Data>> Pipeline<Source> {
+    pub fn new(source: Source, filters: Vec<Box<dyn StreamFilter>>) -> Self {
+        Self { source, filters, ready: VecDeque::new(), is_finished: false, is_mismatched: false }
+    }
+
+    /// Appends the filter to the end of the chain.
+    pub fn push(&mut self, filter: Box<dyn StreamFilter>) {
+        self.filters.push(filter);
+    }
+
+    /// Whether the pipeline has stopped as some filter got a chunk of the inappropriate type.
+    #[must_use]
+    pub fn is_mismatched(&self) -> bool {
+        self.is_mismatched
+    }
+
+    fn pass(&mut self, mut chunk: Data, first_filter: usize) {
+        for filter in &mut self.filters[first_filter..] {
+            let Some(filtered) = filter.feed(chunk) else {
+                self.is_mismatched = true;
+                self.is_finished = true;
+                return;
+            };
+            chunk = filtered;
+        }
+
+        if !chunk.is_empty() {
+            self.ready.push_back(chunk);
+        }
+    }
+
+    fn finish(&mut self) {
+        // Each filter flushes its remainder through the rest of the chain:
+        for filter_index in 0..self.filters.len() {
+            if let Some(remainder) = self.filters[filter_index].finish() {
+                self.pass(remainder, filter_index + 1);
+            }
+        }
+
+        self.is_finished = true;
+    }
+}
+
+impl<Source: Iterator<Item = Data>> Iterator for Pipeline<Source> {
+    type Item = Data;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(chunk) = self.ready.pop_front() {
+                return Some(chunk);
+            }
+
+            if self.is_finished {
+                return None;
+            }
+
+            match self.source.next() {
+                Some(chunk) => self.pass(chunk, 0),
+                None => self.finish()
+            }
+        }
+    }
+}
+
+
+#[test]
+fn test() {
+    use super::{bakery::{Bakery, Strategy}, filter::{Filter, Position, WaveData}, resampler::Resampler};
+
+    let samples = (0..4000_u16).map(|index| f32::from(index % 50) / 25.0 - 1.0).collect::<Vec<f32>>();
+    let chunks = samples.chunks(700).map(|chunk| Data::Amplitude(WaveData { samples: chunk.to_vec(), sample_rate: 8000 })).collect::<Vec<Data>>();
+
+    // The chain yields the same durations as the whole wave passed through its filters:
+    let filters: Vec<Box<dyn StreamFilter>> = vec![Box::new(Resampler::new(4000)), Box::new(Bakery::new(Strategy::Simple))];
+    let streamed = Pipeline::new(chunks.into_iter(), filters)
+        .flat_map(|chunk| match chunk {
+            Data::Position(records) => records,
+            _ => panic!("Unexpected data type")
+        })
+        .fold(Vec::new(), |mut merged: Vec<(bool, u64)>, record| {
+            match merged.last_mut() {
+                Some((is_up, duration)) if *is_up == (record.position == Position::Up) => *duration += record.duration,
+                _ => merged.push((record.position == Position::Up, record.duration))
+            }
+            merged
+        });
+
+    let Some(resampled) = Resampler::new(4000).filter(Data::Amplitude(WaveData { samples, sample_rate: 8000 })) else {
+        panic!("Unexpected data type");
+    };
+    let Some(Data::Position(batch)) = Bakery::new(Strategy::Simple).filter(resampled) else {
+        panic!("Unexpected data type");
+    };
+    let batch = batch.iter().map(|record| (record.position == Position::Up, record.duration)).collect::<Vec<(bool, u64)>>();
+    assert_eq!(streamed.iter().map(|(_, duration)| duration).sum::<u64>(), batch.iter().map(|(_, duration)| duration).sum::<u64>());
+    assert_eq!(streamed, batch);
+
+    // The mismatched chunk stops the pipeline:
+    let mut pipeline = Pipeline::new(std::iter::once(Data::Position(Vec::new())), vec![Box::new(Resampler::new(4000)) as Box<dyn StreamFilter>]);
+    assert!(pipeline.next().is_none());
+    assert!(pipeline.is_mismatched());
+}
@@ -0,0 +1,932 @@
+//!
+//! Textual description of the filter chain:
+//!
+//!     highpass(300) | lowpass(4000) | extract(min=100,max=3000,channels=3) | notes
+//!
+//!     Stages are separated by '|' and applied from left to right.
+//!     Arguments are optional, each one is either a value or a key=value pair:
+//!         resample(<hz>)
+//!         highpass(<hz>)
+//!         lowpass(<hz>)
+//!         biquad(<lowpass|highpass|bandpass|notch|peak|lowshelf|highshelf>,freq=<hz>,q=<q>,gain=<db>)
+//!         butterworth(<lowpass|highpass>,freq=<hz>,order=<n>)
+//!         linkwitz-riley(<lowpass|highpass>,freq=<hz>,order=<even n>)
+//!         normalize(<peak|rms>,level=<db>)
+//!         compress(threshold=<db>,ratio=<ratio>,attack=<ms>,release=<ms>,makeup=<db>)
+//!         limit(threshold=<db>,release=<ms>)
+//!         gate(threshold=<db>,attack=<ms>,hold=<ms>,release=<ms>)
+//!         bake(simple), bake(diff=<percentage>), bake(sigma-delta,order=<1|2>,rate=<hz>)
+//!             or bake(pwm,carrier=<hz>,levels=<count>), bake is the same as bake(diff=5)
+//!         extract(min=<hz>,max=<hz>,sampling=<samples>,step=<samples>,channels=<count>,
+//!             window=<rect|hann|hamming|blackman-harris>,padding=<factor>,interpolation=<none|parabolic|phase>,
+//!             min-track=<ms>,silence=<db|off>,relative=<db>,hysteresis=<db>)
+//!             or extract(band=<min>-<max>[/<sampling>[/<step>[/<channels>]]],band=...,<shared arguments>)
+//!         pitch(min=<hz>,max=<hz>,step=<samples>,confidence=<0..1>,silence=<db>)
+//!         cqt(low=<note>,high=<note>,bins=<per octave>,step=<samples>,channels=<count>,min-track=<ms>,silence=<db>)
+//!         notes, notes(key=<tonic|auto>,scale=<name|degrees>,a4=<hz>,temperament=<equal|just|pythagorean|cents>)
+//!         transpose(<interval>,octaves=<n>,fit=<low>-<high>,scope=<channel|phrase>,gap=<ms>), transpose(fit)
+//!         vibrato(rate=<hz>,depth=<cents>,delay=<ms>,step=<ms>)
+//!         glide(time=<ms>,step=<ms>)
+//!         arpeggio(<up|down|updown>,chord=<quality>,note=<ms>)
+//!
+//! Each stage must accept the kind of data produced by the previous one,
+//! the chain is rejected otherwise before any data is decoded.
+//!
+
+use std::{ops::Range, str::FromStr};
+
+use note::{ChordQuality, Interval, Note, Scale};
+
+use super::{
+    bakery::{Bakery, Modulator, Strategy},
+    constant_q::ConstantQ,
+    dynamics::{Compressor, Gate, Measure, Normalizer},
+    filter::{HertzInt, Nsec, StreamFilter, Type},
+    freq_extractor::{FreqExtractor, Interpolation, Silence, Window},
+    freq_filters::{Biquad, HighPass, LowPass, Pass, Response},
+    multi_band::MultiBandExtractor,
+    note_matcher::{NoteMatcher, Temperament},
+    pitch_effects::{self, Arpeggio, Pattern, Portamento, Source, Vibrato, DEFAULT_STEP_MS},
+    pitch_tracker::PitchTracker,
+    resampler::Resampler,
+    transposer::{Scope, Transposer, COMFORTABLE_RANGE}
+};
+
+const NS_IN_SEC: u64 = 1_000_000_000;
+
+#[derive(Debug)]
+pub struct ParseError {
+    expression: String,
+    span: Range<usize>, // Offending part of the expression in bytes
+    message: String
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let indent = self.expression[..self.span.start].chars().count();
+        let width = self.expression[self.span.clone()].chars().count().max(1);
+
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "    {}", self.expression)?;
+        write!(f, "    {}{}", " ".repeat(indent), "^".repeat(width))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+
+
+#[derive(Clone, Copy)]
+struct Argument<'a> {
+    key: Option<&'a str>,
+    value: &'a str
+}
+
+struct Stage<'a> {
+    name: &'a str,
+    arguments: Vec<Argument<'a>>
+}
+
+///
+/// Returns the byte range of the text without the surrounding whitespaces.
+///
+fn trimmed_span(text: &str, offset: usize) -> Range<usize> {
+    let start = offset + (text.len() - text.trim_start().len());
+    let end = offset + text.trim_end().len();
+    start..end.max(start)
+}
+
+fn parse_stage(text: &str) -> Result<Stage<'_>, String> {
+    let Some(open) = text.find('(') else {
+        return Ok(Stage { name: text, arguments: Vec::new() });
+    };
+
+    let Some(list) = text[open + 1..].strip_suffix(')') else {
+        return Err(String::from("Missing the closing parenthesis"));
+    };
+
+    let name = text[..open].trim_end();
+    let arguments = list
+        .split(',')
+        .map(str::trim)
+        .filter(|argument| !argument.is_empty())
+        .map(|argument| match argument.split_once('=') {
+            Some((key, value)) => Argument { key: Some(key.trim()), value: value.trim() },
+            None => Argument { key: None, value: argument }
+        })
+        .collect();
+
+    Ok(Stage { name, arguments })
+}
+
+fn number<T: FromStr>(argument: &Argument) -> Result<T, String>
+    where T::Err: std::fmt::Display
+{
+    argument.value.parse::<T>().map_err(|err| format!("Invalid value {}: {err}", argument.value))
+}
+
+///
+/// The only positional argument of the stage.
+///
+fn single<'a>(stage: &'a Stage) -> Result<&'a Argument<'a>, String> {
+    match stage.arguments.as_slice() {
+        [argument @ Argument { key: None, .. }] => Ok(argument),
+        _ => Err(format!("{} requires exactly one value, e.g. {}(1000)", stage.name, stage.name))
+    }
+}
+
+fn positive_freq(stage: &Stage) -> Result<f32, String> {
+    let freq = number::<f32>(single(stage)?)?;
+    if !freq.is_finite() || freq <= 0.0 {
+        return Err(format!("Invalid frequency {freq}"));
+    }
+
+    Ok(freq)
+}
+
+///
+/// Positional response followed by the named frequency, Q and gain.
+///
+fn build_biquad(stage: &Stage) -> Result<Biquad, String> {
+    let mut freq = None;
+    let mut q = Biquad::DEFAULT_Q;
+    let mut gain = None;
+    let mut response = None;
+
+    for argument in &stage.arguments {
+        match argument.key {
+            Some("freq") => freq = Some(number::<f32>(argument)?),
+            Some("q") => q = number(argument)?,
+            Some("gain") => gain = Some(number::<f32>(argument)?),
+            Some(key) => return Err(format!("Unknown argument {key}")),
+            None => response = Some(argument.value)
+        }
+    }
+
+    let freq = freq.filter(|freq| freq.is_finite() && *freq > 0.0).ok_or("Missing or invalid freq=<hz>")?;
+    if !q.is_finite() || q <= 0.0 {
+        return Err(format!("Invalid Q {q}"));
+    }
+
+    let with_gain = |response: fn(f32) -> Response| gain.map(response).ok_or("Missing gain=<db>");
+    let response = match response {
+        Some("lowpass") => Response::LowPass,
+        Some("highpass") => Response::HighPass,
+        Some("bandpass") => Response::BandPass,
+        Some("notch" | "bandstop") => Response::BandStop,
+        Some("peak") => with_gain(Response::Peaking)?,
+        Some("lowshelf") => with_gain(Response::LowShelf)?,
+        Some("highshelf") => with_gain(Response::HighShelf)?,
+        Some(response) => return Err(format!("Unknown response {response}")),
+        None => return Err(String::from("Missing response, e.g. biquad(bandpass,freq=1000)"))
+    };
+
+    Ok(Biquad::new(response, freq, q))
+}
+
+///
+/// Butterworth and Linkwitz-Riley filters: the pass, the cutoff and the order.
+///
+fn build_cascade(stage: &Stage) -> Result<Biquad, String> {
+    const MAX_ORDER: u8 = 16;
+
+    let mut freq = None;
+    let mut order = 4;
+    let mut pass = None;
+
+    for argument in &stage.arguments {
+        match (argument.key, argument.value) {
+            (Some("freq"), _) => freq = Some(number::<f32>(argument)?),
+            (Some("order"), _) => order = number(argument)?,
+            (Some(key), _) => return Err(format!("Unknown argument {key}")),
+            (None, "lowpass") => pass = Some(Pass::Low),
+            (None, "highpass") => pass = Some(Pass::High),
+            (None, value) => return Err(format!("Unknown pass {value}, expected lowpass or highpass"))
+        }
+    }
+
+    let freq = freq.filter(|freq| freq.is_finite() && *freq > 0.0).ok_or("Missing or invalid freq=<hz>")?;
+    let pass = pass.ok_or_else(|| format!("Missing pass, e.g. {}(lowpass,freq=2000)", stage.name))?;
+    if order == 0 || order > MAX_ORDER {
+        return Err(format!("Invalid order {order}, must be in the range of 1..{MAX_ORDER}"));
+    }
+
+    if stage.name == "butterworth" {
+        Ok(Biquad::butterworth(pass, freq, order))
+    } else {
+        Biquad::linkwitz_riley(pass, freq, order).ok_or_else(|| format!("Invalid order {order}, must be even"))
+    }
+}
+
+///
+/// Named arguments of the stage, the ones absent in the list are rejected.
+///
+fn named<'a>(stage: &'a Stage, keys: &[&str]) -> Result<impl Fn(&str) -> Option<&'a Argument<'a>>, String> {
+    if let Some(argument) = stage.arguments.iter().find(|argument| !argument.key.is_some_and(|key| keys.contains(&key))) {
+        return Err(match argument.key {
+            Some(key) => format!("Unknown argument {key}"),
+            None => format!("Missing name of the argument {}", argument.value)
+        });
+    }
+
+    Ok(|key: &str| stage.arguments.iter().rev().find(|argument| argument.key == Some(key)))
+}
+
+fn number_or<T: FromStr>(argument: Option<&Argument>, default: T) -> Result<T, String>
+    where T::Err: std::fmt::Display
+{
+    argument.map_or(Ok(default), number)
+}
+
+fn build_normalizer(stage: &Stage) -> Result<Normalizer, String> {
+    let mut measure = Measure::Peak;
+    let mut level = None;
+
+    for argument in &stage.arguments {
+        match (argument.key, argument.value) {
+            (None, "peak") => measure = Measure::Peak,
+            (None, "rms") => measure = Measure::Rms,
+            (None, value) => return Err(format!("Unknown measure {value}, expected peak or rms")),
+            (Some("level"), _) => level = Some(number::<f32>(argument)?),
+            (Some(key), _) => return Err(format!("Unknown argument {key}"))
+        }
+    }
+
+    // The RMS of music is much lower than its peaks:
+    let default_level = if measure == Measure::Peak { -1.0 } else { -14.0 };
+    Ok(Normalizer::new(measure, level.unwrap_or(default_level)))
+}
+
+fn build_compressor(stage: &Stage) -> Result<Compressor, String> {
+    let argument = named(stage, &["threshold", "ratio", "attack", "release", "makeup"])?;
+    let ratio = number_or(argument("ratio"), 4.0)?;
+    if ratio < 1.0 {
+        return Err(format!("Invalid ratio {ratio}, must be at least 1"));
+    }
+
+    Ok(Compressor::new(
+        number_or(argument("threshold"), -20.0)?,
+        ratio,
+        number_or(argument("attack"), 5.0)?,
+        number_or(argument("release"), 100.0)?,
+        number_or(argument("makeup"), 0.0)?
+    ))
+}
+
+fn build_limiter(stage: &Stage) -> Result<Compressor, String> {
+    let argument = named(stage, &["threshold", "release"])?;
+    Ok(Compressor::limiter(number_or(argument("threshold"), -1.0)?, number_or(argument("release"), 50.0)?))
+}
+
+fn build_gate(stage: &Stage) -> Result<Gate, String> {
+    let argument = named(stage, &["threshold", "attack", "hold", "release"])?;
+    Ok(Gate::new(
+        number_or(argument("threshold"), -50.0)?,
+        number_or(argument("attack"), 1.0)?,
+        number_or(argument("hold"), 50.0)?,
+        number_or(argument("release"), 100.0)?
+    ))
+}
+
+fn build_bakery(stage: &Stage, min_pulse_ns: Nsec) -> Result<Bakery, String> {
+    match stage.arguments.as_slice() {
+        [] => Ok(Bakery::new(Strategy::Differential(5))),
+        [Argument { key: None, value: "simple" }] => Ok(Bakery::new(Strategy::Simple)),
+        [argument @ Argument { key: Some("diff"), .. }] => Ok(Bakery::new(Strategy::Differential(number(argument)?))),
+        [Argument { key: None, value: "sigma-delta" }, rest @ ..] => build_sigma_delta(&Stage { name: stage.name, arguments: rest.to_vec() }, min_pulse_ns),
+        [Argument { key: None, value: "pwm" }, rest @ ..] => build_pwm(&Stage { name: stage.name, arguments: rest.to_vec() }, min_pulse_ns),
+        _ => Err(String::from("Unknown bake strategy, expected bake(simple), bake(diff=<percentage>), bake(sigma-delta) or bake(pwm)"))
+    }
+}
+
+///
+/// Order of the modulator and its oversampled rate, each tick must be at least the shortest pulse of the backend.
+/// The default rate is lowered to the fastest one the backend plays.
+///
+fn build_sigma_delta(stage: &Stage, min_pulse_ns: Nsec) -> Result<Bakery, String> {
+    const DEFAULT_RATE: HertzInt = 192_000;
+
+    let argument = named(stage, &["order", "rate"])?;
+    let modulator = match number_or(argument("order"), 2_u8)? {
+        1 => Modulator::FirstOrder,
+        2 => Modulator::SecondOrder,
+        order => return Err(format!("Invalid order {order}, expected 1 or 2"))
+    };
+
+    let max_rate = HertzInt::try_from(NS_IN_SEC / min_pulse_ns.max(1)).unwrap_or(HertzInt::MAX);
+    let rate = number_or(argument("rate"), DEFAULT_RATE.min(max_rate))?;
+    if rate == 0 {
+        return Err(String::from("The rate must be positive"));
+    }
+    if rate > max_rate {
+        return Err(format!("The rate of {rate} Hz is too fast for the backend, the highest one is {max_rate} Hz"));
+    }
+
+    Ok(Bakery::new(Strategy::SigmaDelta { modulator, rate }))
+}
+
+///
+/// Carrier frequency and the number of pulse widths,
+/// the carrier period must fit both the shortest Up and Down of the backend.
+///
+fn build_pwm(stage: &Stage, min_pulse_ns: Nsec) -> Result<Bakery, String> {
+    let argument = named(stage, &["carrier", "levels"])?;
+    let carrier_hz = number_or::<HertzInt>(argument("carrier"), 20_000)?;
+    let levels = number_or::<u16>(argument("levels"), 32)?;
+
+    if carrier_hz == 0 {
+        return Err(String::from("The carrier must be positive"));
+    }
+    if levels < 2 {
+        return Err(format!("Invalid number of levels {levels}, must be at least 2"));
+    }
+
+    let max_carrier_hz = NS_IN_SEC / (2 * min_pulse_ns).max(1);
+    if u64::from(carrier_hz) > max_carrier_hz {
+        return Err(format!("The carrier of {carrier_hz} Hz is too fast for the backend, the highest one is {max_carrier_hz} Hz"));
+    }
+
+    Ok(Bakery::new(Strategy::Pwm { carrier_hz, levels }).with_min_pulse(min_pulse_ns))
+}
+
+///
+/// Band of the multi-band extractor: `<min>-<max>[/<sampling>[/<step>[/<channels>]]]`,
+/// the omitted values are taken from the stage.
+///
+fn parse_band(text: &str, sampling: u32, step: u32, channels: u8) -> Result<(u32, u32, u32, u32, u8), String> {
+    let mut parts = text.split('/');
+    let range = parts.next().unwrap_or_default();
+    let Some((min, max)) = range.split_once('-') else {
+        return Err(format!("Invalid band {text}, expected <min>-<max>[/<sampling>[/<step>[/<channels>]]]"));
+    };
+
+    let value = |part: &str| part.trim().parse::<u32>().map_err(|err| format!("Invalid value {part} of the band {text}: {err}"));
+    let (min, max) = (value(min)?, value(max)?);
+    if min >= max {
+        return Err(format!("Invalid band {text}, the lower bound must be below the upper one"));
+    }
+
+    let sampling = parts.next().map_or(Ok(sampling), value)?;
+    let step = parts.next().map_or(Ok(step), value)?;
+    let channels = match parts.next() {
+        Some(part) => part.trim().parse::<u8>().map_err(|err| format!("Invalid number of channels {part} of the band {text}: {err}"))?,
+        None => channels
+    };
+
+    if parts.next().is_some() {
+        return Err(format!("Invalid band {text}, too many values"));
+    }
+    if channels == 0 {
+        return Err(format!("Invalid band {text}, it must have at least one channel"));
+    }
+
+    Ok((min, max, sampling, step, channels))
+}
+
+///
+/// Single extractor or the multi-band one if the bands are given,
+/// the rest of the arguments are shared by all bands.
+///
+fn build_extractor(stage: &Stage) -> Result<Box<dyn StreamFilter>, String> {
+    const MAX_PADDING: u8 = 16;
+
+    let mut bands = Vec::new();
+    let mut min = None;
+    let mut max = None;
+    let mut sampling = 4096;
+    let mut step = 32;
+    let mut channels = 2;
+    let mut window = Window::Hann;
+    let mut padding = 1;
+    let mut interpolation = Interpolation::Parabolic;
+    let mut min_track = 0;
+    let mut silence = None;
+    let mut relative = None;
+    let mut hysteresis = 6.0;
+
+    for argument in &stage.arguments {
+        match (argument.key, argument.value) {
+            (Some("band"), value) => bands.push(value),
+            (Some("min"), _) => min = Some(number(argument)?),
+            (Some("max"), _) => max = Some(number(argument)?),
+            (Some("sampling"), _) => sampling = number(argument)?,
+            (Some("step"), _) => step = number(argument)?,
+            (Some("channels"), _) => channels = number(argument)?,
+            (Some("window"), "rect") => window = Window::Rectangular,
+            (Some("window"), "hann") => window = Window::Hann,
+            (Some("window"), "hamming") => window = Window::Hamming,
+            (Some("window"), "blackman-harris") => window = Window::BlackmanHarris,
+            (Some("window"), value) => return Err(format!("Unknown window {value}, expected rect, hann, hamming or blackman-harris")),
+            (Some("padding"), _) => padding = number(argument)?,
+            (Some("min-track"), _) => min_track = number(argument)?,
+            (Some("silence"), "off") => silence = None,
+            (Some("silence"), _) => silence = Some(number(argument)?),
+            (Some("relative"), _) => relative = Some(number(argument)?),
+            (Some("hysteresis"), _) => hysteresis = number(argument)?,
+            (Some("interpolation"), "none") => interpolation = Interpolation::None,
+            (Some("interpolation"), "parabolic") => interpolation = Interpolation::Parabolic,
+            (Some("interpolation"), "phase") => interpolation = Interpolation::Phase,
+            (Some("interpolation"), value) => return Err(format!("Unknown interpolation {value}, expected none, parabolic or phase")),
+            (Some(key), _) => return Err(format!("Unknown argument {key}")),
+            (None, value) => return Err(format!("Missing name of the argument {value}"))
+        }
+    }
+
+    if !(1..=MAX_PADDING).contains(&padding) {
+        return Err(format!("Invalid padding {padding}, must be in the range of 1..{MAX_PADDING}"));
+    }
+
+    if hysteresis < 0.0 {
+        return Err(format!("Invalid hysteresis {hysteresis}, must not be negative"));
+    }
+
+    let silence = (silence.is_some() || relative.is_some()).then(|| Silence {
+        threshold: silence.unwrap_or(f32::NEG_INFINITY),
+        relative,
+        hysteresis
+    });
+
+    let extractor = |min, max, sampling: u32, step, channels| {
+        if sampling < 2 {
+            return Err(String::from("The sampling window must have at least 2 samples"));
+        }
+        if step == 0 {
+            return Err(String::from("The step must be at least 1 sample"));
+        }
+        if channels == 0 {
+            return Err(String::from("There must be at least one channel"));
+        }
+
+        let extractor = FreqExtractor::new(min, max, sampling, step, channels)
+            .with_window(window)
+            .with_padding(padding)
+            .with_interpolation(interpolation)
+            .with_min_track(min_track);
+
+        Ok(match silence {
+            Some(silence) => extractor.with_silence(silence),
+            None => extractor
+        })
+    };
+
+    if bands.is_empty() {
+        return Ok(Box::new(extractor(min, max, sampling, step, channels)?));
+    }
+    if min.is_some() || max.is_some() {
+        return Err(String::from("The bounds are set by each band, min and max can't be used along with them"));
+    }
+
+    let mut bands = bands
+        .into_iter()
+        .map(|band| parse_band(band, sampling, step, channels))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    // The bands split the spectrum, so each peak belongs to a single one:
+    bands.sort_by_key(|band| band.0);
+    if let Some(pair) = bands.windows(2).find(|pair| pair[0].1 > pair[1].0) {
+        return Err(format!("The bands {}-{} and {}-{} overlap", pair[0].0, pair[0].1, pair[1].0, pair[1].1));
+    }
+
+    let bands = bands
+        .into_iter()
+        .map(|(min, max, sampling, step, channels)| extractor(Some(min), Some(max), sampling, step, channels))
+        .collect::<Result<Vec<FreqExtractor>, String>>()?;
+
+    Ok(Box::new(MultiBandExtractor::new(bands)))
+}
+
+///
+/// Frequency range of the voice, the step between frames and the voicing thresholds.
+///
+fn build_pitch_tracker(stage: &Stage) -> Result<PitchTracker, String> {
+    let argument = named(stage, &["min", "max", "step", "confidence", "silence"])?;
+    let min = number_or::<f32>(argument("min"), 60.0)?;
+    let max = number_or::<f32>(argument("max"), 1500.0)?;
+    let confidence = number_or::<f32>(argument("confidence"), 0.85)?;
+    let step = number_or(argument("step"), 256)?;
+
+    if !(min > 0.0 && min < max && max.is_finite()) {
+        return Err(format!("Invalid frequency range {min}..{max}"));
+    }
+    if !(0.0..=1.0).contains(&confidence) {
+        return Err(format!("Invalid confidence {confidence}, must be in the range of 0..1"));
+    }
+    if step == 0 {
+        return Err(String::from("The step must be at least 1 sample"));
+    }
+
+    Ok(PitchTracker::new(
+        min,
+        max,
+        step,
+        confidence,
+        number_or(argument("silence"), -50.0)?
+    ))
+}
+
+///
+/// Range of notes, the resolution in bins per octave and the same tracking as of the extractor.
+///
+fn build_constant_q(stage: &Stage) -> Result<ConstantQ, String> {
+    const MAX_BINS_PER_OCTAVE: u8 = 96;
+
+    let argument = named(stage, &["low", "high", "bins", "step", "channels", "min-track", "silence"])?;
+    let low = number_or(argument("low"), Note::C(2))?;
+    let high = number_or(argument("high"), Note::C(7))?;
+    let bins = number_or::<u8>(argument("bins"), 12)?;
+    let channels = number_or::<u8>(argument("channels"), 2)?;
+    let step = number_or(argument("step"), 256)?;
+
+    if low.position() >= high.position() {
+        return Err(format!("Invalid range of notes {low}..{high}"));
+    }
+    if bins == 0 || bins % 12 != 0 || bins > MAX_BINS_PER_OCTAVE {
+        return Err(format!("Invalid number of bins per octave {bins}, must be a multiple of 12 up to {MAX_BINS_PER_OCTAVE}"));
+    }
+    if channels == 0 {
+        return Err(String::from("There must be at least one channel"));
+    }
+    if step == 0 {
+        return Err(String::from("The step must be at least 1 sample"));
+    }
+
+    Ok(ConstantQ::new(low, high, bins, step, channels)
+        .with_min_track(number_or(argument("min-track"), 50)?)
+        .with_silence(number_or(argument("silence"), -60.0)?))
+}
+
+///
+/// Pitch class of the key: the letter with the optional sharp or flat, e.g. `F#` or `Bb`.
+///
+fn parse_tonic(text: &str) -> Result<u8, String> {
+    let (tonic, _) = Note::parse_pitch_class(text).map_err(|err| format!("Invalid key {text}: {err}"))?;
+    Ok(tonic)
+}
+
+///
+/// Values separated by '/', e.g. the degrees of the scale or the cents of the temperament.
+///
+fn parse_list<T: FromStr>(text: &str) -> Result<Vec<T>, String>
+    where T::Err: std::fmt::Display
+{
+    text.split('/')
+        .map(|value| value.trim().parse::<T>().map_err(|err| format!("Invalid value {value} of {text}: {err}")))
+        .collect()
+}
+
+fn parse_scale(text: &str) -> Result<Scale, String> {
+    text.parse::<Scale>().map_err(|err| format!("Invalid scale {text}: {err}"))
+}
+
+fn parse_temperament(text: &str) -> Result<Temperament, String> {
+    let temperament = match text {
+        "equal" => Temperament::Equal,
+        "just" => Temperament::Just,
+        "pythagorean" => Temperament::Pythagorean,
+        cents => {
+            let cents = parse_list::<f32>(cents).map_err(|err| format!("{err}, expected equal, just, pythagorean or 12 cents like 0/90/204/..."))?;
+            let Ok(cents) = <[f32; 12]>::try_from(cents.as_slice()) else {
+                return Err(format!("The temperament must have 12 degrees, got {}", cents.len()));
+            };
+            Temperament::Custom(cents)
+        }
+    };
+
+    Ok(temperament)
+}
+
+///
+/// The key is a tonic or auto, the scale defaults to the major one if only the tonic is given
+/// and to the chromatic one if neither is.
+///
+fn build_note_matcher(stage: &Stage) -> Result<NoteMatcher, String> {
+    let argument = named(stage, &["key", "scale", "a4", "temperament"])?;
+
+    let tonic = match argument("key").map(|argument| argument.value) {
+        None | Some("auto") => None,
+        Some(key) => Some(parse_tonic(key)?)
+    };
+
+    // The auto key without the scale picks between the major and minor ones:
+    let scale = match (argument("key").map(|argument| argument.value), argument("scale")) {
+        (_, Some(argument)) => Some(parse_scale(argument.value)?),
+        (Some("auto"), None) => None,
+        (Some(_), None) => Some(Scale::Major),
+        (None, None) => Some(Scale::Chromatic)
+    };
+    if scale.is_some_and(|scale| scale.degrees() == 0) {
+        return Err(String::from("The scale must have at least one degree"));
+    }
+
+    let a4 = number_or::<f32>(argument("a4"), 440.0)?;
+    if !a4.is_finite() || a4 <= 0.0 {
+        return Err(format!("Invalid pitch of A4 {a4}"));
+    }
+    let temperament = argument("temperament").map_or(Ok(Temperament::Equal), |argument| parse_temperament(argument.value))?;
+
+    let matcher = NoteMatcher::new().with_key(tonic, scale).with_tuning(a4, temperament);
+
+    Ok(matcher)
+}
+
+///
+/// Bound of the band to fit into: either the frequency or the note, e.g. 110 or A2.
+///
+fn parse_bound(text: &str) -> Result<f32, String> {
+    let freq = match text.parse::<f32>() {
+        Ok(freq) => freq,
+        Err(_) => text.parse::<Note>().map_err(|err| format!("Invalid bound {text}: {err}"))?.freq()
+    };
+
+    if !freq.is_finite() || freq <= 0.0 {
+        return Err(format!("Invalid bound {text}"));
+    }
+
+    Ok(freq)
+}
+
+///
+/// The interval is the positional argument and the octaves are added to it. Fitting is turned on
+/// by the positional fit (the comfortable band) or by fit=<low>-<high>, it's done per phrase by default.
+///
+fn build_transposer(stage: &Stage) -> Result<Transposer, String> {
+    let mut interval = Interval::UNISON;
+    let mut range = None;
+    let mut scope = None;
+    let mut gap = None;
+
+    for argument in &stage.arguments {
+        match (argument.key, argument.value) {
+            (None, "fit") => range = Some(COMFORTABLE_RANGE),
+            (None, value) => interval = interval + value.parse::<Interval>().map_err(|err| format!("Invalid interval {value}: {err}"))?,
+            (Some("octaves"), _) => interval = interval + Interval::octaves(number::<i16>(argument)?),
+            (Some("fit"), value) => {
+                let (low, high) = value.split_once('-').ok_or_else(|| format!("Invalid band {value}, expected <low>-<high>"))?;
+                range = Some((parse_bound(low)?, parse_bound(high)?));
+            },
+            (Some("scope"), "channel") => scope = Some(Scope::Channel),
+            (Some("scope"), "phrase") => scope = Some(Scope::Phrase),
+            (Some("scope"), value) => return Err(format!("Unknown scope {value}, expected channel or phrase")),
+            (Some("gap"), _) => gap = Some(number::<u64>(argument)?),
+            (Some(key), _) => return Err(format!("Unknown argument {key}"))
+        }
+    }
+
+    let mut transposer = Transposer::new(interval);
+    match range {
+        Some((low, high)) if low >= high => return Err(format!("Invalid band {low}..{high}")),
+        Some((low, high)) => transposer = transposer.with_fit(low, high, scope.unwrap_or(Scope::Phrase)),
+        None if scope.is_some() || gap.is_some() => return Err(String::from("The scope and the gap are applicable only with fit")),
+        None if interval == Interval::UNISON && stage.arguments.is_empty() => {
+            return Err(String::from("transpose requires an interval or fit, e.g. transpose(-12) or transpose(fit)"));
+        },
+        None => ()
+    }
+
+    if let Some(gap) = gap {
+        transposer = transposer.with_phrase_gap(gap);
+    }
+
+    Ok(transposer)
+}
+
+///
+/// Transposer from the arguments of the stage without the parentheses, e.g. `-12,fit=C2-C7`.
+///
+pub fn parse_transposer(arguments: &str) -> Result<Transposer, String> {
+    build_transposer(&parse_stage(&format!("transpose({arguments})"))?)
+}
+
+///
+/// Duration in msec of the steps between the frequency changes, they can't be shorter than the backend allows.
+///
+fn parse_step(argument: Option<&Argument>, default: f32, min_pulse_ns: Nsec) -> Result<f32, String> {
+    const NS_IN_MSEC: f32 = 1_000_000.0;
+
+    let step = number_or(argument, default)?;
+    if !step.is_finite() || step <= 0.0 {
+        return Err(format!("Invalid duration {step} ms"));
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let min_step = pitch_effects::min_step(min_pulse_ns) as f32 / NS_IN_MSEC;
+    if step < min_step {
+        return Err(format!("The step of {step} ms is too fast for the backend, the shortest one is {min_step} ms"));
+    }
+
+    Ok(step)
+}
+
+fn build_vibrato(stage: &Stage, min_pulse_ns: Nsec) -> Result<Vibrato, String> {
+    let argument = named(stage, &["rate", "depth", "delay", "step"])?;
+    let rate = number_or(argument("rate"), 5.5_f32)?;
+    let depth = number_or(argument("depth"), 30.0_f32)?;
+    let delay = number_or(argument("delay"), 150.0_f32)?;
+
+    if !rate.is_finite() || rate <= 0.0 {
+        return Err(format!("Invalid rate {rate}"));
+    }
+    if !(0.0..=1200.0).contains(&depth) {
+        return Err(format!("Invalid depth {depth}, must be in the range of 0..1200 cents"));
+    }
+    if !delay.is_finite() || delay < 0.0 {
+        return Err(format!("Invalid delay {delay}"));
+    }
+
+    Ok(Vibrato::new(rate, depth, delay).with_step(parse_step(argument("step"), DEFAULT_STEP_MS, min_pulse_ns)?))
+}
+
+fn build_portamento(stage: &Stage, min_pulse_ns: Nsec) -> Result<Portamento, String> {
+    let argument = named(stage, &["time", "step"])?;
+    let time = number_or(argument("time"), 60.0_f32)?;
+    if !time.is_finite() || time <= 0.0 {
+        return Err(format!("Invalid time {time}"));
+    }
+
+    Ok(Portamento::new(time).with_step(parse_step(argument("step"), DEFAULT_STEP_MS, min_pulse_ns)?))
+}
+
+///
+/// Positional pattern, the quality of the chord built on each note and the length of the tones.
+/// Without the chord the tones sounding together on all channels are merged.
+///
+fn build_arpeggio(stage: &Stage, min_pulse_ns: Nsec) -> Result<Arpeggio, String> {
+    let mut pattern = Pattern::Up;
+    let mut source = Source::Channels;
+    let mut note = None;
+
+    for argument in &stage.arguments {
+        match (argument.key, argument.value) {
+            (None, "up") => pattern = Pattern::Up,
+            (None, "down") => pattern = Pattern::Down,
+            (None, "updown") => pattern = Pattern::UpDown,
+            (None, value) => return Err(format!("Unknown pattern {value}, expected up, down or updown")),
+            (Some("chord"), value) => {
+                source = Source::Chord(value.parse::<ChordQuality>().map_err(|err| format!("Invalid chord {value}: {err}"))?);
+            },
+            (Some("note"), _) => note = Some(argument),
+            (Some(key), _) => return Err(format!("Unknown argument {key}"))
+        }
+    }
+
+    Ok(Arpeggio::new(source, pattern, parse_step(note, 20.0, min_pulse_ns)?))
+}
+
+fn build(stage: &Stage, min_pulse_ns: Nsec) -> Result<Box<dyn StreamFilter>, String> {
+    let filter: Box<dyn StreamFilter> = match stage.name {
+        "resample" => {
+            let sample_rate = number::<u32>(single(stage)?)?;
+            if sample_rate == 0 {
+                return Err(String::from("Invalid sample rate 0"));
+            }
+            Box::new(Resampler::new(sample_rate))
+        },
+        "highpass" => Box::new(HighPass::new(positive_freq(stage)?)),
+        "lowpass" => Box::new(LowPass::new(positive_freq(stage)?)),
+        "biquad" => Box::new(build_biquad(stage)?),
+        "butterworth" | "linkwitz-riley" => Box::new(build_cascade(stage)?),
+        "normalize" => Box::new(build_normalizer(stage)?),
+        "compress" => Box::new(build_compressor(stage)?),
+        "limit" => Box::new(build_limiter(stage)?),
+        "gate" => Box::new(build_gate(stage)?),
+        "bake" => Box::new(build_bakery(stage, min_pulse_ns)?),
+        "extract" => build_extractor(stage)?,
+        "pitch" => Box::new(build_pitch_tracker(stage)?),
+        "cqt" => Box::new(build_constant_q(stage)?),
+        "notes" => Box::new(build_note_matcher(stage)?),
+        "transpose" => Box::new(build_transposer(stage)?),
+        "vibrato" => Box::new(build_vibrato(stage, min_pulse_ns)?),
+        "glide" => Box::new(build_portamento(stage, min_pulse_ns)?),
+        "arpeggio" => Box::new(build_arpeggio(stage, min_pulse_ns)?),
+        "" => return Err(String::from("Empty stage")),
+        name => return Err(format!("Unknown stage {name}"))
+    };
+
+    Ok(filter)
+}
+
+///
+/// Parses the expression into the chain of filters which takes the data of the given kind.
+/// Pulses of the bakeries and steps of the pitch effects are not shorter than `min_pulse_ns` allows,
+/// it depends on the backend.
+///
+pub fn parse(expression: &str, input: Type, min_pulse_ns: Nsec) -> Result<Vec<Box<dyn StreamFilter>>, ParseError> {
+    let mut filters = Vec::<Box<dyn StreamFilter>>::new();
+    let mut data_type = input;
+    let mut offset = 0;
+
+    for text in expression.split('|') {
+        let span = trimmed_span(text, offset);
+        offset += text.len() + 1;
+
+        let error = |message: String| ParseError { expression: expression.to_string(), span: span.clone(), message };
+
+        let stage = parse_stage(expression[span.clone()].trim()).map_err(error)?;
+        let filter = build(&stage, min_pulse_ns).map_err(error)?;
+
+        if filter.input_type() != data_type {
+            return Err(error(format!(
+                "{} expects {:?} data, but the previous stage produces {:?} data",
+                stage.name,
+                filter.input_type(),
+                data_type
+            )));
+        }
+
+        data_type = filter.output_type();
+        filters.push(filter);
+    }
+
+    Ok(filters)
+}
+
+
+
+#[test]
+fn test() {
+    let filters = parse("highpass(300) | lowpass(4000)|extract(min=100,max=3000,channels=3,window=blackman-harris,padding=2,min-track=80,relative=-40) | notes", Type::Amplitude, 0).unwrap();
+    assert_eq!(filters.len(), 4);
+    assert_eq!(filters[3].output_type(), Type::Frequency);
+
+    let filters = parse("butterworth(highpass,freq=200) | linkwitz-riley(lowpass,freq=2000,order=8) | biquad(peak,freq=1000,gain=-3)", Type::Amplitude, 0).unwrap();
+    assert_eq!(filters.len(), 3);
+
+    let filters = parse("gate(threshold=-45) | normalize(rms,level=-12) | compress(ratio=8,attack=2) | limit | bake", Type::Amplitude, 0).unwrap();
+    assert_eq!(filters.len(), 5);
+
+    let filters = parse(" resample(22050) | bake(simple) ", Type::Amplitude, 0).unwrap();
+    assert_eq!(filters.len(), 2);
+    assert_eq!(filters[1].output_type(), Type::Position);
+    assert!(parse("bake(sigma-delta,order=1,rate=352800)", Type::Amplitude, 0).is_ok());
+    assert!(parse("bake(pwm,carrier=20000,levels=16)", Type::Amplitude, 10_000).is_ok());
+    assert!(parse("bake(pwm,carrier=60000)", Type::Amplitude, 10_000).is_err());
+    assert!(parse("bake(sigma-delta)", Type::Amplitude, 10_000).is_ok());
+    assert!(parse("bake(sigma-delta,rate=192000)", Type::Amplitude, 10_000).is_err());
+
+    // The error points at the offending stage:
+    let span = |expression| parse(expression, Type::Amplitude, 0).err().map(|err| err.span);
+    assert_eq!(span("lowpass(4000) | notes | bake"), Some(16..21));
+    assert_eq!(span("extract | bake"), Some(10..14));
+    assert_eq!(span("lowpass(4000) | | notes"), Some(16..16));
+    assert_eq!(span("lowpass(-1)"), Some(0..11));
+    assert_eq!(span("extract(min=100"), Some(0..15));
+    assert_eq!(span("extract(window=hann,padding=64)"), Some(0..31));
+    assert_eq!(span("extract(interpolation=cubic)"), Some(0..28));
+    assert_eq!(span("extract(silence=quiet)"), Some(0..22));
+    assert_eq!(span("bake(diff=5) | resample(8000)"), Some(15..29));
+    assert_eq!(span("biquad(peak,freq=1000)"), Some(0..22));
+    assert_eq!(span("normalize(loud)"), Some(0..15));
+    assert_eq!(span("compress(ratio=0.5)"), Some(0..19));
+    assert_eq!(span("gate(-50)"), Some(0..9));
+    assert_eq!(span("bake(sigma-delta,order=3)"), Some(0..25));
+    assert_eq!(span("lowpass(100) | linkwitz-riley(lowpass,freq=2000,order=3)"), Some(15..56));
+
+    let message = parse("lowpass(4000) | notes | bake", Type::Amplitude, 0).err().unwrap().to_string();
+    assert!(message.ends_with("\n                    ^^^^^"));
+
+    assert!(parse("extract(band=20-250/8192/256/1,band=250-4000/2048,channels=2,step=64) | notes", Type::Amplitude, 0).is_ok());
+    assert_eq!(span("extract(band=20-300,band=250-4000)"), Some(0..34));
+    assert_eq!(span("extract(band=20-300,min=50)"), Some(0..27));
+    assert_eq!(span("extract(band=300/4096)"), Some(0..22));
+    assert_eq!(span("extract(band=20-300/1)"), Some(0..22));
+    assert_eq!(span("extract(step=0)"), Some(0..15));
+    assert_eq!(span("extract(channels=0)"), Some(0..19));
+    assert_eq!(span("extract(band=20-300/4096/0)"), Some(0..27));
+    assert_eq!(span("extract(band=20-300/4096/256/0)"), Some(0..31));
+
+    assert!(parse("cqt(low=A1,high=C6#,bins=36,channels=3) | notes", Type::Amplitude, 0).is_ok());
+    assert_eq!(span("cqt(low=C5,high=C4)"), Some(0..19));
+    assert_eq!(span("cqt(bins=16)"), Some(0..12));
+    assert_eq!(span("cqt(low=H2)"), Some(0..11));
+    assert_eq!(span("cqt(step=0)"), Some(0..11));
+    assert!(parse("cqt(high=C30)", Type::Amplitude, 0).is_ok());
+
+    assert!(parse("notes", Type::Frequency, 0).is_ok());
+    assert!(parse("notes(key=F#,scale=dorian,a4=442)", Type::Frequency, 0).is_ok());
+    assert!(parse("notes(key=auto,temperament=just)", Type::Frequency, 0).is_ok());
+    assert!(parse("notes(scale=0/2/4/7/9,temperament=0/90/204/294/408/498/612/702/792/906/996/1110)", Type::Frequency, 0).is_ok());
+    assert!(parse("notes(key=H)", Type::Frequency, 0).is_err());
+    assert!(parse("notes(scale=0/12)", Type::Frequency, 0).is_err());
+    assert!(parse("notes(temperament=0/100)", Type::Frequency, 0).is_err());
+    assert!(parse("notes(a4=0)", Type::Frequency, 0).is_err());
+    assert!(parse("lowpass(2000) | pitch(min=80,max=800) | notes", Type::Amplitude, 0).is_ok());
+    assert_eq!(span("pitch(min=500,max=100)"), Some(0..22));
+    assert_eq!(span("pitch(step=0)"), Some(0..13));
+
+    assert!(parse("transpose(-12) | transpose(M3,octaves=1) | transpose(fit)", Type::Frequency, 0).is_ok());
+    assert!(parse("transpose(fit=C2-4000,scope=channel) | transpose(fit=100-A7,gap=500)", Type::Frequency, 0).is_ok());
+    assert!(parse("transpose(fit=C30-C40)", Type::Frequency, 0).is_ok());
+    assert!(parse("transpose", Type::Frequency, 0).is_err());
+    assert!(parse("transpose(X5)", Type::Frequency, 0).is_err());
+    assert!(parse("transpose(fit=4000-100)", Type::Frequency, 0).is_err());
+    assert!(parse("transpose(-12,scope=channel)", Type::Frequency, 0).is_err());
+    assert!(parse_transposer("P5,fit").is_ok());
+
+    let filters = parse("glide(time=80) | vibrato(rate=6,depth=40,delay=200) | arpeggio(updown,note=25)", Type::Frequency, 10_000).unwrap();
+    assert_eq!(filters[2].output_type(), Type::Frequency);
+    assert!(parse("arpeggio(down,chord=m7) | vibrato(step=0.02)", Type::Frequency, 10_000).is_ok());
+    assert!(parse("vibrato(step=0.01)", Type::Frequency, 10_000).is_err());
+    assert!(parse("vibrato(depth=-5)", Type::Frequency, 0).is_err());
+    assert!(parse("arpeggio(chord=m13)", Type::Frequency, 0).is_err());
+    assert!(parse("glide", Type::Amplitude, 0).is_err());
+}
@@ -0,0 +1,515 @@
+#![allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+
+//!
+//! Pitch effects render the piecewise-constant notes into finely stepped records:
+//!     Vibrato - the pitch of the note swings periodically around it after a delay.
+//!     Portamento - the pitch glides from the previous note to the next one.
+//!     Arpeggio - the tones of the chord are cycled rapidly on a single channel.
+//!
+//! Each step costs a new divisor of the timer, so the steps are limited by the backend,
+//! see `min_step`. A step is never shorter than a period of its tone: the timer reloads
+//! the new divisor only when the current period ends, the shorter steps would be lost.
+//!
+
+use std::{collections::VecDeque, f32::consts::TAU};
+
+use note::ChordQuality;
+
+use super::filter::{Type, Data, Filter, StreamFilter, FreqRecord, FreqData, Nsec};
+
+pub const DEFAULT_STEP_MS: f32 = 5.0;
+
+const NANOS_IN_MSEC: f32 = 1_000_000.0;
+const NANOS_IN_SEC: f32 = 1_000_000_000.0;
+const CENTS_IN_OCTAVE: f32 = 1200.0;
+const DIVISOR_WRITES: Nsec = 2; // The low and the high bytes of the divisor are written into the port 0x42
+
+///
+/// Shortest step between the frequency changes: each change writes the divisor into the port,
+/// and each write takes about as long as the shortest pulse of the backend.
+///
+#[must_use]
+pub fn min_step(min_pulse_ns: Nsec) -> Nsec {
+    DIVISOR_WRITES * min_pulse_ns
+}
+
+fn nsec(msec: f32) -> Nsec {
+    (msec.max(0.0) * NANOS_IN_MSEC).round() as Nsec
+}
+
+///
+/// Length of the step starting the tone: not shorter than its period and not longer than the remaining time.
+///
+fn step_of(freq: f32, step: Nsec, remaining: Nsec) -> Nsec {
+    let period = (NANOS_IN_SEC / freq).ceil() as Nsec;
+    step.max(period).min(remaining).max(1)
+}
+
+fn cents(from: f32, to: f32) -> f32 {
+    CENTS_IN_OCTAVE * (to / from).log2()
+}
+
+///
+/// Applies the stateful rendering to each channel, the states are kept between the chunks.
+///
+fn render_channels<State: Default>(
+    states: &mut Vec<State>,
+    channels: FreqData<f32>,
+    mut render: impl FnMut(&mut State, FreqRecord<f32>, &mut Vec<FreqRecord<f32>>)) -> FreqData<f32>
+{
+    states.resize_with(states.len().max(channels.len()), State::default);
+
+    channels
+        .into_iter()
+        .zip(states.iter_mut())
+        .map(|(records, state)| {
+            let mut output = Vec::with_capacity(records.len());
+            for record in records {
+                render(state, record, &mut output);
+            }
+            output
+        })
+        .collect()
+}
+
+///
+/// Periodic swing of the pitch. The delay restarts after a pause or a jump by a semitone and more,
+/// so the smaller changes (e.g. the steps of the portamento) continue the swing of the same note.
+///
+pub struct Vibrato {
+    rate: f32,  // Swings per second
+    depth: f32, // Amplitude of the swing in cents
+    delay: Nsec,
+    step: Nsec,
+    notes: Vec<Option<(f32, Nsec)>> // The last frequency of each channel and the time since its note started
+}
+
+impl Vibrato {
+    #[must_use]
+    pub fn new(rate: f32, depth: f32, delay_ms: f32) -> Self {
+        Self { rate, depth, delay: nsec(delay_ms), step: nsec(DEFAULT_STEP_MS), notes: Vec::new() }
+    }
+
+    #[must_use]
+    pub fn with_step(mut self, step_ms: f32) -> Self {
+        self.step = nsec(step_ms);
+        self
+    }
+
+    fn render(&self, note: &mut Option<(f32, Nsec)>, record: FreqRecord<f32>, output: &mut Vec<FreqRecord<f32>>) {
+        if record.freq <= 0.0 {
+            *note = None;
+            output.push(record);
+            return;
+        }
+
+        let mut elapsed = match *note {
+            Some((freq, elapsed)) if cents(freq, record.freq).abs() < 100.0 => elapsed,
+            _ => 0
+        };
+        *note = Some((record.freq, elapsed + record.duration));
+
+        let end = elapsed + record.duration;
+        if elapsed < self.delay {
+            let steady = (self.delay - elapsed).min(record.duration);
+            output.push(FreqRecord { freq: record.freq, duration: steady });
+            elapsed += steady;
+        }
+
+        while elapsed < end {
+            let duration = step_of(record.freq, self.step, end - elapsed);
+            let time = (elapsed + duration / 2 - self.delay) as f32 / NANOS_IN_SEC;
+            let swing = self.depth * (TAU * self.rate * time).sin();
+            output.push(FreqRecord { freq: record.freq * 2_f32.powf(swing / CENTS_IN_OCTAVE), duration });
+            elapsed += duration;
+        }
+    }
+}
+
+impl Filter for Vibrato {
+    fn input_type(&self) -> Type {
+        Type::Frequency
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        let Data::Frequency(channels) = data else {
+            return None;
+        };
+
+        Some(Data::Frequency(render_channels(&mut Vec::new(), channels, |note, record, output| self.render(note, record, output))))
+    }
+}
+
+impl StreamFilter for Vibrato {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Frequency(channels) = chunk else {
+            return None;
+        };
+
+        let mut notes = std::mem::take(&mut self.notes);
+        let channels = render_channels(&mut notes, channels, |note, record, output| self.render(note, record, output));
+        self.notes = notes;
+        Some(Data::Frequency(channels))
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        None
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct Glide {
+    from: f32,
+    to: f32,
+    elapsed: Nsec
+}
+
+impl Glide {
+    fn freq_at(&self, elapsed: Nsec, time: Nsec) -> f32 {
+        if elapsed >= time {
+            return self.to;
+        }
+        self.from * (self.to / self.from).powf(elapsed as f32 / time as f32)
+    }
+}
+
+///
+/// Glide between the adjoining notes: the pitch moves from the previous note to the next one
+/// evenly in cents. The notes after a pause start at once.
+///
+pub struct Portamento {
+    time: Nsec, // Duration of the glide, it's cut by the shorter notes
+    step: Nsec,
+    glides: Vec<Option<Glide>> // The current glide of each channel, none after a pause
+}
+
+impl Portamento {
+    #[must_use]
+    pub fn new(time_ms: f32) -> Self {
+        Self { time: nsec(time_ms), step: nsec(DEFAULT_STEP_MS), glides: Vec::new() }
+    }
+
+    #[must_use]
+    pub fn with_step(mut self, step_ms: f32) -> Self {
+        self.step = nsec(step_ms);
+        self
+    }
+
+    fn render(&self, glide: &mut Option<Glide>, record: FreqRecord<f32>, output: &mut Vec<FreqRecord<f32>>) {
+        if record.freq <= 0.0 {
+            *glide = None;
+            output.push(record);
+            return;
+        }
+
+        let mut current = match *glide {
+            Some(glide) if (glide.to - record.freq).abs() < f32::EPSILON => glide,
+            Some(glide) => Glide { from: glide.freq_at(glide.elapsed, self.time), to: record.freq, elapsed: 0 },
+            None => Glide { from: record.freq, to: record.freq, elapsed: self.time }
+        };
+
+        let end = current.elapsed + record.duration;
+        while current.elapsed < end.min(self.time) {
+            let freq = current.freq_at(current.elapsed, self.time);
+            let duration = step_of(freq, self.step, end.min(self.time) - current.elapsed);
+            output.push(FreqRecord { freq: current.freq_at(current.elapsed + duration / 2, self.time), duration });
+            current.elapsed += duration;
+        }
+
+        if current.elapsed < end {
+            output.push(FreqRecord { freq: record.freq, duration: end - current.elapsed });
+            current.elapsed = end;
+        }
+
+        *glide = Some(current);
+    }
+}
+
+impl Filter for Portamento {
+    fn input_type(&self) -> Type {
+        Type::Frequency
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        let Data::Frequency(channels) = data else {
+            return None;
+        };
+
+        Some(Data::Frequency(render_channels(&mut Vec::new(), channels, |glide, record, output| self.render(glide, record, output))))
+    }
+}
+
+impl StreamFilter for Portamento {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Frequency(channels) = chunk else {
+            return None;
+        };
+
+        let mut glides = std::mem::take(&mut self.glides);
+        let channels = render_channels(&mut glides, channels, |glide, record, output| self.render(glide, record, output));
+        self.glides = glides;
+        Some(Data::Frequency(channels))
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    Up,
+    Down,
+    UpDown // The highest and the lowest tones aren't repeated at the turns
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Channels,             // The tones sounding together on all channels are merged into one channel
+    Chord(ChordQuality)   // Each note of each channel becomes the root of the chord
+}
+
+#[derive(Default)]
+struct Cycle {
+    chord: Vec<f32>, // Tones of the current chord from the lowest one
+    elapsed: Nsec    // Since the chord started, so each new chord starts from the beginning of the pattern
+}
+
+///
+/// Rapid cycling through the tones of the chord, one tone per note length.
+///
+pub struct Arpeggio {
+    source: Source,
+    pattern: Pattern,
+    note: Nsec,                             // Length of each tone
+    cycles: Vec<Cycle>,                     // Single one for the merged channels
+    pending: Vec<VecDeque<FreqRecord<f32>>> // Records of the channels waiting for the others to be merged
+}
+
+impl Arpeggio {
+    #[must_use]
+    pub fn new(source: Source, pattern: Pattern, note_ms: f32) -> Self {
+        Self { source, pattern, note: nsec(note_ms).max(1), cycles: Vec::new(), pending: Vec::new() }
+    }
+
+    fn tone(&self, chord: &[f32], index: usize) -> f32 {
+        let count = chord.len();
+        let index = match self.pattern {
+            Pattern::Up => index % count,
+            Pattern::Down => count - 1 - index % count,
+            Pattern::UpDown if count < 3 => index % count,
+            Pattern::UpDown => {
+                let index = index % (2 * count - 2);
+                if index < count { index } else { 2 * count - 2 - index }
+            }
+        };
+        chord[index]
+    }
+
+    ///
+    /// Plays the chord for the duration continuing its cycle, a new chord restarts the cycle.
+    ///
+    fn render(&self, cycle: &mut Cycle, mut chord: Vec<f32>, duration: Nsec, output: &mut Vec<FreqRecord<f32>>) {
+        chord.sort_by(f32::total_cmp);
+        chord.dedup_by(|next, prev| (*next - *prev).abs() < 0.01);
+
+        if chord != cycle.chord {
+            *cycle = Cycle { chord, elapsed: 0 };
+        }
+
+        if cycle.chord.len() < 2 {
+            let freq = cycle.chord.first().copied().unwrap_or_default();
+            output.push(FreqRecord { freq, duration });
+            cycle.elapsed += duration;
+            return;
+        }
+
+        let end = cycle.elapsed + duration;
+        while cycle.elapsed < end {
+            let index = (cycle.elapsed / self.note) as usize;
+            let freq = self.tone(&cycle.chord, index);
+            let next_tone = (cycle.elapsed / self.note + 1) * self.note;
+            let duration = step_of(freq, next_tone - cycle.elapsed, end - cycle.elapsed);
+            output.push(FreqRecord { freq, duration });
+            cycle.elapsed += duration;
+        }
+    }
+
+    fn render_chords(&self, cycles: &mut Vec<Cycle>, channels: FreqData<f32>, quality: ChordQuality) -> FreqData<f32> {
+        let ratios = quality.intervals().iter().map(|interval| interval.ratio()).collect::<Vec<f32>>();
+        render_channels(cycles, channels, |cycle, record, output| {
+            let chord = if record.freq > 0.0 { ratios.iter().map(|ratio| record.freq * ratio).collect() } else { Vec::new() };
+            self.render(cycle, chord, record.duration, output);
+        })
+    }
+
+    ///
+    /// Merges the channels while all of them have records, or until the longest one ends if it's the final chunk:
+    /// the finished channels are silent.
+    ///
+    fn merge(&self, cycle: &mut Cycle, pending: &mut [VecDeque<FreqRecord<f32>>], is_final: bool) -> Vec<FreqRecord<f32>> {
+        let mut output = Vec::new();
+
+        loop {
+            let is_ready = if is_final {
+                pending.iter().any(|records| !records.is_empty())
+            } else {
+                !pending.is_empty() && pending.iter().all(|records| !records.is_empty())
+            };
+            if !is_ready {
+                return output;
+            }
+
+            let duration = pending.iter().filter_map(|records| records.front()).map(|record| record.duration).min().unwrap_or_default();
+            let chord = pending
+                .iter()
+                .filter_map(|records| records.front())
+                .filter(|record| record.freq > 0.0)
+                .map(|record| record.freq)
+                .collect();
+            self.render(cycle, chord, duration, &mut output);
+
+            for records in pending.iter_mut() {
+                if let Some(record) = records.front_mut() {
+                    record.duration -= duration;
+                    if record.duration == 0 {
+                        records.pop_front();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Filter for Arpeggio {
+    fn input_type(&self) -> Type {
+        Type::Frequency
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        let Data::Frequency(channels) = data else {
+            return None;
+        };
+
+        Some(Data::Frequency(match self.source {
+            Source::Chord(quality) => self.render_chords(&mut Vec::new(), channels, quality),
+            Source::Channels => {
+                let mut pending = channels.into_iter().map(VecDeque::from).collect::<Vec<_>>();
+                vec![self.merge(&mut Cycle::default(), &mut pending, true)]
+            }
+        }))
+    }
+}
+
+impl StreamFilter for Arpeggio {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Frequency(channels) = chunk else {
+            return None;
+        };
+
+        let mut cycles = std::mem::take(&mut self.cycles);
+        let output = match self.source {
+            Source::Chord(quality) => self.render_chords(&mut cycles, channels, quality),
+            Source::Channels => {
+                let mut pending = std::mem::take(&mut self.pending);
+                pending.resize_with(pending.len().max(channels.len()), VecDeque::new);
+                for (records, channel) in pending.iter_mut().zip(channels) {
+                    records.extend(channel);
+                }
+
+                cycles.resize_with(1, Cycle::default);
+                let merged = self.merge(&mut cycles[0], &mut pending, false);
+                self.pending = pending;
+                vec![merged]
+            }
+        };
+
+        self.cycles = cycles;
+        Some(Data::Frequency(output))
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        if self.source != Source::Channels || self.pending.iter().all(VecDeque::is_empty) {
+            return None;
+        }
+
+        let mut pending = std::mem::take(&mut self.pending);
+        let mut cycle = self.cycles.pop().unwrap_or_default();
+        Some(Data::Frequency(vec![self.merge(&mut cycle, &mut pending, true)]))
+    }
+}
+
+
+
+#[test]
+fn test() {
+    const MS: Nsec = 1_000_000;
+
+    let note = |freq: f32, msec: Nsec| FreqRecord { freq, duration: msec * MS };
+    let run = |filter: &dyn Filter, channels: FreqData<f32>| -> FreqData<f32> {
+        let Some(Data::Frequency(channels)) = filter.filter(Data::Frequency(channels)) else {
+            panic!("Unexpected data type");
+        };
+        channels
+    };
+    let total = |records: &[FreqRecord<f32>]| records.iter().map(|record| record.duration).sum::<Nsec>();
+
+    // The vibrato starts after the delay and swings within the depth, the durations are kept:
+    let vibrato = Vibrato::new(5.0, 50.0, 100.0).with_step(10.0);
+    let channels = run(&vibrato, vec![vec![note(440.0, 500), note(0.0, 100)]]);
+    assert_eq!(total(&channels[0]), 600 * MS);
+    assert_eq!(channels[0][0].duration, 100 * MS);
+    assert!((channels[0][0].freq - 440.0).abs() < f32::EPSILON);
+    assert_eq!(channels[0].len(), 1 + 40 + 1);
+    let swings = channels[0][1..41].iter().map(|record| cents(440.0, record.freq)).collect::<Vec<f32>>();
+    assert!(swings.iter().all(|swing| swing.abs() <= 50.0));
+    assert!(swings.iter().any(|swing| *swing > 45.0) && swings.iter().any(|swing| *swing < -45.0));
+
+    // The steps are never shorter than the period of the tone:
+    let channels = run(&Vibrato::new(5.0, 50.0, 0.0).with_step(1.0), vec![vec![note(50.0, 100)]]);
+    assert!(channels[0].iter().all(|record| record.duration >= 20 * MS));
+
+    // The glide moves evenly in cents between the adjoining notes only:
+    let portamento = Portamento::new(40.0).with_step(10.0);
+    let channels = run(&portamento, vec![vec![note(440.0, 100), note(880.0, 100), note(0.0, 50), note(220.0, 100)]]);
+    let freqs = channels[0].iter().map(|record| record.freq.round()).collect::<Vec<f32>>();
+    assert_eq!(freqs, vec![440.0, 480.0, 571.0, 679.0, 807.0, 880.0, 0.0, 220.0]);
+    assert_eq!(total(&channels[0]), 350 * MS);
+
+    // The glide continues when the target note is split between the chunks:
+    let mut streamed = Portamento::new(40.0).with_step(10.0);
+    let mut output = Vec::new();
+    for chunk in [vec![note(440.0, 100), note(880.0, 20)], vec![note(880.0, 80), note(0.0, 50), note(220.0, 100)]] {
+        let Some(Data::Frequency(channels)) = streamed.feed(Data::Frequency(vec![chunk])) else {
+            panic!("Unexpected data type");
+        };
+        output.extend(channels.into_iter().flatten().map(|record| record.freq.round()));
+    }
+    output.dedup();
+    assert_eq!(output, freqs);
+
+    // The chord of the channels is cycled up and down without the repeated turns:
+    let arpeggio = Arpeggio::new(Source::Channels, Pattern::UpDown, 20.0);
+    let channels = run(&arpeggio, vec![vec![note(440.0, 200)], vec![note(330.0, 100), note(0.0, 100)], vec![note(262.0, 200)]]);
+    assert_eq!(channels.len(), 1);
+    let freqs = channels[0].iter().map(|record| record.freq).collect::<Vec<f32>>();
+    assert_eq!(freqs, vec![262.0, 330.0, 440.0, 330.0, 262.0, 262.0, 440.0, 262.0, 440.0, 262.0]);
+    assert_eq!(total(&channels[0]), 200 * MS);
+
+    // The merged channels wait for each other while streaming:
+    let mut streamed = Arpeggio::new(Source::Channels, Pattern::Up, 50.0);
+    let Some(Data::Frequency(channels)) = streamed.feed(Data::Frequency(vec![vec![note(440.0, 100)], vec![note(220.0, 50)]])) else {
+        panic!("Unexpected data type");
+    };
+    assert_eq!(channels[0].iter().map(|record| record.freq).collect::<Vec<f32>>(), vec![220.0]);
+    let Some(Data::Frequency(channels)) = streamed.finish() else {
+        panic!("Unexpected data type");
+    };
+    assert_eq!(channels[0].iter().map(|record| record.freq).collect::<Vec<f32>>(), vec![440.0]);
+
+    // Each note becomes the chord of the quality:
+    let arpeggio = Arpeggio::new(Source::Chord(ChordQuality::Minor), Pattern::Down, 10.0);
+    let channels = run(&arpeggio, vec![vec![note(220.0, 40)]]);
+    let freqs = channels[0].iter().map(|record| record.freq.round()).collect::<Vec<f32>>();
+    assert_eq!(freqs, vec![330.0, 262.0, 220.0, 330.0]);
+}
@@ -0,0 +1,300 @@
+use std::sync::Arc;
+
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use super::filter::{Type, Data, Filter, StreamFilter, FreqRecord, FreqRecordFlt, FreqData};
+
+///
+/// Monophonic pitch tracker based on YIN (de Cheveigné and Kawahara, 2002).
+/// The fundamental is the shortest lag at which the frame resembles itself,
+/// so strong upper harmonics don't pull the pitch up as with the spectral peaks.
+/// Unvoiced and silent frames become pauses.
+///
+pub struct PitchTracker {
+    min_freq_hz: f32,
+    max_freq_hz: f32,
+    step_by: u32,      // In samples
+    confidence: f32,   // Minimal periodicity of the voiced frame in the range of [0, 1]
+    silence_db: f32,   // Frames quieter than this RMS level in dBFS are unvoiced
+    stream: Option<Stream>
+}
+
+///
+/// Lags in samples and the FFT of the size covering the frame.
+///
+struct Detector {
+    sample_rate: u32,
+    min_lag: usize,
+    max_lag: usize, // Also the width of the integration window, so the frame is twice as long
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>
+}
+
+///
+/// State of the incremental tracking: samples that haven't been covered
+/// by the frame yet and the last record which may be prolonged.
+///
+struct Stream {
+    detector: Detector,
+    samples: Vec<f32>,
+    skip: usize, // Samples of the next chunks to skip if the step is longer than the frame
+    last_record: Option<FreqRecordFlt>
+}
+
+const NANOS_IN_SEC: u64 = 1_000_000_000;
+const SAME_PITCH_CENTS: f32 = 10.0; // Consecutive frames closer than this are merged into a single record
+
+impl Detector {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    fn new(tracker: &PitchTracker, sample_rate: u32) -> Self {
+        let min_lag = ((sample_rate as f32 / tracker.max_freq_hz).floor() as usize).max(2);
+        let max_lag = ((sample_rate as f32 / tracker.min_freq_hz).ceil() as usize).max(min_lag + 2);
+
+        let mut planner = FftPlanner::new();
+        let size = (2 * max_lag).next_power_of_two();
+
+        Self {
+            sample_rate,
+            min_lag,
+            max_lag,
+            forward: planner.plan_fft_forward(size),
+            inverse: planner.plan_fft_inverse(size)
+        }
+    }
+
+    fn frame_size(&self) -> usize {
+        2 * self.max_lag
+    }
+
+    ///
+    /// Cross term of the difference function: `sum(x[j] * x[j + lag])` over the integration window.
+    ///
+    #[allow(clippy::cast_precision_loss)]
+    fn correlation(&self, frame: &[f32]) -> Vec<f32> {
+        let size = self.forward.len();
+        let spectrum = |samples: &[f32]| {
+            let mut spectrum = samples.iter().map(|sample| Complex::new(*sample, 0_f32)).collect::<Vec<Complex<f32>>>();
+            spectrum.resize(size, Complex::default());
+            self.forward.process(&mut spectrum);
+            spectrum
+        };
+
+        let window = spectrum(&frame[..self.max_lag]);
+        let mut product = spectrum(frame)
+            .into_iter()
+            .zip(window)
+            .map(|(frame, window)| frame * window.conj())
+            .collect::<Vec<Complex<f32>>>();
+
+        self.inverse.process(&mut product);
+        product[..=self.max_lag].iter().map(|value| value.re / size as f32).collect()
+    }
+
+    ///
+    /// The fundamental frequency of the frame or None if it's unvoiced.
+    ///
+    #[allow(clippy::cast_precision_loss)]
+    fn detect(&self, frame: &[f32], confidence: f32, silence_db: f32) -> Option<f32> {
+        let width = self.max_lag;
+        let power = frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len() as f32;
+        if 10.0 * power.log10() < silence_db {
+            return None;
+        }
+
+        // Energies of all windows at once:
+        let mut energies = Vec::with_capacity(frame.len() + 1);
+        energies.push(0_f32);
+        for sample in frame {
+            energies.push(energies[energies.len() - 1] + sample * sample);
+        }
+        let energy = |lag: usize| energies[lag + width] - energies[lag];
+
+        // Difference function normalized by its cumulative mean, it doesn't dip at the zero lag:
+        let correlation = self.correlation(frame);
+        let mut normalized = vec![1_f32; self.max_lag + 1];
+        let mut sum = 0_f32;
+        for lag in 1..=self.max_lag {
+            let difference = (energy(0) + energy(lag) - 2.0 * correlation[lag]).max(0.0);
+            sum += difference;
+            normalized[lag] = if sum > 0.0 { difference * lag as f32 / sum } else { 1.0 };
+        }
+
+        // The first dip below the threshold followed down to its bottom:
+        let threshold = 1.0 - confidence;
+        let mut lag = (self.min_lag..self.max_lag).find(|lag| normalized[*lag] < threshold)?;
+        while lag + 1 < self.max_lag && normalized[lag + 1] < normalized[lag] {
+            lag += 1;
+        }
+
+        let (left, centre, right) = (normalized[lag - 1], normalized[lag], normalized[lag + 1]);
+        let offset = 0.5 * (left - right) / (left - 2.0 * centre + right);
+        let offset = if offset.is_finite() { offset.clamp(-1.0, 1.0) } else { 0.0 };
+
+        Some(self.sample_rate as f32 / (lag as f32 + offset))
+    }
+}
+
+impl PitchTracker {
+    #[must_use]
+    pub fn new(min_freq_hz: f32, max_freq_hz: f32, step_by: u32, confidence: f32, silence_db: f32) -> Self {
+        Self { min_freq_hz, max_freq_hz, step_by, confidence, silence_db, stream: None }
+    }
+
+    fn step_duration(&self, sample_rate: u32) -> u64 {
+        (NANOS_IN_SEC * u64::from(self.step_by)) / u64::from(sample_rate)
+    }
+
+    ///
+    /// Prolongs the last record if the pitch is the same, otherwise
+    /// replaces it with the new one and returns the replaced record.
+    ///
+    fn prolong_or_replace(last_record: &mut Option<FreqRecordFlt>, freq: f32, duration: u64) -> Option<FreqRecordFlt> {
+        if let Some(last) = last_record {
+            let is_same = match (freq > 0.0, last.freq > 0.0) {
+                (true, true) => (1200.0 * (freq / last.freq).log2()).abs() < SAME_PITCH_CENTS,
+                (is_voiced, was_voiced) => is_voiced == was_voiced // Pauses are merged as well
+            };
+
+            if is_same {
+                last.duration += duration;
+                return None;
+            }
+        }
+
+        last_record.replace(FreqRecord { freq, duration })
+    }
+
+    ///
+    /// Tracks the pitch of all complete frames of the samples,
+    /// returns the number of samples the frames have been shifted by.
+    ///
+    fn track(&self, detector: &Detector, samples: &[f32], last_record: &mut Option<FreqRecordFlt>, records: &mut Vec<FreqRecordFlt>) -> usize {
+        let frame_size = detector.frame_size();
+        let step_by = (self.step_by as usize).max(1);
+        let duration = self.step_duration(detector.sample_rate);
+
+        let mut offset = 0;
+        while offset + frame_size <= samples.len() {
+            let freq = detector
+                .detect(&samples[offset..offset + frame_size], self.confidence, self.silence_db)
+                .unwrap_or(0.0);
+
+            records.extend(Self::prolong_or_replace(last_record, freq, duration));
+            offset += step_by;
+        }
+
+        offset
+    }
+}
+
+impl Filter for PitchTracker {
+    fn input_type(&self) -> Type {
+        Type::Amplitude
+    }
+
+    fn output_type(&self) -> Type {
+        Type::Frequency
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = data else {
+            return None;
+        };
+
+        let detector = Detector::new(self, wave.sample_rate);
+        let mut last_record = None;
+        let mut records = Vec::new();
+
+        self.track(&detector, &wave.samples, &mut last_record, &mut records);
+        records.extend(last_record);
+
+        Some(Data::Frequency(FreqData::from([records])))
+    }
+}
+
+impl StreamFilter for PitchTracker {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = chunk else {
+            return None;
+        };
+
+        let mut stream = self.stream.take().unwrap_or_else(|| Stream {
+            detector: Detector::new(self, wave.sample_rate),
+            samples: Vec::new(),
+            skip: 0,
+            last_record: None
+        });
+
+        let skipped = stream.skip.min(wave.samples.len());
+        stream.skip -= skipped;
+        stream.samples.extend_from_slice(&wave.samples[skipped..]);
+
+        let mut records = Vec::new();
+        let offset = self.track(&stream.detector, &stream.samples, &mut stream.last_record, &mut records);
+
+        let drained = offset.min(stream.samples.len());
+        stream.samples.drain(..drained);
+        stream.skip += offset - drained;
+        self.stream = Some(stream);
+
+        Some(Data::Frequency(FreqData::from([records])))
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        let stream = self.stream.take()?;
+        Some(Data::Frequency(FreqData::from([stream.last_record.into_iter().collect()])))
+    }
+}
+
+
+
+#[test]
+#[allow(clippy::cast_precision_loss)]
+fn test() {
+    use super::filter::WaveData;
+    use super::freq_extractor::FreqExtractor;
+
+    const SAMPLE_RATE: u32 = 22050;
+    const FREQ: f32 = 220.0;
+
+    // The 2nd harmonic is twice as loud as the fundamental, then the silence follows:
+    let samples = (0..SAMPLE_RATE)
+        .map(|index| {
+            let phase = std::f32::consts::TAU * FREQ * index as f32 / SAMPLE_RATE as f32;
+            0.2 * phase.sin() + 0.4 * (2.0 * phase).sin() + 0.2 * (3.0 * phase).sin()
+        })
+        .chain(std::iter::repeat_n(0.0, SAMPLE_RATE as usize / 2))
+        .collect::<Vec<f32>>();
+
+    let spectral = FreqExtractor::new(Some(100), Some(2000), 2048, 512, 1)
+        .filter(Data::Amplitude(WaveData { samples: samples.clone(), sample_rate: SAMPLE_RATE }));
+    let Some(Data::Frequency(channels)) = spectral else {
+        panic!("Unexpected data type");
+    };
+    assert!((channels[0][0].freq - 2.0 * FREQ).abs() < 2.0);
+
+    let mut tracker = PitchTracker::new(60.0, 1000.0, 256, 0.85, -50.0);
+    let mut records = Vec::new();
+    for chunk in samples.chunks(1000) {
+        if let Some(Data::Frequency(channels)) = tracker.feed(Data::Amplitude(WaveData { samples: chunk.to_vec(), sample_rate: SAMPLE_RATE })) {
+            records.extend(channels.into_iter().flatten());
+        }
+    }
+    if let Some(Data::Frequency(channels)) = tracker.finish() {
+        records.extend(channels.into_iter().flatten());
+    }
+
+    let [tone, pause] = records.as_slice() else {
+        panic!("Expected the tone and the pause, got {} records", records.len());
+    };
+    assert!((tone.freq - FREQ).abs() < 0.5, "{}", tone.freq);
+    assert!(pause.freq.abs() < f32::EPSILON);
+
+    // Streaming yields the same records as the whole data:
+    let Some(Data::Frequency(channels)) = PitchTracker::new(60.0, 1000.0, 256, 0.85, -50.0)
+        .filter(Data::Amplitude(WaveData { samples, sample_rate: SAMPLE_RATE }))
+    else {
+        panic!("Unexpected data type");
+    };
+    assert_eq!(channels[0].iter().map(|record| record.duration).collect::<Vec<u64>>(), [tone.duration, pause.duration]);
+}
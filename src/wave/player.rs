@@ -78,8 +78,6 @@ pub mod frequencies {
     }
 
     mod multichannel {
-        use std::arch::x86_64;
-
         use beeper::sound_emitter::{SoundEmitter, BeeperFrequency};
         use nano_sleep::NanoWaiter;
         use crate::wave::filter::{Ticks, Nsec};
@@ -189,11 +187,11 @@ pub mod frequencies {
         
             let channel_count = playback_channels.len();
             let mut is_mute = false;
-            let mut previous_tick_count = unsafe { x86_64::_rdtsc() };
+            let mut previous_tick_count = waiter.ticks();
             let mut channel_switch_timestamp = previous_tick_count;
             let mut channel_index = 0;
             loop {
-                let current_ticks = unsafe { x86_64::_rdtsc() };
+                let current_ticks = waiter.ticks();
                 let elapsed_ticks = current_ticks - previous_tick_count;
                 previous_tick_count = current_ticks;
         
@@ -208,7 +206,7 @@ pub mod frequencies {
                         State::Mute(_) => {
                             has_unfinished_channels = true;
                         },
-                        State::TheEnd => continue,
+                        State::TheEnd => (),
                     }
                 }
                 
@@ -254,7 +252,7 @@ pub mod frequencies {
                             emitter.set_frequency(*freq);
                             break;
                         },
-                        State::Mute(_) | State::TheEnd => continue,
+                        State::Mute(_) | State::TheEnd => (),
                     }
                 }
 
@@ -0,0 +1,252 @@
+use std::f64::consts::PI;
+
+use super::filter::{Type, Data, Filter, StreamFilter, WaveData};
+
+///
+/// Band-limited resampler based on the Blackman-windowed sinc kernel.
+/// The kernel is tabulated once for the given pair of rates, so each output sample
+/// costs `2 * ZERO_CROSSINGS / cutoff` multiply-adds with linear interpolation between phases.
+///
+pub struct Resampler {
+    target_rate: u32,
+    stream: Option<Stream>
+}
+
+///
+/// Input samples around the current position and the position itself
+/// measured in input samples from the beginning of the buffer.
+///
+struct Stream {
+    kernel: Kernel,
+    source_rate: u32,
+    buffer: Vec<f32>,
+    position: f64,
+    end: Option<f64> // Position of the last input sample, known once the stream is finished
+}
+
+struct Kernel {
+    table: Vec<f32>,   // Right half of the kernel sampled with the PHASES step
+    half_width: usize, // In input samples
+    step: f64          // Input samples per output sample
+}
+
+const ZERO_CROSSINGS: f64 = 16.0; // On each side of the kernel
+const PHASES: usize = 256; // Table entries per input sample
+const ROLLOFF: f64 = 0.95; // Cutoff relative to the lowest Nyquist frequency to leave room for the transition band
+
+impl Kernel {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    fn new(source_rate: u32, target_rate: u32) -> Self {
+        let step = f64::from(source_rate) / f64::from(target_rate);
+
+        // Downsampling requires to cut everything above the new Nyquist frequency:
+        let cutoff = ROLLOFF * (1.0 / step).min(1.0);
+        let width = ZERO_CROSSINGS / cutoff;
+        let half_width = width.ceil() as usize;
+
+        let table = (0..=half_width * PHASES)
+            .map(|index| {
+                let distance = index as f64 / PHASES as f64;
+                if distance >= width {
+                    return 0.0;
+                }
+
+                let sinc = if index == 0 {
+                    1.0
+                } else {
+                    (PI * cutoff * distance).sin() / (PI * cutoff * distance)
+                };
+
+                // Blackman window over [-width, width]:
+                let x = PI * (distance / width + 1.0);
+                let window = 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos();
+
+                (cutoff * sinc * window) as f32
+            })
+            .collect();
+
+        Self { table, half_width, step }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    fn at(&self, distance: f64) -> f32 {
+        let scaled = distance.abs() * PHASES as f64;
+        let index = scaled as usize;
+        if index + 1 >= self.table.len() {
+            return 0.0;
+        }
+
+        let fraction = (scaled - scaled.floor()) as f32;
+        self.table[index] + (self.table[index + 1] - self.table[index]) * fraction
+    }
+}
+
+impl Stream {
+    fn new(source_rate: u32, target_rate: u32) -> Self {
+        let kernel = Kernel::new(source_rate, target_rate);
+
+        // Samples before the beginning are zeros:
+        let buffer = vec![0.0; kernel.half_width];
+        #[allow(clippy::cast_precision_loss)]
+        let position = kernel.half_width as f64;
+
+        Self { kernel, source_rate, buffer, position, end: None }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    fn process(&mut self) -> Vec<f32> {
+        let half_width = self.kernel.half_width;
+        let mut output = Vec::new();
+
+        loop {
+            if self.end.is_some_and(|end| self.position >= end) {
+                break;
+            }
+
+            let center = self.position as usize;
+            if center + half_width >= self.buffer.len() {
+                break;
+            }
+
+            let first = center + 1 - half_width;
+            let sample = self.buffer[first..=center + half_width]
+                .iter()
+                .enumerate()
+                .map(|(offset, &input)| input * self.kernel.at(self.position - (first + offset) as f64))
+                .sum();
+
+            output.push(sample);
+            self.position += self.kernel.step;
+        }
+
+        // Keep only the samples required by the next output:
+        let consumed = (self.position as usize + 1).saturating_sub(half_width).min(self.buffer.len());
+        self.buffer.drain(..consumed);
+        self.position -= consumed as f64;
+        if let Some(end) = self.end.as_mut() {
+            *end -= consumed as f64;
+        }
+
+        output
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn finish(&mut self) -> Vec<f32> {
+        self.end = Some(self.buffer.len() as f64);
+        self.buffer.resize(self.buffer.len() + self.kernel.half_width + 1, 0.0);
+        self.process()
+    }
+}
+
+impl Resampler {
+    #[must_use]
+    pub fn new(target_rate: u32) -> Self {
+        Self { target_rate, stream: None }
+    }
+}
+
+impl Filter for Resampler {
+    fn input_type(&self) -> Type {
+        Type::Amplitude
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = data else {
+            return None;
+        };
+
+        if wave.sample_rate == self.target_rate || wave.sample_rate == 0 {
+            return Some(Data::Amplitude(wave));
+        }
+
+        let mut stream = Stream::new(wave.sample_rate, self.target_rate);
+        stream.buffer.extend_from_slice(&wave.samples);
+        let mut samples = stream.process();
+        samples.extend(stream.finish());
+
+        Some(Data::Amplitude(WaveData { samples, sample_rate: self.target_rate }))
+    }
+}
+
+impl StreamFilter for Resampler {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Amplitude(wave) = chunk else {
+            return None;
+        };
+
+        if wave.sample_rate == self.target_rate || wave.sample_rate == 0 {
+            return Some(Data::Amplitude(wave));
+        }
+
+        // The rate may change only between songs, the previous one is flushed then:
+        let mut samples = Vec::new();
+        if self.stream.as_ref().is_some_and(|stream| stream.source_rate != wave.sample_rate) {
+            samples.extend(self.stream.take().map(|mut stream| stream.finish()).unwrap_or_default());
+        }
+
+        let stream = self.stream.get_or_insert_with(|| Stream::new(wave.sample_rate, self.target_rate));
+        stream.buffer.extend_from_slice(&wave.samples);
+        samples.extend(stream.process());
+
+        Some(Data::Amplitude(WaveData { samples, sample_rate: self.target_rate }))
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        let mut stream = self.stream.take()?;
+
+        Some(Data::Amplitude(WaveData { samples: stream.finish(), sample_rate: self.target_rate }))
+    }
+}
+
+
+
+#[test]
+#[allow(clippy::cast_precision_loss)]
+fn test() {
+    let source_rate = 48000;
+    let target_rate = 16000;
+
+    // 1 kHz passes, 12 kHz is above the new Nyquist frequency and must be suppressed:
+    let samples: Vec<f32> = (0..source_rate)
+        .map(|index| {
+            let time = f64::from(index) / f64::from(source_rate);
+            #[allow(clippy::cast_possible_truncation)]
+            let sample = (0.5 * (2.0 * PI * 1000.0 * time).sin() + 0.3 * (2.0 * PI * 12000.0 * time).sin()) as f32;
+            sample
+        })
+        .collect();
+
+    let batch = Resampler::new(target_rate).filter(Data::Amplitude(WaveData { samples: samples.clone(), sample_rate: source_rate }));
+    let Some(Data::Amplitude(batch)) = batch else {
+        panic!("Unexpected data type");
+    };
+
+    assert_eq!(batch.sample_rate, target_rate);
+    assert_eq!(batch.samples.len(), target_rate as usize);
+
+    // Compare with the pure 1 kHz sine far from the edges:
+    let max_error = batch.samples[100..batch.samples.len() - 100]
+        .iter()
+        .enumerate()
+        .map(|(index, &sample)| {
+            let time = (index + 100) as f64 / f64::from(target_rate);
+            (f64::from(sample) - 0.5 * (2.0 * PI * 1000.0 * time).sin()).abs()
+        })
+        .fold(0.0, f64::max);
+    assert!(max_error < 0.01, "max error is {max_error}");
+
+    // Streaming by uneven chunks gives the same result:
+    let mut resampler = Resampler::new(target_rate);
+    let mut streamed = Vec::new();
+    for chunk in samples.chunks(1000) {
+        if let Some(Data::Amplitude(wave)) = resampler.feed(Data::Amplitude(WaveData { samples: chunk.to_vec(), sample_rate: source_rate })) {
+            streamed.extend(wave.samples);
+        }
+    }
+    if let Some(Data::Amplitude(wave)) = resampler.finish() {
+        streamed.extend(wave.samples);
+    }
+
+    assert_eq!(streamed.len(), batch.samples.len());
+    assert!(streamed.iter().zip(&batch.samples).all(|(left, right)| (left - right).abs() < 1e-5));
+}
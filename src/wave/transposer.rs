@@ -0,0 +1,261 @@
+use note::Interval;
+
+use super::filter::{Type, Data, Filter, StreamFilter, FreqRecord, FreqData, Nsec};
+
+///
+/// Band which the speaker plays well: the notes below it sound thin and the ones above it piercing.
+///
+pub const COMFORTABLE_RANGE: (f32, f32) = (100.0, 4000.0);
+
+const DEFAULT_PHRASE_GAP_MS: u64 = 300;
+const MAX_OCTAVES: i32 = 10; // The farthest shift of a channel or a phrase into the band
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Channel, // The whole channel is shifted by the same octaves
+    Phrase   // Each phrase between the pauses is shifted on its own
+}
+
+///
+/// Transposes the notes by the interval. Optionally fits them into the band afterwards: the whole
+/// channel or phrase is shifted by the octaves keeping the most of its sounding time within the band,
+/// so the melodic contour stays, and only the notes still out of the band are folded into it one by one.
+///
+pub struct Transposer {
+    interval: Interval,
+    fit: Option<(f32, f32, Scope)>, // The lowest and highest frequencies and the scope of the octave shifts
+    phrase_gap: Nsec,               // Pauses of this length and longer separate the phrases
+    pending: FreqData<f32>          // Unfinished phrases or the whole channels to fit
+}
+
+const NANOS_IN_MSEC: Nsec = 1_000_000;
+
+impl Transposer {
+    #[must_use]
+    pub fn new(interval: Interval) -> Self {
+        Self { interval, fit: None, phrase_gap: DEFAULT_PHRASE_GAP_MS * NANOS_IN_MSEC, pending: FreqData::default() }
+    }
+
+    #[must_use]
+    pub fn with_fit(mut self, low: f32, high: f32, scope: Scope) -> Self {
+        self.fit = Some((low, high, scope));
+        self
+    }
+
+    #[must_use]
+    pub fn with_phrase_gap(mut self, msec: u64) -> Self {
+        self.phrase_gap = msec * NANOS_IN_MSEC;
+        self
+    }
+
+    ///
+    /// Indices of the records starting the phrases and whether the records end with a gap,
+    /// i.e. the last phrase is complete. The pauses belong to the phrase they follow.
+    ///
+    fn phrases(&self, records: &[FreqRecord<f32>]) -> (Vec<usize>, bool) {
+        let mut starts = vec![0];
+        let mut pause: Option<Nsec> = None; // Nothing has sounded yet
+
+        for (index, record) in records.iter().enumerate() {
+            if record.freq > 0.0 {
+                if pause.is_some_and(|pause| pause >= self.phrase_gap) {
+                    starts.push(index);
+                }
+                pause = Some(0);
+            } else if let Some(pause) = &mut pause {
+                *pause += record.duration;
+            }
+        }
+
+        (starts, pause.is_none_or(|pause| pause >= self.phrase_gap))
+    }
+
+    ///
+    /// Transposes the records, then fits each phrase or all of them into the band.
+    ///
+    fn apply(&self, records: &mut [FreqRecord<f32>]) {
+        let ratio = self.interval.ratio();
+        records.iter_mut().filter(|record| record.freq > 0.0).for_each(|record| record.freq *= ratio);
+
+        let Some((low, high, scope)) = self.fit else {
+            return;
+        };
+
+        if scope == Scope::Channel {
+            fit(records, low, high);
+            return;
+        }
+
+        let (mut bounds, _) = self.phrases(records);
+        bounds.push(records.len());
+        for phrase in bounds.windows(2) {
+            fit(&mut records[phrase[0]..phrase[1]], low, high);
+        }
+    }
+
+    ///
+    /// Takes the leading records which may be already emitted: the complete phrases
+    /// or nothing until the end of the stream if the whole channel is fitted at once.
+    ///
+    fn take_complete(&self, records: &mut Vec<FreqRecord<f32>>) -> Vec<FreqRecord<f32>> {
+        let complete = match self.fit {
+            None => records.len(),
+            Some((_, _, Scope::Channel)) => 0,
+            Some((_, _, Scope::Phrase)) => match self.phrases(records) {
+                (_, true) => records.len(),
+                (starts, false) => starts.last().copied().unwrap_or_default()
+            }
+        };
+
+        records.drain(..complete).collect()
+    }
+}
+
+///
+/// Shifts the records by the octaves which keep the most of the sounding time within the band
+/// (the nearest shift wins the ties), then folds the rest of the notes into the band.
+///
+fn fit(records: &mut [FreqRecord<f32>], low: f32, high: f32) {
+    let within = |octaves: i32| -> Nsec {
+        let ratio = 2_f32.powi(octaves);
+        records
+            .iter()
+            .filter(|record| record.freq > 0.0 && (low..=high).contains(&(record.freq * ratio)))
+            .map(|record| record.duration)
+            .sum()
+    };
+
+    let octaves = (-MAX_OCTAVES..=MAX_OCTAVES)
+        .max_by_key(|octaves| (within(*octaves), std::cmp::Reverse(octaves.abs())))
+        .unwrap_or_default();
+
+    let ratio = 2_f32.powi(octaves);
+    for record in records.iter_mut().filter(|record| record.freq > 0.0) {
+        record.freq = fold(record.freq * ratio, low, high);
+    }
+}
+
+///
+/// Moves the frequency by octaves into the band, or as near to it as possible if the band is narrower than an octave.
+///
+fn fold(freq: f32, low: f32, high: f32) -> f32 {
+    if !freq.is_finite() {
+        return freq;
+    }
+
+    let mut folded = freq;
+    while folded > high {
+        folded /= 2.0;
+    }
+    while folded < low {
+        folded *= 2.0;
+    }
+
+    // Both neighbours are out of the narrow band, the nearer one in cents is taken:
+    if folded > high && low / (folded / 2.0) < folded / high {
+        folded / 2.0
+    } else {
+        folded
+    }
+}
+
+impl Filter for Transposer {
+    fn input_type(&self) -> Type {
+        Type::Frequency
+    }
+
+    fn filter(&self, data: Data) -> Option<Data> {
+        let Data::Frequency(mut channels) = data else {
+            return None;
+        };
+
+        for channel in &mut channels {
+            self.apply(channel);
+        }
+        Some(Data::Frequency(channels))
+    }
+}
+
+impl StreamFilter for Transposer {
+    fn feed(&mut self, chunk: Data) -> Option<Data> {
+        let Data::Frequency(channels) = chunk else {
+            return None;
+        };
+
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.resize_with(pending.len().max(channels.len()), Vec::new);
+
+        let mut output = FreqData::default();
+        for (pending, records) in pending.iter_mut().zip(channels) {
+            pending.extend(records);
+
+            let mut complete = self.take_complete(pending);
+            self.apply(&mut complete);
+            output.push(complete);
+        }
+
+        self.pending = pending;
+        Some(Data::Frequency(output))
+    }
+
+    fn finish(&mut self) -> Option<Data> {
+        let mut channels = std::mem::take(&mut self.pending);
+        if channels.iter().all(Vec::is_empty) {
+            return None;
+        }
+
+        for channel in &mut channels {
+            self.apply(channel);
+        }
+        Some(Data::Frequency(channels))
+    }
+}
+
+
+
+#[test]
+fn test() {
+    let notes = |freqs: &[f32]| freqs.iter().map(|freq| FreqRecord { freq: *freq, duration: 100 * NANOS_IN_MSEC }).collect::<Vec<FreqRecord<f32>>>();
+    let freqs = |records: &[FreqRecord<f32>]| records.iter().map(|record| record.freq).collect::<Vec<f32>>();
+    let close = |left: &[f32], right: &[f32]| left.len() == right.len() && left.iter().zip(right).all(|(left, right)| (left - right).abs() < 0.01);
+
+    // A fifth up, the pauses stay:
+    let transposer = Transposer::new(Interval::PERFECT_FIFTH);
+    let Some(Data::Frequency(channels)) = transposer.filter(Data::Frequency(vec![notes(&[220.0, 0.0])])) else {
+        panic!("Unexpected data type");
+    };
+    assert!(close(&freqs(&channels[0]), &[329.63, 0.0]));
+
+    // The bass line goes two octaves up as a whole, the outlier is folded alone:
+    let transposer = Transposer::new(Interval::UNISON).with_fit(100.0, 4000.0, Scope::Channel);
+    let Some(Data::Frequency(channels)) = transposer.filter(Data::Frequency(vec![notes(&[41.2, 55.0, 49.0, 27.5, 5000.0])])) else {
+        panic!("Unexpected data type");
+    };
+    assert!(close(&freqs(&channels[0]), &[164.8, 220.0, 196.0, 110.0, 2500.0]));
+
+    // The phrases separated by the long pause are shifted on their own while streaming:
+    let mut transposer = Transposer::new(Interval::UNISON).with_fit(100.0, 4000.0, Scope::Phrase).with_phrase_gap(250);
+    let mut output = Vec::new();
+    for chunk in [notes(&[60.0, 80.0, 0.0]), notes(&[0.0, 0.0, 9000.0]), notes(&[6000.0])] {
+        let Some(Data::Frequency(channels)) = transposer.feed(Data::Frequency(vec![chunk])) else {
+            panic!("Unexpected data type");
+        };
+        output.extend(channels.into_iter().flatten());
+        if output.is_empty() {
+            continue;
+        }
+        // The first phrase is emitted as soon as the gap is over:
+        assert!(close(&freqs(&output[..2]), &[120.0, 160.0]));
+    }
+    assert_eq!(output.len(), 5);
+
+    let Some(Data::Frequency(channels)) = transposer.finish() else {
+        panic!("Unexpected data type");
+    };
+    output.extend(channels.into_iter().flatten());
+    assert!(close(&freqs(&output), &[120.0, 160.0, 0.0, 0.0, 0.0, 2250.0, 1500.0]));
+
+    // A band narrower than an octave keeps the notes as near to it as possible:
+    assert!((fold(300.0, 400.0, 500.0) - 600.0).abs() < 0.01);
+    assert!((fold(350.0, 400.0, 450.0) - 350.0).abs() < 0.01);
+}
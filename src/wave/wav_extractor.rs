@@ -1,47 +1,160 @@
-use super::wav_header::{Wave, WaveView};
+use std::ops::Range;
+
+use super::wav_header::{Format, WaveView};
 use super::filter::{Data, WaveData};
 
+///
+/// The way to turn multichannel frames into mono samples.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Downmix {
+    #[default] Mono, // Average of all channels
+    Mid,             // (Left + Right) / 2, keeps the center: vocals and lead melody
+    Side,            // (Left - Right) / 2, removes the center
+    Left,
+    Right,
+    Channel(u16)     // Zero-based index of the channel
+}
+
+impl Downmix {
+    ///
+    /// Whether the strategy can be applied to frames of the given number of channels.
+    /// Mono files are treated as both left and right channels,
+    /// but not for the side which would always be silent.
+    ///
+    #[must_use]
+    pub fn is_applicable(self, num_channels: u16) -> bool {
+        match self {
+            Downmix::Channel(index) => index < num_channels,
+            Downmix::Side => num_channels > 1,
+            _ => num_channels > 0
+        }
+    }
+
+    ///
+    /// Mixes a single frame down, `channel` returns the sample of the channel by its index.
+    ///
+    pub fn mix(self, num_channels: usize, channel: impl Fn(usize) -> f32) -> f32 {
+        let right = || channel(usize::from(num_channels > 1));
+
+        match self {
+            #[allow(clippy::cast_precision_loss)]
+            Downmix::Mono => (0..num_channels).map(&channel).sum::<f32>() / num_channels.max(1) as f32,
+            Downmix::Mid => f32::midpoint(channel(0), right()),
+            Downmix::Side => (channel(0) - right()) / 2.0,
+            Downmix::Left => channel(0),
+            Downmix::Right => right(),
+            Downmix::Channel(index) => channel(usize::from(index))
+        }
+    }
+}
+
+///
+/// Converts the given frames to the [-1.0, 1.0] samples mixing the channels down.
+///
+fn convert_frames(format: &Format, data: &[u8], frames: Range<usize>, downmix: Downmix) -> Vec<f32> {
+    let block_align = usize::from(format.block_align);
+    let sample_size = format.encoding.sample_size();
+    data[(frames.start * block_align)..(frames.end * block_align)]
+        .chunks_exact(block_align)
+        .map(|frame| downmix.mix(usize::from(format.num_channels), |index| frame
+            .get(index * sample_size..(index + 1) * sample_size)
+            .map_or(0.0, |sample| format.encoding.decode(sample))
+        ))
+        .collect()
+}
+
+///
+/// Converts all samples of the wave at once.
+///
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn extract(wave_view: &WaveView, downmix: Downmix) -> Data {
+    Data::Amplitude(
+        WaveData {
+            samples: convert_frames(wave_view.format(), wave_view.data(), 0..wave_view.frame_count(), downmix),
+            sample_rate: wave_view.format().sample_rate
+        }
+    )
+}
+
 impl From<WaveView<'_>> for Data {
-    #[allow(clippy::cast_possible_truncation)]
     fn from(wave_view: WaveView) -> Self {
-        let wave = wave_view.lookup_samples();
-        match wave {
-            Wave::Unknown => Data::Amplitude(
-                WaveData {
-                    samples: Vec::new(),
-                    sample_rate: wave_view.header().sample_rate as u16
-                }
-            ),
-            Wave::Wave8(samples) => Data::Amplitude(
-                // [0, 255] -> [-1.0, 1.0]:
-                WaveData {
-                    samples: samples.iter()
-                        .step_by(usize::from(wave_view.header().num_channels))
-                        .map(|&sample| ((f32::from(sample) - f32::from(i8::MAX)) / f32::from(i8::MAX)).clamp(-1.0, 1.0))
-                        .collect(),
-                    sample_rate: wave_view.header().sample_rate as u16
-                }
-            ),
-            Wave::Wave16(samples) => Data::Amplitude(
-                // [-32768, 32767] -> [0.0, 1.0]:
-                WaveData {
-                    samples: samples.iter()
-                        .step_by(usize::from(wave_view.header().num_channels))
-                        .map(|&sample| (f32::from(sample) / f32::from(i16::MAX)).clamp(-1.0, 1.0))
-                        .collect(),
-                    sample_rate: wave_view.header().sample_rate as u16
-                }
-            ),
-            Wave::Wave32(samples) => Data::Amplitude(
-                // [-2'147'483'648, 2'147'483'647] -> [0.0, 1.0]:
-                WaveData {
-                    samples: samples.iter()
-                        .step_by(usize::from(wave_view.header().num_channels))
-                        .map(|&sample| (f64::from(sample) / f64::from(i32::MAX)).clamp(-1.0, 1.0) as f32)
-                        .collect(),
-                    sample_rate: wave_view.header().sample_rate as u16
-                }
-            )
+        extract(&wave_view, Downmix::default())
+    }
+}
+
+///
+/// Lazily converts the samples of the wave by chunks of the given number of frames.
+///
+pub struct WaveChunks<'a> {
+    format: Format,
+    data: &'a [u8],
+    frame_count: usize,
+    sample_rate: u32,
+    downmix: Downmix,
+    position: usize,
+    frames_per_chunk: usize
+}
+
+impl<'a> WaveChunks<'a> {
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn new(wave_view: &WaveView<'a>, frames_per_chunk: usize, downmix: Downmix) -> Self {
+        Self {
+            format: *wave_view.format(),
+            data: wave_view.data(),
+            frame_count: wave_view.frame_count(),
+            sample_rate: wave_view.format().sample_rate,
+            downmix,
+            position: 0,
+            frames_per_chunk: frames_per_chunk.max(1)
+        }
+    }
+}
+
+impl Iterator for WaveChunks<'_> {
+    type Item = Data;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.frame_count {
+            return None;
         }
+
+        let end = (self.position + self.frames_per_chunk).min(self.frame_count);
+        let samples = convert_frames(&self.format, self.data, self.position..end, self.downmix);
+        self.position = end;
+
+        Some(Data::Amplitude(WaveData { samples, sample_rate: self.sample_rate }))
     }
-}
\ No newline at end of file
+}
+
+
+
+#[test]
+fn test() {
+    let stereo = [0.5_f32, -0.25];
+    let mix = |downmix: Downmix, frame: &[f32]| downmix.mix(frame.len(), |index| frame[index]);
+    assert!((mix(Downmix::Mono, &stereo) - 0.125).abs() < 1e-6);
+    assert!((mix(Downmix::Mid, &stereo) - 0.125).abs() < 1e-6);
+    assert!((mix(Downmix::Side, &stereo) - 0.375).abs() < 1e-6);
+    assert!((mix(Downmix::Left, &stereo) - 0.5).abs() < 1e-6);
+    assert!((mix(Downmix::Right, &stereo) + 0.25).abs() < 1e-6);
+    assert!((mix(Downmix::Channel(1), &stereo) + 0.25).abs() < 1e-6);
+
+    // 5.1 is FL, FR, FC, LFE, BL, BR, the left and right are the front ones:
+    let surround = [0.6_f32, 0.2, 0.3, -0.6, 0.1, 0.6];
+    assert!((mix(Downmix::Mono, &surround) - 0.2).abs() < 1e-6);
+    assert!((mix(Downmix::Mid, &surround) - 0.4).abs() < 1e-6);
+    assert!((mix(Downmix::Side, &surround) - 0.2).abs() < 1e-6);
+    assert!((mix(Downmix::Left, &surround) - 0.6).abs() < 1e-6);
+    assert!((mix(Downmix::Right, &surround) - 0.2).abs() < 1e-6);
+    assert!((mix(Downmix::Channel(3), &surround) + 0.6).abs() < 1e-6);
+
+    // The mono file is both left and right, but has no side:
+    assert!((mix(Downmix::Right, &[0.7]) - 0.7).abs() < 1e-6);
+    assert!(Downmix::Right.is_applicable(1) && Downmix::Mid.is_applicable(1));
+    assert!(!Downmix::Side.is_applicable(1) && Downmix::Side.is_applicable(2));
+    assert!(Downmix::Channel(5).is_applicable(6) && !Downmix::Channel(6).is_applicable(6));
+    assert!(!Downmix::Mono.is_applicable(0));
+}
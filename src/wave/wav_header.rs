@@ -1,149 +1,538 @@
-#[repr(C, packed(1))]
-pub struct WavHeader {
-    pub chunk_id: u32, // 'RIFF' in ASCII
-    pub chunk_size: u32,
-    pub format: u32, // 'WAVE'
-    pub first_subchunk_header: SubchunkHeader, // 'fmt' subchunk
-    pub audio_format: u16, // PCM = 1, other values mean that some compression is present
+//!
+//! Bounds-checked parser of RIFF/RF64 WAVE files.
+//! Chunks may come in any order, unknown chunks are skipped.
+//!
+
+pub type FourCC = [u8; 4];
+
+const RIFF_SIGNATURE: FourCC = *b"RIFF";
+const RF64_SIGNATURE: FourCC = *b"RF64";
+const WAVE_SIGNATURE: FourCC = *b"WAVE";
+const FMT_SIGNATURE: FourCC = *b"fmt ";
+const DATA_SIGNATURE: FourCC = *b"data";
+const DS64_SIGNATURE: FourCC = *b"ds64";
+const LIST_SIGNATURE: FourCC = *b"LIST";
+const INFO_SIGNATURE: FourCC = *b"INFO";
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// KSDATAFORMAT_SUBTYPE_* GUIDs differ only in the first two bytes which hold the format tag:
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71];
+
+const CHUNK_HEADER_SIZE: usize = 8; // Id and size
+const RIFF_HEADER_SIZE: usize = 12; // 'RIFF', size and 'WAVE'
+const RF64_SIZE_PLACEHOLDER: u32 = 0xFFFF_FFFF; // The real size is stored in the 'ds64' chunk
+
+#[derive(Debug)]
+pub enum Error {
+    NotWave,
+    Truncated(FourCC, usize),   // Chunk id and its offset
+    InvalidFmt(&'static str),
+    MissingDs64,
+    MissingFmt,
+    MissingData,
+    UnsupportedFormat(u16),     // Format tag
+    UnsupportedBitsPerSample(u16, u16) // Format tag and bits per sample
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NotWave => write!(f, "It's not a known wav-file"),
+            Error::Truncated(id, offset) => write!(
+                f,
+                "The '{}' chunk at offset {offset} exceeds the end of the file",
+                String::from_utf8_lossy(id)
+            ),
+            Error::InvalidFmt(reason) => write!(f, "Invalid 'fmt ' chunk: {reason}"),
+            Error::MissingDs64 => write!(f, "The RF64 file has no 'ds64' chunk"),
+            Error::MissingFmt => write!(f, "There is no 'fmt ' chunk"),
+            Error::MissingData => write!(f, "There is no 'data' chunk"),
+            Error::UnsupportedFormat(tag) => write!(f, "Unsupported audio format 0x{tag:04X}"),
+            Error::UnsupportedBitsPerSample(tag, bits) => write!(f, "Unsupported {bits}-bit samples for the audio format 0x{tag:04X}")
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+///
+/// Layout of a single sample in the 'data' chunk.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Pcm8,    // Unsigned
+    Pcm16,
+    Pcm24,   // Packed into 3 bytes
+    Pcm32,
+    Float32,
+    Float64
+}
+
+impl Encoding {
+    #[must_use]
+    pub fn sample_size(self) -> usize {
+        match self {
+            Encoding::Pcm8 => 1,
+            Encoding::Pcm16 => 2,
+            Encoding::Pcm24 => 3,
+            Encoding::Pcm32 | Encoding::Float32 => 4,
+            Encoding::Float64 => 8
+        }
+    }
+
+    ///
+    /// Converts the little-endian sample to the [-1.0, 1.0] range.
+    /// The `bytes` must hold at least `sample_size()` bytes.
+    ///
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    pub fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            // [0, 255] -> [-1.0, 1.0]:
+            Encoding::Pcm8 => ((f32::from(bytes[0]) - f32::from(i8::MAX)) / f32::from(i8::MAX)).clamp(-1.0, 1.0),
+            // [-32768, 32767] -> [-1.0, 1.0]:
+            Encoding::Pcm16 => (f32::from(i16::from_le_bytes([bytes[0], bytes[1]])) / f32::from(i16::MAX)).clamp(-1.0, 1.0),
+            // [-8'388'608, 8'388'607] -> [-1.0, 1.0], sign is extended by the arithmetic shift:
+            Encoding::Pcm24 => {
+                const MAX: f32 = 8_388_607.0;
+                let sample = i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8;
+                (sample as f32 / MAX).clamp(-1.0, 1.0)
+            },
+            // [-2'147'483'648, 2'147'483'647] -> [-1.0, 1.0]:
+            Encoding::Pcm32 => {
+                let sample = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                (f64::from(sample) / f64::from(i32::MAX)).clamp(-1.0, 1.0) as f32
+            },
+            Encoding::Float32 => {
+                let sample = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                if sample.is_nan() { 0.0 } else { sample.clamp(-1.0, 1.0) }
+            },
+            Encoding::Float64 => {
+                let mut raw = [0_u8; 8];
+                raw.copy_from_slice(&bytes[..8]);
+                let sample = f64::from_le_bytes(raw);
+                if sample.is_nan() { 0.0 } else { sample.clamp(-1.0, 1.0) as f32 }
+            }
+        }
+    }
+}
+
+///
+/// Contents of the 'fmt ' chunk with the extensible format resolved to its subformat.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Format {
+    pub tag: u16, // Audio format: PCM or IEEE float, even if the file uses the extensible format
+    pub encoding: Encoding,
     pub num_channels: u16,
     pub sample_rate: u32, // In Hertz
     pub byte_rate: u32, // Bytes per second
     pub block_align: u16, // Byte count for one sample including all channels
-    pub bits_per_sample: u16,
-    // subchunk_id
-    // subchunk_size
-    // data
-    // ...
+    pub bits_per_sample: u16, // Container size of the sample
+    pub valid_bits_per_sample: u16, // Meaningful bits, equal to `bits_per_sample` if not extensible
+    pub channel_mask: u32 // Speaker positions, zero if not extensible
 }
 
-impl WavHeader {
-    const RIFF_SIGNATURE: u32 = 0x464_64952; // 'R', 'I', 'F', 'F'
-    const WAVE_SIGNATURE: u32 = 0x455_64157; // 'W', 'A', 'V', 'E'
+impl Format {
+    fn parse(body: &[u8]) -> Result<Self, Error> {
+        if body.len() < 16 {
+            return Err(Error::InvalidFmt("the chunk is shorter than 16 bytes"));
+        }
+
+        let mut format = Format {
+            tag: read_u16(body, 0),
+            encoding: Encoding::Pcm16,
+            num_channels: read_u16(body, 2),
+            sample_rate: read_u32(body, 4),
+            byte_rate: read_u32(body, 8),
+            block_align: read_u16(body, 12),
+            bits_per_sample: read_u16(body, 14),
+            valid_bits_per_sample: read_u16(body, 14),
+            channel_mask: 0
+        };
 
-    pub fn is_wav(buf: &[u8]) -> bool {
-        if buf.len() < std::mem::size_of::<WavHeader>() {
-            return false;
+        if format.tag == WAVE_FORMAT_EXTENSIBLE {
+            // cbSize, wValidBitsPerSample, dwChannelMask and SubFormat follow the basic fields:
+            if body.len() < 40 || read_u16(body, 16) < 22 {
+                return Err(Error::InvalidFmt("the extensible format is shorter than 40 bytes"));
+            }
+
+            if body[26..40] != SUBFORMAT_GUID_TAIL {
+                return Err(Error::UnsupportedFormat(WAVE_FORMAT_EXTENSIBLE));
+            }
+
+            format.valid_bits_per_sample = read_u16(body, 18);
+            format.channel_mask = read_u32(body, 20);
+            format.tag = read_u16(body, 24);
+        }
+
+        format.encoding = match (format.tag, format.bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => Encoding::Pcm8,
+            (WAVE_FORMAT_PCM, 16) => Encoding::Pcm16,
+            (WAVE_FORMAT_PCM, 24) => Encoding::Pcm24,
+            (WAVE_FORMAT_PCM, 32) => Encoding::Pcm32,
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Encoding::Float32,
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => Encoding::Float64,
+            (WAVE_FORMAT_PCM | WAVE_FORMAT_IEEE_FLOAT, bits) => return Err(Error::UnsupportedBitsPerSample(format.tag, bits)),
+            (tag, _) => return Err(Error::UnsupportedFormat(tag))
+        };
+
+        if format.num_channels == 0 {
+            return Err(Error::InvalidFmt("zero channels"));
         }
 
-        let wav_header: &WavHeader = unsafe { &*(std::ptr::addr_of!(buf[0]).cast()) };
-        if (wav_header.chunk_id != WavHeader::RIFF_SIGNATURE) || (wav_header.format != WavHeader::WAVE_SIGNATURE) {
-            return false;
+        if format.sample_rate == 0 {
+            return Err(Error::InvalidFmt("zero sample rate"));
         }
 
-        true
+        if usize::from(format.block_align) < usize::from(format.num_channels) * format.encoding.sample_size() {
+            return Err(Error::InvalidFmt("the block align is less than the size of all channels' samples"));
+        }
+
+        Ok(format)
     }
 }
 
+///
+/// Single entry of the 'LIST' chunk of the 'INFO' type, e.g. 'INAM' (title) or 'IART' (artist).
+///
+#[derive(Debug, Clone)]
+pub struct InfoEntry {
+    pub id: FourCC,
+    pub value: String
+}
+
+pub struct WaveView<'a> {
+    format: Format,
+    data: &'a [u8], // Whole frames only
+    info: Vec<InfoEntry>
+}
+
+///
+/// Checks the RIFF/RF64 signature without parsing the chunks.
+///
+#[must_use]
+pub fn is_wav(buf: &[u8]) -> bool {
+    buf.len() >= RIFF_HEADER_SIZE
+        && (buf[..4] == RIFF_SIGNATURE || buf[..4] == RF64_SIGNATURE)
+        && buf[8..12] == WAVE_SIGNATURE
+}
 
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
 
-#[repr(C, packed(1))]
-pub struct SubchunkHeader {
-    pub subchunk_id: u32,   // 'fmt' in BigEndian for the first chunk and custom id for the next chunks
-    pub subchunk_size: u32, // Size of raw data not including this header
-    // ... raw data with size of 'subchunk_size' field ...
-} 
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
 
-impl SubchunkHeader {
-    pub const FMT_SIGNATURE: u32 = 0x2074_6D66;  // 'f', 'm', 't', ' '
-    pub const DATA_SIGNATURE: u32 = 0x6174_6164; // 'd', 'a', 't', 'a'
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from(read_u32(buf, offset)) | (u64::from(read_u32(buf, offset + 4)) << 32)
+}
 
-    #[must_use]
-    pub fn next(&self) -> *const SubchunkHeader {
-        unsafe {
-            std::ptr::addr_of!(*self)
-                .cast::<u8>()
-                .add(std::mem::size_of::<SubchunkHeader>() + self.subchunk_size as usize)
-                .cast()
+fn read_fourcc(buf: &[u8], offset: usize) -> FourCC {
+    [buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]]
+}
+
+///
+/// 64-bit sizes of the RF64 file which replace the 0xFFFFFFFF placeholders of the 32-bit ones.
+///
+#[derive(Default)]
+struct Ds64 {
+    riff_size: u64,
+    data_size: u64,
+    table: Vec<(FourCC, u64)> // Sizes of other chunks exceeding 4 GB
+}
+
+impl Ds64 {
+    fn parse(body: &[u8]) -> Result<Self, Error> {
+        if body.len() < 28 {
+            return Err(Error::MissingDs64);
         }
-    }
 
-    #[must_use]
-    pub fn is_fmt(&self) -> bool {
-        self.subchunk_id == Self::FMT_SIGNATURE
+        let table_length = read_u32(body, 24) as usize;
+        let table = body[28..]
+            .chunks_exact(12)
+            .take(table_length)
+            .map(|entry| (read_fourcc(entry, 0), read_u64(entry, 4)))
+            .collect();
+
+        Ok(Self { riff_size: read_u64(body, 0), data_size: read_u64(body, 8), table })
     }
 
-    #[must_use]
-    pub fn is_data(&self) -> bool {
-        self.subchunk_id == Self::DATA_SIGNATURE
+    fn chunk_size(&self, id: FourCC) -> Option<u64> {
+        if id == DATA_SIGNATURE {
+            return Some(self.data_size);
+        }
+
+        self.table.iter().find(|(entry_id, _)| *entry_id == id).map(|&(_, size)| size)
     }
 }
 
+fn parse_info(body: &[u8]) -> Vec<InfoEntry> {
+    let mut entries = Vec::new();
 
+    // Skip the 'INFO' type, broken entries end the list without failing the whole file:
+    let mut offset = 4;
+    while offset + CHUNK_HEADER_SIZE <= body.len() {
+        let id = read_fourcc(body, offset);
+        let size = read_u32(body, offset + 4) as usize;
+        let start = offset + CHUNK_HEADER_SIZE;
+        let Some(text) = start.checked_add(size).and_then(|end| body.get(start..end)) else {
+            break;
+        };
 
-pub enum Wave<'a> {
-    Unknown,
-    Wave8(&'a [u8]),
-    Wave16(&'a [i16]),
-    Wave32(&'a [i32])
-}
+        let value = String::from_utf8_lossy(text).trim_end_matches('\0').to_owned();
+        entries.push(InfoEntry { id, value });
 
-impl From<Wave<'_>> for Vec<i16> {
-    ///
-    /// Converts waves to 16-bit signed samples with a tiny amplitude loss.
-    ///
-    #[allow(clippy::cast_possible_truncation)]
-    fn from(wave: Wave) -> Self {
-        match wave {
-            Wave::Wave8(data) => data.iter().map(|&sample| (i16::from(sample) - i16::from(i8::MAX)) * (i16::from(u8::MAX) + 1)).collect(),
-            Wave::Wave16(data) => data.to_vec(),
-            Wave::Wave32(data) => data.iter().map(|&sample| (sample / (i32::from(u16::MAX) + 1)) as i16).collect(),
-            Wave::Unknown => Vec::new()
-        }
+        offset = start + size + (size & 1);
     }
-}
 
-pub struct WaveView<'a> {
-    header: &'a WavHeader
+    entries
 }
 
 impl<'a> TryFrom<&'a [u8]> for WaveView<'a> {
-    type Error = &'static str;
+    type Error = Error;
+
+    fn try_from(buf: &'a [u8]) -> Result<WaveView<'a>, Self::Error> {
+        if !is_wav(buf) {
+            return Err(Error::NotWave);
+        }
+
+        let is_rf64 = buf[..4] == RF64_SIGNATURE;
+        let mut ds64: Option<Ds64> = None;
+        let mut format: Option<Format> = None;
+        let mut data: Option<&'a [u8]> = None;
+        let mut info = Vec::new();
+
+        // The size of the RIFF chunk limits the chunks, trailing garbage is ignored.
+        // Streaming writers may leave it zero, so the end of the buffer is used then:
+        let riff_size = read_u32(buf, 4) as usize;
+        let mut end_of_riff = if !is_rf64 && riff_size >= 4 {
+            buf.len().min(riff_size.saturating_add(CHUNK_HEADER_SIZE))
+        } else {
+            buf.len()
+        };
+
+        let mut offset = RIFF_HEADER_SIZE;
+        while offset + CHUNK_HEADER_SIZE <= end_of_riff {
+            let id = read_fourcc(buf, offset);
+            let declared_size = read_u32(buf, offset + 4);
+
+            let size = match &ds64 {
+                Some(ds64) if declared_size == RF64_SIZE_PLACEHOLDER => ds64.chunk_size(id).unwrap_or(u64::from(declared_size)),
+                _ => u64::from(declared_size)
+            };
+
+            let start = offset + CHUNK_HEADER_SIZE;
+            let body = usize::try_from(size)
+                .ok()
+                .and_then(|size| start.checked_add(size))
+                .and_then(|end| buf.get(start..end))
+                .ok_or(Error::Truncated(id, offset))?;
 
-    fn try_from(data: &'a [u8]) -> Result<WaveView, Self::Error> {
-        if !WavHeader::is_wav(data) {
-            return Err("It's not a known wav-file.");
+            match id {
+                DS64_SIGNATURE if is_rf64 && offset == RIFF_HEADER_SIZE => {
+                    let parsed = Ds64::parse(body)?;
+                    if let Ok(riff_end) = usize::try_from(parsed.riff_size.saturating_add(CHUNK_HEADER_SIZE as u64)) {
+                        end_of_riff = end_of_riff.min(riff_end);
+                    }
+                    ds64 = Some(parsed);
+                },
+                FMT_SIGNATURE => format = Some(Format::parse(body)?),
+                DATA_SIGNATURE => data = Some(body),
+                LIST_SIGNATURE if body.len() >= 4 && read_fourcc(body, 0) == INFO_SIGNATURE => info.extend(parse_info(body)),
+                _ => ()
+            }
+
+            if is_rf64 && ds64.is_none() {
+                return Err(Error::MissingDs64);
+            }
+
+            // Chunks are word-aligned:
+            offset = start + body.len() + (body.len() & 1);
         }
 
-        let wav_header: &WavHeader = unsafe { &*(std::ptr::addr_of!(data[0]).cast()) };
+        let format = format.ok_or(Error::MissingFmt)?;
+        let data = data.ok_or(Error::MissingData)?;
 
-        Ok(Self { header: wav_header })
+        let block_align = usize::from(format.block_align);
+        let data = &data[..data.len() - data.len() % block_align];
+
+        Ok(Self { format, data, info })
     }
 }
 
 impl<'a> WaveView<'a> {
     #[must_use]
-    pub fn header(&self) -> &WavHeader {
-        self.header
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+
+    ///
+    /// Raw interleaved frames of the 'data' chunk, each one is `block_align` bytes.
+    ///
+    #[must_use]
+    pub fn data(&self) -> &'a [u8] {
+        self.data
     }
 
     #[must_use]
-    pub fn lookup_samples(&self) -> Wave<'a> {
-        unsafe {
-            let mut subchunk = std::ptr::addr_of!(self.header.first_subchunk_header);
-            let end_of_file = std::ptr::addr_of!(*self.header).cast::<u8>().add(self.header.chunk_size as usize - 8);
-            
-            while !(*subchunk).is_data() {
-                subchunk = (*subchunk).next();
-                if subchunk as usize >= end_of_file as usize {
-                    return Wave::Unknown;
-                }
-            }
+    pub fn frame_count(&self) -> usize {
+        self.data.len() / usize::from(self.format.block_align)
+    }
 
-            match self.header().bits_per_sample {
-                8 => Wave::Wave8(std::slice::from_raw_parts(
-                    subchunk.cast::<u8>().add(std::mem::size_of::<SubchunkHeader>()).cast(),
-                    (*subchunk).subchunk_size as usize
-                )),
-                16 => Wave::Wave16(std::slice::from_raw_parts(
-                    subchunk.cast::<u8>().add(std::mem::size_of::<SubchunkHeader>()).cast(),
-                    (*subchunk).subchunk_size as usize / 2
-                )),
-                32 => Wave::Wave32(std::slice::from_raw_parts(
-                    subchunk.cast::<u8>().add(std::mem::size_of::<SubchunkHeader>()).cast(),
-                    (*subchunk).subchunk_size as usize / 4
-                )),
-                _ => Wave::Unknown
-            }
+    ///
+    /// Entries of the 'LIST/INFO' chunks in the order of appearance.
+    ///
+    #[must_use]
+    pub fn info(&self) -> &[InfoEntry] {
+        &self.info
+    }
+
+    #[must_use]
+    pub fn info_value(&self, id: FourCC) -> Option<&str> {
+        self.info.iter().find(|entry| entry.id == id).map(|entry| entry.value.as_str())
+    }
+}
+
+
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn test() {
+    fn chunk(id: FourCC, body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&u32::try_from(body.len()).unwrap().to_le_bytes());
+        chunk.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
         }
+        chunk
+    }
+
+    fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&u32::try_from(body.len() + 4).unwrap().to_le_bytes());
+        file.extend_from_slice(b"WAVE");
+        file.extend_from_slice(&body);
+        file
+    }
+
+    // Extensible 24-bit stereo with the 'LIST' and 'data' chunks before 'fmt ':
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&WAVE_FORMAT_EXTENSIBLE.to_le_bytes());
+    fmt.extend_from_slice(&2_u16.to_le_bytes());
+    fmt.extend_from_slice(&48000_u32.to_le_bytes());
+    fmt.extend_from_slice(&(48000_u32 * 6).to_le_bytes());
+    fmt.extend_from_slice(&6_u16.to_le_bytes());
+    fmt.extend_from_slice(&24_u16.to_le_bytes());
+    fmt.extend_from_slice(&22_u16.to_le_bytes());
+    fmt.extend_from_slice(&24_u16.to_le_bytes());
+    fmt.extend_from_slice(&3_u32.to_le_bytes());
+    fmt.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+    fmt.extend_from_slice(&SUBFORMAT_GUID_TAIL);
+
+    let mut list = b"INFO".to_vec();
+    list.extend(chunk(*b"INAM", b"Song\0"));
+
+    let data = [0xFF, 0xFF, 0x7F, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00]; // Max, min and one truncated sample
+    let file = riff(&[chunk(*b"LIST", &list), chunk(*b"data", &data), chunk(*b"fmt ", &fmt)]);
+
+    let view = WaveView::try_from(file.as_slice()).unwrap();
+    assert_eq!(view.format().encoding, Encoding::Pcm24);
+    assert_eq!(view.format().num_channels, 2);
+    assert_eq!(view.frame_count(), 1);
+    assert_eq!(view.info_value(*b"INAM"), Some("Song"));
+    assert!((Encoding::Pcm24.decode(&view.data()[0..3]) - 1.0).abs() < f32::EPSILON);
+    assert!((Encoding::Pcm24.decode(&view.data()[3..6]) + 1.0).abs() < f32::EPSILON);
+
+    // The 'data' chunk claims more bytes than the file has:
+    let mut truncated = file.clone();
+    truncated.truncate(file.len() - 30);
+    assert!(matches!(WaveView::try_from(truncated.as_slice()), Err(Error::Truncated(..))));
+
+    assert!(matches!(WaveView::try_from(&file[..8]), Err(Error::NotWave)));
+
+    // IEEE float mono with 32-bit and 64-bit samples:
+    let basic_fmt = |tag: u16, bits: u16| -> Vec<u8> {
+        let block_align = bits / 8;
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&tag.to_le_bytes());
+        fmt.extend_from_slice(&1_u16.to_le_bytes());
+        fmt.extend_from_slice(&8000_u32.to_le_bytes());
+        fmt.extend_from_slice(&(8000 * u32::from(block_align)).to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+        fmt
+    };
+
+    let float32 = riff(&[chunk(*b"fmt ", &basic_fmt(WAVE_FORMAT_IEEE_FLOAT, 32)), chunk(*b"data", &[0.5_f32.to_le_bytes(), 2.0_f32.to_le_bytes()].concat())]);
+    let view = WaveView::try_from(float32.as_slice()).unwrap();
+    assert_eq!(view.format().encoding, Encoding::Float32);
+    assert_eq!(view.frame_count(), 2);
+    assert!((Encoding::Float32.decode(&view.data()[0..4]) - 0.5).abs() < f32::EPSILON);
+    assert!((Encoding::Float32.decode(&view.data()[4..8]) - 1.0).abs() < f32::EPSILON); // Clamped
+
+    let float64 = riff(&[chunk(*b"fmt ", &basic_fmt(WAVE_FORMAT_IEEE_FLOAT, 64)), chunk(*b"data", &(-0.25_f64).to_le_bytes())]);
+    let view = WaveView::try_from(float64.as_slice()).unwrap();
+    assert_eq!(view.format().encoding, Encoding::Float64);
+    assert_eq!(view.frame_count(), 1);
+    assert!((Encoding::Float64.decode(view.data()) + 0.25).abs() < f32::EPSILON);
+
+    let float16 = riff(&[chunk(*b"fmt ", &basic_fmt(WAVE_FORMAT_IEEE_FLOAT, 16)), chunk(*b"data", &[0, 0])]);
+    assert!(matches!(WaveView::try_from(float16.as_slice()), Err(Error::UnsupportedBitsPerSample(WAVE_FORMAT_IEEE_FLOAT, 16))));
+
+    // RF64 with the sizes of the RIFF and 'data' chunks in 'ds64':
+    let rf64 = |ds64: &[u8], data: &[u8]| -> Vec<u8> {
+        let mut file = b"RF64".to_vec();
+        file.extend_from_slice(&RF64_SIZE_PLACEHOLDER.to_le_bytes());
+        file.extend_from_slice(b"WAVE");
+        file.extend(chunk(*b"ds64", ds64));
+        file.extend(chunk(*b"fmt ", &basic_fmt(WAVE_FORMAT_PCM, 16)));
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&RF64_SIZE_PLACEHOLDER.to_le_bytes());
+        file.extend_from_slice(data);
+        file
+    };
+    let ds64 = |riff_size: u64, data_size: u64| -> Vec<u8> {
+        let mut ds64 = Vec::new();
+        ds64.extend_from_slice(&riff_size.to_le_bytes());
+        ds64.extend_from_slice(&data_size.to_le_bytes());
+        ds64.extend_from_slice(&(data_size / 2).to_le_bytes()); // Sample count
+        ds64.extend_from_slice(&0_u32.to_le_bytes()); // Table length
+        ds64
+    };
+
+    let samples = [0xFF, 0x7F, 0x01, 0x80, 0x00, 0x00];
+    let riff_size = (4 + (8 + 28) + (8 + 16) + 8 + samples.len()) as u64;
+    let file = rf64(&ds64(riff_size, samples.len() as u64), &[samples.as_slice(), b"junk"].concat());
+    let view = WaveView::try_from(file.as_slice()).unwrap();
+    assert_eq!(view.format().encoding, Encoding::Pcm16);
+    assert_eq!(view.frame_count(), 3);
+    assert!((Encoding::Pcm16.decode(&view.data()[0..2]) - 1.0).abs() < f32::EPSILON);
+
+    // The 'data' size of 'ds64' exceeds the file:
+    let file = rf64(&ds64(riff_size, 1 << 40), &samples);
+    assert!(matches!(WaveView::try_from(file.as_slice()), Err(Error::Truncated(DATA_SIGNATURE, _))));
+
+    // The 'ds64' chunk is shorter than its fields or cut by the end of the file:
+    let file = rf64(&ds64(riff_size, samples.len() as u64)[..16], &samples);
+    assert!(matches!(WaveView::try_from(file.as_slice()), Err(Error::MissingDs64)));
+    let file = rf64(&ds64(riff_size, samples.len() as u64), &samples);
+    for length in RIFF_HEADER_SIZE..RIFF_HEADER_SIZE + CHUNK_HEADER_SIZE + 28 {
+        assert!(WaveView::try_from(&file[..length]).is_err());
     }
+
+    // RF64 without 'ds64' first:
+    let mut file = b"RF64".to_vec();
+    file.extend_from_slice(&RF64_SIZE_PLACEHOLDER.to_le_bytes());
+    file.extend_from_slice(b"WAVE");
+    file.extend(chunk(*b"fmt ", &basic_fmt(WAVE_FORMAT_PCM, 16)));
+    file.extend(chunk(*b"data", &samples));
+    assert!(matches!(WaveView::try_from(file.as_slice()), Err(Error::MissingDs64)));
 }
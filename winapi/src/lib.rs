@@ -1,8 +1,14 @@
 #![warn(clippy::pedantic)]
 
 pub mod arch;
+
+#[cfg(target_os = "windows")]
 pub mod auto;
+#[cfg(target_os = "windows")]
 pub mod sync;
+#[cfg(target_os = "windows")]
 pub mod ioctl;
+#[cfg(target_os = "windows")]
 pub mod sched;
+#[cfg(target_os = "windows")]
 pub mod scm;
\ No newline at end of file